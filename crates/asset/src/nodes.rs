@@ -102,7 +102,9 @@ impl PipeNode for ArchiveCreatorNode {
         vec![
             "pack_output_path".to_string(),
             "html_output_path".to_string(),
+            "html_content?".to_string(),
             "asset_file_map?".to_string(),
+            "context?".to_string(),
         ]
     }
 
@@ -132,9 +134,16 @@ impl PipeNode for ArchiveCreatorNode {
             .compression_method(CompressionMethod::Deflated)
             .unix_permissions(0o755);
 
-        if html_output_path.exists() {
-            let html_content = std::fs::read(html_output_path)?;
-            let html_name = get_html_name(&data);
+        // Generated HTML is taken from the pipeline when the build ran in it,
+        // the file may not be written yet when nodes run concurrently
+        let html_content = match data.get_typed(tweers_core::pipeline::HTML_CONTENT) {
+            Some(html) => Some(html.clone().into_bytes()),
+            None if html_output_path.exists() => Some(std::fs::read(html_output_path)?),
+            None => None,
+        };
+
+        if let Some(html_content) = html_content {
+            let html_name = get_html_name(&data)?;
             zip.start_file(&html_name, options)
                 .map_err(|e| TweersError::other(format!("Zip error: {}", e)))?;
             zip.write_all(&html_content)?;
@@ -155,22 +164,22 @@ impl PipeNode for ArchiveCreatorNode {
 
         zip.finish()
             .map_err(|e| TweersError::other(format!("Zip error: {}", e)))?;
-        info!("Archive created: {:?}", output_path);
+        debug!("Archive created: {:?}", output_path);
         Ok(data)
     }
 }
 
-fn get_html_name(data: &PipeMap) -> String {
-    if let Some(context) = data.get_typed(CONTEXT) {
-        let (all_passages, _) = context.get_all_cached_data();
-        all_passages
-            .get("StoryTitle")
-            .map(|p| format!("{}.html", p.content.trim()))
-            .filter(|name| !name.trim().is_empty() && name != ".html")
-            .unwrap_or_else(|| "index.html".to_string())
-    } else {
-        "index.html".to_string()
-    }
+fn get_html_name(data: &PipeMap) -> Result<String> {
+    let Some(context) = data.get_typed(CONTEXT) else {
+        return Ok("index.html".to_string());
+    };
+
+    let (all_passages, _) = context.get_all_cached_data()?;
+    Ok(all_passages
+        .get("StoryTitle")
+        .map(|p| format!("{}.html", p.content.trim()))
+        .filter(|name| !name.trim().is_empty() && name != ".html")
+        .unwrap_or_else(|| "index.html".to_string()))
 }
 
 fn collect_asset_files_with_relative_paths(
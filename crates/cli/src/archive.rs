@@ -0,0 +1,70 @@
+//! Archive extraction shared by `format add` and `update`
+
+use std::io::Cursor;
+use std::path::Path;
+use zip::ZipArchive;
+
+/// Extract a ZIP archive into `target_dir`
+///
+/// Fails on an entry that would end up outside the target directory, such
+/// as `../evil.js` or an absolute path.
+pub fn extract_zip(
+    data: &[u8],
+    target_dir: &Path,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut archive = ZipArchive::new(Cursor::new(data))?;
+
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let name = file.enclosed_name().ok_or_else(|| {
+            format!(
+                "Archive entry '{}' is outside the target directory",
+                file.name()
+            )
+        })?;
+        let outpath = target_dir.join(name);
+
+        if file.is_dir() {
+            std::fs::create_dir_all(outpath)?;
+        } else {
+            if let Some(p) = outpath.parent() {
+                std::fs::create_dir_all(p)?;
+            }
+            let mut outfile = std::fs::File::create(&outpath)?;
+            std::io::copy(&mut file, &mut outfile)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::{SimpleFileOptions, ZipWriter};
+
+    fn zip_with(names: &[&str]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for name in names {
+            zip.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip.write_all(b"window.storyFormat({});").unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_entries_outside_the_target_are_rejected() {
+        let root = std::env::temp_dir().join(format!("tweers_extract_{}", std::process::id()));
+        let target = root.join("target");
+
+        extract_zip(&zip_with(&["format/format.js"]), &target).unwrap();
+        assert!(target.join("format/format.js").is_file());
+
+        let err = extract_zip(&zip_with(&["../evil.js"]), &target).unwrap_err();
+        assert!(err.to_string().contains("'../evil.js'"), "{err}");
+        assert!(!root.join("evil.js").exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,31 +1,149 @@
 use clap::{Parser, Subcommand};
+use std::net::IpAddr;
 use std::path::PathBuf;
+use tweers_core::analysis::GraphFormat;
+use tweers_core::core::conflict::{DuplicatePolicy, StoryDataPolicy};
+use tweers_core_full::scaffold::ProjectTemplate;
 
 #[derive(Subcommand)]
 #[command(version, about, long_about = None)]
 pub enum Commands {
     /// Convert .twee/.tw files to HTML
     Build {
-        /// Sources
-        #[arg(required = true)]
+        /// Sources, defaults to the sources in tweers.toml
         sources: Vec<PathBuf>,
         /// Watch
         #[clap(short, long)]
         watch: bool,
-        /// Output path
-        #[clap(short = 'o', long, default_value = "index.html")]
-        output_path: PathBuf,
+        /// Output path [default: index.html]
+        #[clap(short = 'o', long)]
+        output_path: Option<PathBuf>,
         /// Debug mode
-        #[clap(short = 't', long)]
+        #[clap(short = 't', long, overrides_with = "no_debug")]
         is_debug: bool,
+        /// Turn off debug mode enabled in tweers.toml
+        #[clap(long = "no-debug", overrides_with = "is_debug")]
+        no_debug: bool,
         /// Convert images to Base64 fragments
-        #[clap(short, long)]
+        #[clap(short, long, overrides_with = "no_base64")]
+        base64: bool,
+        /// Turn off Base64 fragments enabled in tweers.toml
+        #[clap(long = "no-base64", overrides_with = "base64")]
+        no_base64: bool,
+        /// Start passage name
+        #[clap(short = 's', long)]
+        start_passage: Option<String>,
+        /// How to handle duplicate passage names: error, warn or last-wins [default: warn]
+        #[clap(long = "duplicates")]
+        duplicate_policy: Option<DuplicatePolicy>,
+        /// How to handle conflicting StoryData/StoryTitle: error, first or last [default: first]
+        #[clap(long = "story-data")]
+        story_data_policy: Option<StoryDataPolicy>,
+        /// Print the time spent in every pipeline node
+        #[clap(long)]
+        timings: bool,
+        /// Write the timings as JSON to this file
+        #[clap(long, value_name = "FILE")]
+        timings_json: Option<PathBuf>,
+    },
+
+    /// Build, serve the story locally and reload the page on changes
+    Serve {
+        /// Sources, defaults to the sources in tweers.toml
+        sources: Vec<PathBuf>,
+        /// Output path [default: .tweers/serve/index.html]
+        #[clap(short = 'o', long)]
+        output_path: Option<PathBuf>,
+        /// Assets directories to serve next to the story
+        #[clap(short = 'a', long = "assets")]
+        assets_dirs: Vec<PathBuf>,
+        /// Address to listen on
+        #[clap(long, default_value = "127.0.0.1")]
+        host: IpAddr,
+        /// Port to listen on
+        #[clap(short = 'p', long, default_value_t = 8080)]
+        port: u16,
+        /// Debug mode
+        #[clap(short = 't', long, overrides_with = "no_debug")]
+        is_debug: bool,
+        /// Turn off debug mode enabled in tweers.toml
+        #[clap(long = "no-debug", overrides_with = "is_debug")]
+        no_debug: bool,
+        /// Convert images to Base64 fragments
+        #[clap(short, long, overrides_with = "no_base64")]
         base64: bool,
+        /// Turn off Base64 fragments enabled in tweers.toml
+        #[clap(long = "no-base64", overrides_with = "base64")]
+        no_base64: bool,
+        /// Start passage name
+        #[clap(short = 's', long)]
+        start_passage: Option<String>,
+        /// How to handle duplicate passage names: error, warn or last-wins [default: warn]
+        #[clap(long = "duplicates")]
+        duplicate_policy: Option<DuplicatePolicy>,
+        /// How to handle conflicting StoryData/StoryTitle: error, first or last [default: first]
+        #[clap(long = "story-data")]
+        story_data_policy: Option<StoryDataPolicy>,
+    },
+
+    /// Create a new project with a fresh IFID
+    Init {
+        /// Project directory
+        #[arg(default_value = ".")]
+        path: PathBuf,
+        /// Story title, defaults to the directory name
+        #[clap(long)]
+        title: Option<String>,
+        /// Seed a Start passage, stylesheet and script: sugarcube or harlowe
+        #[clap(short = 'T', long)]
+        template: Option<ProjectTemplate>,
+    },
+
+    /// Check sources for common story problems without building
+    Check {
+        /// Sources, defaults to the sources in tweers.toml
+        sources: Vec<PathBuf>,
         /// Start passage name
         #[clap(short = 's', long)]
         start_passage: Option<String>,
+        /// How to handle duplicate passage names: error, warn or last-wins [default: warn]
+        #[clap(long = "duplicates")]
+        duplicate_policy: Option<DuplicatePolicy>,
+        /// How to handle conflicting StoryData/StoryTitle: error, first or last [default: first]
+        #[clap(long = "story-data")]
+        story_data_policy: Option<StoryDataPolicy>,
     },
 
+    /// Render the passage link graph as DOT, Mermaid or JSON
+    Graph {
+        /// Sources, defaults to the sources in tweers.toml
+        sources: Vec<PathBuf>,
+        /// Output format: dot, mermaid or json
+        #[clap(short = 'f', long, default_value = "dot")]
+        format: GraphFormat,
+        /// Output path, prints to stdout when omitted
+        #[clap(short = 'o', long)]
+        output_path: Option<PathBuf>,
+    },
+
+    /// Rewrite .twee/.tw files into a canonical layout
+    Fmt {
+        /// Sources, defaults to the sources in tweers.toml
+        sources: Vec<PathBuf>,
+        /// Only report unformatted files and fail if there are any
+        #[clap(long)]
+        check: bool,
+    },
+
+    /// Manage installed story formats
+    Format {
+        #[command(subcommand)]
+        action: FormatAction,
+    },
+
+    /// Run a language server over stdio for editor integration
+    Lsp,
+
     /// Convert Twine export HTML to a single .twee file
     #[command(name = "html2twee")]
     Html2Twee {
@@ -39,21 +157,38 @@ pub enum Commands {
 
     /// Build and pack with compressed assets
     Pack {
-        /// Sources
-        #[arg(required = true)]
+        /// Sources, defaults to the sources in tweers.toml
         sources: Vec<PathBuf>,
         /// Assets directories to compress
         #[clap(short = 'a', long = "assets")]
         assets_dirs: Vec<PathBuf>,
-        /// Output archive path
-        #[clap(short = 'o', long, default_value = "package.zip")]
-        output_path: PathBuf,
+        /// Output archive path [default: package.zip]
+        #[clap(short = 'o', long)]
+        output_path: Option<PathBuf>,
         /// Enable fast compression (lower quality, faster speed)
-        #[clap(short = 'f', long)]
+        #[clap(short = 'f', long, overrides_with = "no_fast_compression")]
         fast_compression: bool,
+        /// Turn off fast compression enabled in tweers.toml
+        #[clap(long = "no-fast-compression", overrides_with = "fast_compression")]
+        no_fast_compression: bool,
         /// Debug mode
-        #[clap(short = 't', long)]
+        #[clap(short = 't', long, overrides_with = "no_debug")]
         is_debug: bool,
+        /// Turn off debug mode enabled in tweers.toml
+        #[clap(long = "no-debug", overrides_with = "is_debug")]
+        no_debug: bool,
+        /// How to handle duplicate passage names: error, warn or last-wins [default: warn]
+        #[clap(long = "duplicates")]
+        duplicate_policy: Option<DuplicatePolicy>,
+        /// How to handle conflicting StoryData/StoryTitle: error, first or last [default: first]
+        #[clap(long = "story-data")]
+        story_data_policy: Option<StoryDataPolicy>,
+        /// Print the time spent in every pipeline node
+        #[clap(long)]
+        timings: bool,
+        /// Write the timings as JSON to this file
+        #[clap(long, value_name = "FILE")]
+        timings_json: Option<PathBuf>,
     },
 
     /// Update TweeRS to the latest release
@@ -64,10 +199,44 @@ pub enum Commands {
     },
 }
 
+#[derive(Subcommand)]
+pub enum FormatAction {
+    /// List the story formats in every format directory
+    List,
+
+    /// Show the details of a story format
+    Inspect {
+        /// Format name, or the path of a format directory
+        format: String,
+        /// Version to resolve, defaults to the highest installed
+        version: Option<String>,
+    },
+
+    /// Install a story format from a directory or zip file
+    Add {
+        /// Format directory or zip file containing format.js
+        path: PathBuf,
+        /// Replace an installed format with the same version
+        #[clap(short, long)]
+        force: bool,
+    },
+
+    /// Delete an installed story format
+    Remove {
+        /// Format name
+        name: String,
+        /// Exact version to delete
+        version: String,
+    },
+}
+
 /// TweeRS Command
 #[derive(Parser)]
 #[command(about = None)]
 pub struct Cli {
     #[command(subcommand)]
     pub cmd: Commands,
+    /// Extra story format directory, searched before the default ones
+    #[clap(long = "format-dir", global = true)]
+    pub format_dirs: Vec<PathBuf>,
 }
@@ -0,0 +1,198 @@
+use crate::archive::extract_zip;
+use crate::cli::FormatAction;
+use std::path::{Path, PathBuf};
+use tweers_core::core::story::StoryFormat;
+use tweers_core_full::format::{
+    FormatSearchPath, install_format, installed_formats, list_format_dir, load_format_dir,
+    parse_version, remove_format, resolve_format, user_format_dir,
+};
+
+/// Format command: list, inspect, install and remove story formats
+///
+/// Formats are added to the first of `format_dirs`, or the user format
+/// directory when there is none.
+pub async fn format_command(
+    action: FormatAction,
+    search_path: FormatSearchPath,
+    format_dirs: Vec<PathBuf>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match action {
+        FormatAction::List => list(&search_path).await,
+        FormatAction::Inspect { format, version } => {
+            inspect(&search_path, &format, version.as_deref()).await
+        }
+        FormatAction::Add { path, force } => {
+            let target = match format_dirs.first() {
+                Some(dir) => dir.clone(),
+                None => user_format_dir().ok_or("Could not determine the user config directory")?,
+            };
+            add(&path, &target, force).await
+        }
+        FormatAction::Remove { name, version } => {
+            let removed = remove_format(&search_path, &name, &version).await?;
+            println!(
+                "Removed {} {} from {}",
+                removed.name,
+                removed.version,
+                removed.path.parent().unwrap_or(&removed.path).display()
+            );
+            Ok(())
+        }
+    }
+}
+
+async fn list(
+    search_path: &FormatSearchPath,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut broken = 0;
+
+    for dir in search_path.dirs() {
+        println!("{}", dir.display());
+        if !dir.is_dir() {
+            println!("  (not found)");
+            continue;
+        }
+
+        let entries = list_format_dir(dir).await?;
+        if entries.is_empty() {
+            println!("  (empty)");
+        }
+        for entry in entries {
+            let dir_name = entry
+                .dir
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            match entry.format {
+                Ok(format) => println!("  {}", summary(&format)),
+                Err(e) => {
+                    broken += 1;
+                    println!("  {}: error: {}", dir_name, e);
+                }
+            }
+        }
+    }
+
+    if broken > 0 {
+        return Err(format!("{} story format(s) could not be loaded", broken).into());
+    }
+    Ok(())
+}
+
+async fn inspect(
+    search_path: &FormatSearchPath,
+    format: &str,
+    version: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let dir = if Path::new(format).is_dir() {
+        PathBuf::from(format)
+    } else {
+        let installed = installed_formats(search_path).await?;
+        let found = match version {
+            Some(version) => resolve_format(&installed, format, version).map(|(found, _)| found),
+            None => installed
+                .iter()
+                .filter(|found| found.name.eq_ignore_ascii_case(format))
+                .max_by_key(|found| parse_version(&found.version)),
+        }
+        .ok_or_else(|| match version {
+            Some(version) => format!("Story format '{format}' version '{version}' not found"),
+            None => format!("Story format '{format}' not found"),
+        })?;
+        found
+            .path
+            .parent()
+            .ok_or("Story format has no directory")?
+            .to_path_buf()
+    };
+
+    let (story_format, _) = load_format_dir(&dir)
+        .await
+        .map_err(|e| format!("{}: {}", dir.display(), e))?;
+
+    let optional = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+    println!("Name:        {}", optional(&story_format.name));
+    println!("Version:     {}", story_format.version);
+    println!(
+        "Proofing:    {}",
+        if story_format.proofing { "yes" } else { "no" }
+    );
+    println!("Author:      {}", optional(&story_format.author));
+    println!("License:     {}", optional(&story_format.license));
+    println!("URL:         {}", optional(&story_format.url));
+    println!("Description: {}", optional(&story_format.description));
+    println!("Location:    {}", dir.display());
+
+    Ok(())
+}
+
+async fn add(
+    path: &Path,
+    target: &Path,
+    force: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let is_zip = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("zip"));
+
+    let installed = if is_zip {
+        let temp_dir = std::env::temp_dir().join(format!("tweers_format_{}", std::process::id()));
+        std::fs::create_dir_all(&temp_dir)?;
+        let result = async {
+            extract_zip(&std::fs::read(path)?, &temp_dir)?;
+            let root = find_format_root(&temp_dir)
+                .ok_or_else(|| format!("No format.js found in {}", path.display()))?;
+            std::fs::create_dir_all(target)?;
+            install_format(&root, target, force).await
+        }
+        .await;
+        std::fs::remove_dir_all(&temp_dir)?;
+        result?
+    } else if path.is_dir() {
+        std::fs::create_dir_all(target)?;
+        install_format(path, target, force).await?
+    } else {
+        return Err(format!("Expected a directory or zip file: {}", path.display()).into());
+    };
+
+    println!(
+        "Installed {} {} to {}",
+        installed.name,
+        installed.version,
+        installed.path.parent().unwrap_or(&installed.path).display()
+    );
+    Ok(())
+}
+
+fn summary(format: &StoryFormat) -> String {
+    let mut line = format!(
+        "{} {}",
+        format.name.as_deref().unwrap_or_default(),
+        format.version
+    );
+    if format.proofing {
+        line.push_str(" (proofing)");
+    }
+    if let Some(author) = &format.author {
+        line.push_str(&format!(", author: {author}"));
+    }
+    if let Some(license) = &format.license {
+        line.push_str(&format!(", license: {license}"));
+    }
+    line
+}
+
+/// The directory holding format.js, the archive root or a directory below it
+fn find_format_root(dir: &Path) -> Option<PathBuf> {
+    if dir.join("format.js").is_file() {
+        return Some(dir.to_path_buf());
+    }
+
+    let mut subdirs: Vec<PathBuf> = std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir())
+        .collect();
+    subdirs.sort();
+    subdirs.iter().find_map(|subdir| find_format_root(subdir))
+}
@@ -43,7 +43,8 @@ pub fn create_log_file() -> Result<std::fs::File, Box<dyn std::error::Error>> {
         .truncate(true)
         .open(&log_path)?;
 
-    println!("Log file created: {}", log_path.display());
+    // stderr keeps stdout clean for the language server protocol
+    eprintln!("Log file created: {}", log_path.display());
 
     Ok(log_file)
 }
@@ -1,30 +1,55 @@
+mod archive;
 mod cli;
+mod format;
 mod logging;
+mod pipeline;
 mod update;
 
 use clap::Parser;
 use std::io::{self, Write};
+use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use tracing::error;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::{EnvFilter, Layer, fmt, layer::SubscriberExt, util::SubscriberInitExt};
 
 use crate::cli::{Cli, Commands};
+use crate::format::format_command;
+use crate::pipeline::{
+    builtin_registry, configured_build_pipeline, configured_pack_pipeline, observe, observers,
+    script_reload,
+};
 use crate::update::update_command;
-use tweers_asset::{ArchiveCreatorNode, AssetCompressorNode};
 use tweers_core::config::constants;
-use tweers_core_full::commands::{build_command_with_nodes, pack_command_with_nodes};
-use tweers_js::manager::ScriptManager;
-use tweers_js::nodes::{DataProcessorNode, HtmlProcessorNode};
+use tweers_core_full::commands::{
+    build_command_with_nodes, check_command, fmt_command, graph_command, init_command,
+    pack_command_with_nodes, serve_command,
+};
+use tweers_core_full::config::ProjectConfig;
+use tweers_core_full::io::absolute_path;
+use tweers_core_full::serve::{DevServer, LiveReload};
+use tweers_js::manager::{ScriptConfig, ScriptManager};
 
 #[tokio::main]
 async fn main() {
     constants::init_constants();
 
+    let args = Cli::parse();
+
+    // The language server talks over stdout, so console logs go to stderr
+    let console_writer = if matches!(args.cmd, Commands::Lsp) {
+        BoxMakeWriter::new(io::stderr)
+    } else {
+        BoxMakeWriter::new(io::stdout)
+    };
+
     let log_file = logging::create_log_file().expect("Failed to create log file");
 
     tracing_subscriber::registry()
         .with(
             fmt::layer()
+                .with_writer(console_writer)
                 .with_target(false)
                 .with_thread_ids(false)
                 .with_level(true)
@@ -42,42 +67,47 @@ async fn main() {
         )
         .init();
 
-    if let Err(e) = run().await {
+    if let Err(e) = run(args).await {
         error!("{}", e);
         std::process::exit(1);
     }
 }
 
-async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let args = Cli::parse();
+async fn run(args: Cli) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     match args.cmd {
         Commands::Build {
             watch,
             output_path,
             sources,
             is_debug,
+            no_debug,
             base64,
+            no_base64,
             start_passage,
+            duplicate_policy,
+            story_data_policy,
+            timings,
+            timings_json,
         } => {
-            let script_manager = ScriptManager::default();
-            let mut data_nodes: Vec<Box<dyn tweers_core_full::pipeline::PipeNode + Send + Sync>> =
-                vec![];
-            let mut html_nodes: Vec<Box<dyn tweers_core_full::pipeline::PipeNode + Send + Sync>> =
-                vec![];
-
-            if script_manager.has_data_scripts() {
-                data_nodes.push(Box::new(
-                    DataProcessorNode::new(script_manager.clone())
-                        .expect("Failed to create DataProcessorNode"),
-                ));
-            }
+            let config = project_config()?;
+            let sources = resolve_sources(sources, &config)?;
+            let output_path = output_path
+                .or_else(|| config.build_output())
+                .unwrap_or_else(|| PathBuf::from("index.html"));
+            let is_debug = flag(is_debug, no_debug, config.build.debug);
+            let base64 = flag(base64, no_base64, config.build.base64);
+            let start_passage = start_passage.or_else(|| config.build.start_passage.clone());
+            let format_search_path = config.format_search_path(args.format_dirs);
 
-            if script_manager.has_html_scripts() {
-                html_nodes.push(Box::new(
-                    HtmlProcessorNode::new(script_manager.clone())
-                        .expect("Failed to create HtmlProcessorNode"),
-                ));
-            }
+            let conflict_policy = config.conflict_policy(duplicate_policy, story_data_policy);
+            let script_manager = script_manager(&config.script_dirs())?;
+            let registry = Arc::new(builtin_registry(script_manager.clone()));
+            let observers = observers(timings, timings_json);
+            let pipeline = observe(
+                configured_build_pipeline(&config, registry, &script_manager)?,
+                &observers,
+            );
+            let reload = watch.then(|| script_reload(config.clone(), script_manager, observers));
 
             build_command_with_nodes(
                 sources,
@@ -86,11 +116,200 @@ async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
                 is_debug,
                 base64,
                 start_passage,
-                data_nodes,
-                html_nodes,
+                conflict_policy,
+                format_search_path,
+                config.cache_dir(),
+                pipeline,
+                reload,
             )
             .await?;
         }
+        Commands::Serve {
+            sources,
+            output_path,
+            assets_dirs,
+            host,
+            port,
+            is_debug,
+            no_debug,
+            base64,
+            no_base64,
+            start_passage,
+            duplicate_policy,
+            story_data_policy,
+        } => {
+            let config = project_config()?;
+            let sources = resolve_sources(sources, &config)?;
+            let project_root = if config.root.as_os_str().is_empty() {
+                PathBuf::from(".")
+            } else {
+                config.root.clone()
+            };
+            // Files are served from where `build` writes the story, so that
+            // relative asset paths resolve the same way. The project root
+            // holds the sources and settings and is never served.
+            let site_dir = output_path
+                .clone()
+                .or_else(|| config.build_output())
+                .and_then(|path| path.parent().map(Path::to_path_buf))
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .filter(|dir| absolute_path(dir) != absolute_path(&project_root));
+            let output_path = output_path
+                .unwrap_or_else(|| project_root.join(constants::SERVE_DIR).join("index.html"));
+            let assets_dirs = if assets_dirs.is_empty() {
+                config.assets_dirs()
+            } else {
+                assets_dirs
+            };
+            let is_debug = flag(is_debug, no_debug, config.build.debug);
+            let base64 = flag(base64, no_base64, config.build.base64);
+            let start_passage = start_passage.or_else(|| config.build.start_passage.clone());
+            let format_search_path = config.format_search_path(args.format_dirs);
+
+            let conflict_policy = config.conflict_policy(duplicate_policy, story_data_policy);
+            let script_manager = script_manager(&config.script_dirs())?;
+            let registry = Arc::new(builtin_registry(script_manager.clone()));
+            let observers = observers(false, None);
+            let pipeline = observe(
+                configured_build_pipeline(&config, registry, &script_manager)?,
+                &observers,
+            );
+            let reload = script_reload(config.clone(), script_manager, observers);
+
+            let live_reload = LiveReload::new();
+            let addr = SocketAddr::new(host, port);
+            let mut server = DevServer::bind(addr, output_path.clone(), live_reload.clone())
+                .await
+                .map_err(|e| format!("Failed to listen on {}: {}", addr, e))?;
+            if let Some(site_dir) = site_dir {
+                server = server.with_dir("", site_dir);
+            }
+            // Assets keep their directory name, as in packed archives
+            for dir in &assets_dirs {
+                let name = dir
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("assets");
+                server = server.with_dir(name, dir.clone());
+            }
+            println!("Serving at http://{}", server.local_addr()?);
+
+            serve_command(
+                sources,
+                output_path,
+                is_debug,
+                base64,
+                start_passage,
+                conflict_policy,
+                format_search_path,
+                config.cache_dir(),
+                server,
+                live_reload,
+                pipeline,
+                Some(reload),
+            )
+            .await?;
+        }
+        Commands::Init {
+            path,
+            title,
+            template,
+        } => {
+            let created = init_command(path, title, template, args.format_dirs).await?;
+
+            for file in &created {
+                println!("Created: {}", file.display());
+            }
+        }
+        Commands::Check {
+            sources,
+            start_passage,
+            duplicate_policy,
+            story_data_policy,
+        } => {
+            let config = project_config()?;
+            let sources = resolve_sources(sources, &config)?;
+            let start_passage = start_passage.or_else(|| config.build.start_passage.clone());
+            let conflict_policy = config.conflict_policy(duplicate_policy, story_data_policy);
+            let diagnostics = check_command(sources, start_passage, conflict_policy).await?;
+
+            for diagnostic in &diagnostics {
+                println!("{}", diagnostic);
+            }
+
+            let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+            let warnings = diagnostics.len() - errors;
+
+            if errors > 0 {
+                return Err(
+                    format!("Check failed: {} error(s), {} warning(s)", errors, warnings).into(),
+                );
+            }
+
+            println!("Check passed: {} warning(s)", warnings);
+        }
+        Commands::Graph {
+            sources,
+            format,
+            output_path,
+        } => {
+            let sources = resolve_sources(sources, &project_config()?)?;
+            let graph = graph_command(sources, format).await?;
+
+            match output_path {
+                Some(output_path) => {
+                    std::fs::write(&output_path, graph)?;
+                    println!("Graph written to: {}", output_path.display());
+                }
+                None => print!("{}", graph),
+            }
+        }
+        Commands::Fmt { sources, check } => {
+            let sources = resolve_sources(sources, &project_config()?)?;
+            let report = fmt_command(sources, check).await?;
+
+            for errors in &report.errors {
+                eprintln!("{}", errors);
+            }
+            for path in &report.changed {
+                if check {
+                    println!("Would reformat: {}", path.display());
+                } else {
+                    println!("Formatted: {}", path.display());
+                }
+            }
+
+            if !report.errors.is_empty() {
+                return Err(format!(
+                    "Failed to format {} file(s) with parse errors",
+                    report.errors.len()
+                )
+                .into());
+            }
+            if check && !report.changed.is_empty() {
+                return Err(format!(
+                    "{} of {} file(s) need formatting",
+                    report.changed.len(),
+                    report.checked
+                )
+                .into());
+            }
+
+            println!(
+                "{} file(s) checked, {} reformatted",
+                report.checked,
+                if check { 0 } else { report.changed.len() }
+            );
+        }
+        Commands::Format { action } => {
+            let config = project_config()?;
+            let search_path = config.format_search_path(args.format_dirs.clone());
+            let format_dirs = [args.format_dirs, config.format_dirs()].concat();
+            format_command(action, search_path, format_dirs).await?;
+        }
+        Commands::Lsp => {
+            tokio::task::spawn_blocking(tweers_lsp::run).await??;
+        }
         Commands::Html2Twee {
             input_path,
             output_path,
@@ -126,20 +345,57 @@ async fn run() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
             assets_dirs,
             output_path,
             fast_compression,
+            no_fast_compression,
             is_debug,
+            no_debug,
+            duplicate_policy,
+            story_data_policy,
+            timings,
+            timings_json,
         } => {
-            let pack_nodes: Vec<Box<dyn tweers_core_full::pipeline::PipeNode + Send + Sync>> =
-                vec![Box::new(AssetCompressorNode), Box::new(ArchiveCreatorNode)];
+            let config = project_config()?;
+            let sources = resolve_sources(sources, &config)?;
+            let assets_dirs = if assets_dirs.is_empty() {
+                config.assets_dirs()
+            } else {
+                assets_dirs
+            };
+            let output_path = output_path
+                .or_else(|| config.pack_output())
+                .unwrap_or_else(|| PathBuf::from("package.zip"));
+            let fast_compression = flag(
+                fast_compression,
+                no_fast_compression,
+                config.pack.fast_compression,
+            );
+            let is_debug = flag(is_debug, no_debug, config.pack.debug);
+            let format_search_path = config.format_search_path(args.format_dirs);
+
+            let conflict_policy = config.conflict_policy(duplicate_policy, story_data_policy);
+            let script_manager = script_manager(&config.script_dirs())?;
+            let registry = Arc::new(builtin_registry(script_manager.clone()));
+            let observers = observers(timings, timings_json);
+            let build_pipeline = observe(
+                configured_build_pipeline(&config, registry.clone(), &script_manager)?,
+                &observers,
+            );
+            let pack_pipeline = observe(configured_pack_pipeline(&config, registry)?, &observers);
 
-            pack_command_with_nodes(
+            let package = pack_command_with_nodes(
                 sources,
                 assets_dirs,
                 output_path,
                 fast_compression,
                 is_debug,
-                pack_nodes,
+                conflict_policy,
+                format_search_path,
+                config.cache_dir(),
+                build_pipeline,
+                pack_pipeline,
             )
             .await?;
+
+            println!("Package written to: {}", package.display());
         }
         Commands::Update { force } => {
             update_command(
@@ -213,3 +469,52 @@ fn confirm_overwrite(path: &Path) -> Result<bool, Box<dyn std::error::Error + Se
         }
     }
 }
+
+/// Value of a switch that is on with `set`, off with `unset`, and taken from
+/// tweers.toml otherwise
+fn flag(set: bool, unset: bool, configured: bool) -> bool {
+    (configured || set) && !unset
+}
+
+/// Project config found from the working directory, empty when there is none
+fn project_config() -> Result<ProjectConfig, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(ProjectConfig::discover(&std::env::current_dir()?)?.unwrap_or_default())
+}
+
+/// Sources from the command line, falling back to the project config
+fn resolve_sources(
+    sources: Vec<PathBuf>,
+    config: &ProjectConfig,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    if !sources.is_empty() {
+        return Ok(sources);
+    }
+
+    let sources = config.sources();
+    if sources.is_empty() {
+        return Err(format!(
+            "No sources given and none configured in {}",
+            constants::CONFIG_FILE
+        )
+        .into());
+    }
+    Ok(sources)
+}
+
+/// Scripts of the configured script directories, or the bundled ones
+fn script_manager(
+    script_dirs: &[PathBuf],
+) -> Result<ScriptManager, Box<dyn std::error::Error + Send + Sync>> {
+    let Some((first, rest)) = script_dirs.split_first() else {
+        return Ok(ScriptManager::default());
+    };
+
+    let mut manager = ScriptManager::new(ScriptConfig {
+        scripts_dir: first.clone(),
+        auto_execute: true,
+    })?;
+    for dir in rest {
+        manager.add_scripts_dir(dir.clone())?;
+    }
+    Ok(manager)
+}
@@ -0,0 +1,161 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::error;
+use tweers_core::config::constants;
+use tweers_core::pipeline::{
+    ExecutionMode, NodeRegistry, PipelineBuilder, PipelineObserver, TimingsObserver,
+    TracingObserver,
+};
+use tweers_core_full::commands::{build_pipeline, pack_pipeline};
+use tweers_core_full::config::ProjectConfig;
+use tweers_core_full::pipeline::DEFAULT_BUILD_NODES;
+use tweers_core_full::watch::PipelineReload;
+use tweers_js::manager::ScriptManager;
+
+/// Node names of the pack pipeline, in execution order
+const DEFAULT_PACK_NODES: &[&str] = &["asset_compressor", "archive_creator"];
+
+/// Registry holding every node shipped with tweers
+pub fn builtin_registry(script_manager: ScriptManager) -> NodeRegistry {
+    let mut registry = NodeRegistry::new();
+    tweers_core_full::pipeline::register_nodes(&mut registry);
+    tweers_js::register_nodes(&mut registry, script_manager);
+    tweers_asset::register_nodes(&mut registry);
+    registry
+}
+
+/// Build pipeline from `[build] pipeline`, or the default one
+///
+/// The default runs the data and HTML script nodes only when there are
+/// scripts for them.
+pub fn configured_build_pipeline(
+    config: &ProjectConfig,
+    registry: Arc<NodeRegistry>,
+    script_manager: &ScriptManager,
+) -> Result<PipelineBuilder, Box<dyn std::error::Error + Send + Sync>> {
+    build_nodes_pipeline(config, registry, build_nodes(config, script_manager))
+}
+
+/// Pack pipeline from `[pack] pipeline`, or the default one
+pub fn configured_pack_pipeline(
+    config: &ProjectConfig,
+    registry: Arc<NodeRegistry>,
+) -> Result<PipelineBuilder, Box<dyn std::error::Error + Send + Sync>> {
+    let nodes = match &config.pack.pipeline {
+        Some(nodes) => nodes.clone(),
+        None => DEFAULT_PACK_NODES
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+    };
+
+    pack_pipeline(registry.clone(), nodes)
+        .map(|pipeline| pipeline.with_execution_mode(execution_mode(config.pack.concurrent)))
+        .map_err(|e| invalid_pipeline("pack", &registry, e).into())
+}
+
+/// Reload of the script directories for watched builds
+///
+/// Changed scripts are rediscovered with `ScriptManager::refresh` and the
+/// build pipeline is created again, so that added or removed scripts also
+/// change which script nodes run.
+pub fn script_reload(
+    config: ProjectConfig,
+    mut script_manager: ScriptManager,
+    observers: Vec<Arc<dyn PipelineObserver>>,
+) -> PipelineReload {
+    PipelineReload::new(script_manager.scripts_dirs(), move || {
+        script_manager.refresh()?;
+        let registry = Arc::new(builtin_registry(script_manager.clone()));
+        let pipeline = configured_build_pipeline(&config, registry, &script_manager)?;
+        Ok(observe(pipeline, &observers))
+    })
+}
+
+/// Observers of a command: node spans, plus timing reports when asked for
+pub fn observers(timings: bool, timings_json: Option<PathBuf>) -> Vec<Arc<dyn PipelineObserver>> {
+    let mut observers: Vec<Arc<dyn PipelineObserver>> = vec![Arc::new(TracingObserver::new())];
+
+    if timings || timings_json.is_some() {
+        observers.push(Arc::new(TimingsObserver::with_reporter(move |report| {
+            if timings {
+                print!("{}", report);
+            }
+            if let Some(path) = &timings_json {
+                let written = serde_json::to_string_pretty(report)
+                    .map_err(|e| e.to_string())
+                    .and_then(|json| std::fs::write(path, json).map_err(|e| e.to_string()));
+                if let Err(e) = written {
+                    error!("Failed to write timings to {}: {}", path.display(), e);
+                }
+            }
+        })));
+    }
+
+    observers
+}
+
+/// Add the observers to a pipeline
+pub fn observe(
+    pipeline: PipelineBuilder,
+    observers: &[Arc<dyn PipelineObserver>],
+) -> PipelineBuilder {
+    observers.iter().fold(pipeline, |pipeline, observer| {
+        pipeline.with_observer(observer.clone())
+    })
+}
+
+fn build_nodes(config: &ProjectConfig, script_manager: &ScriptManager) -> Vec<String> {
+    match &config.build.pipeline {
+        Some(nodes) => nodes.clone(),
+        None => default_build_nodes(script_manager),
+    }
+}
+
+fn build_nodes_pipeline(
+    config: &ProjectConfig,
+    registry: Arc<NodeRegistry>,
+    nodes: Vec<String>,
+) -> Result<PipelineBuilder, Box<dyn std::error::Error + Send + Sync>> {
+    build_pipeline(registry.clone(), nodes)
+        .map(|pipeline| pipeline.with_execution_mode(execution_mode(config.build.concurrent)))
+        .map_err(|e| invalid_pipeline("build", &registry, e).into())
+}
+
+fn default_build_nodes(script_manager: &ScriptManager) -> Vec<String> {
+    let mut nodes = Vec::new();
+    for &name in DEFAULT_BUILD_NODES {
+        nodes.push(name.to_string());
+        if name == "data_aggregator" && script_manager.has_data_scripts() {
+            nodes.push("data_processor".to_string());
+        }
+        if name == "html_generator" && script_manager.has_html_scripts() {
+            nodes.push("html_processor".to_string());
+        }
+    }
+    nodes
+}
+
+fn execution_mode(concurrent: bool) -> ExecutionMode {
+    if concurrent {
+        ExecutionMode::Concurrent
+    } else {
+        ExecutionMode::Sequential
+    }
+}
+
+fn invalid_pipeline(
+    section: &str,
+    registry: &NodeRegistry,
+    error: tweers_core::error::TweersError,
+) -> String {
+    let mut available = registry.list_nodes();
+    available.sort();
+    format!(
+        "[{}] pipeline in {}: {} (available: {})",
+        section,
+        constants::CONFIG_FILE,
+        error,
+        available.join(", ")
+    )
+}
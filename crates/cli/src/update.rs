@@ -1,3 +1,4 @@
+use crate::archive::extract_zip;
 use serde::Deserialize;
 use std::path::{Path, PathBuf};
 use tracing::info;
@@ -148,37 +149,6 @@ pub async fn update_command(
     Ok(())
 }
 
-/// Extract ZIP archive to target directory
-fn extract_zip(
-    data: &[u8],
-    target_dir: &Path,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    use std::io::Cursor;
-    use zip::ZipArchive;
-
-    let cursor = Cursor::new(data);
-    let mut archive = ZipArchive::new(cursor)?;
-
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let outpath = target_dir.join(file.name());
-
-        if file.name().ends_with('/') {
-            std::fs::create_dir_all(outpath)?;
-        } else {
-            if let Some(p) = outpath.parent()
-                && !p.exists()
-            {
-                std::fs::create_dir_all(p)?;
-            }
-            let mut outfile = std::fs::File::create(&outpath)?;
-            std::io::copy(&mut file, &mut outfile)?;
-        }
-    }
-
-    Ok(())
-}
-
 /// Extract TAR.GZ archive to target directory
 #[cfg(unix)]
 fn extract_tar_gz(
@@ -28,6 +28,8 @@ pub struct BuildConfig {
     format_name: Option<String>,
     format_version: Option<String>,
     format_source: Option<String>,
+    format_dirs: Vec<PathBuf>,
+    project_dir: PathBuf,
     is_debug: bool,
     base64: bool,
     start_passage: Option<String>,
@@ -41,6 +43,8 @@ impl Default for BuildConfig {
             format_name: None,
             format_version: None,
             format_source: None,
+            format_dirs: Vec::new(),
+            project_dir: PathBuf::from("."),
             is_debug: false,
             base64: false,
             start_passage: None,
@@ -120,6 +124,19 @@ impl BuildConfig {
         self
     }
 
+    /// Look up the story format in this directory before the default search path
+    pub fn format_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.format_dirs.push(dir.into());
+        self
+    }
+
+    /// Project whose `.storyformats` directory is searched, the current
+    /// directory by default
+    pub fn project_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.project_dir = dir.into();
+        self
+    }
+
     pub fn debug(mut self, is_debug: bool) -> Self {
         self.is_debug = is_debug;
         self
@@ -156,7 +173,9 @@ pub async fn run_build(
     let format_source = if let Some(source) = config.format_source {
         source
     } else {
-        crate::format::find_and_load_format(&format_name, &format_version).await?
+        let search_path = crate::format::FormatSearchPath::discover(&config.project_dir)
+            .with_dirs(config.format_dirs);
+        crate::format::find_and_load_format(&search_path, &format_name, &format_version).await?
     };
 
     // 2. Load all sources (files, URLs, etc.)
@@ -1,17 +1,26 @@
-use crate::io::is_support_file_with_base64;
+use crate::context::{CachedFile, ContentHash, DiskCache};
+use crate::format::FormatSearchPath;
 use crate::pipeline::nodes::basic::{
-    DataAggregatorNode, FileChangeDetectorNode, FileCollectorNode, FileParserNode, FileWriterNode,
-    HtmlGeneratorNode,
+    AggregateCache, DataAggregatorNode, FileChangeDetectorNode, FileCollectorNode, FileParserNode,
 };
-use crate::pipeline::{PipeMap, PipeNode, Pipeline};
+use crate::pipeline::nodes::lint::LintNode;
+use crate::pipeline::{default_registry, PipeMap, Pipeline, DEFAULT_BUILD_NODES};
+use crate::scaffold::{self, ProjectTemplate};
+use crate::serve::{DevServer, LiveReload};
+use crate::watch::{is_format_change, watch_changes, PipelineReload};
 use indexmap::IndexMap;
-use notify::{EventKind, RecursiveMode};
+use notify::RecursiveMode;
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 use tracing::{debug, error, info, warn};
+use tweers_core::analysis::{Diagnostic, GraphFormat};
+use tweers_core::core::conflict::{ConflictPolicy, PassageMerger};
+use tweers_core::core::output::HtmlCache;
 use tweers_core::core::story::{Passage, StoryData, StoryFormat};
-use tweers_core::pipeline::TypedKey;
+use tweers_core::error::{ParseDiagnostics, TweersError};
+use tweers_core::pipeline::{NodeRegistry, PipelineBuilder, TypedKey};
 use tweers_core::util::file::get_media_passage_type;
 
 /// Cached file info
@@ -39,6 +48,22 @@ pub struct BuildContext {
     pub assets_dirs: Vec<PathBuf>,
     /// Start passage name
     pub start_passage: Option<String>,
+    /// How to handle duplicate passages and conflicting StoryData across files
+    pub conflict_policy: ConflictPolicy,
+    /// Directories the story format is looked up in, the commands set the
+    /// one of the project, the current directory is assumed otherwise
+    pub format_search_path: FormatSearchPath,
+    /// Parse results kept between runs, keyed by file content
+    pub disk_cache: Option<DiskCache>,
+    /// `format.js` the story format was loaded from
+    pub format_path: Option<PathBuf>,
+    /// Rendered passages and format template reused by the next build
+    ///
+    /// Shared between clones of the context, so that passing the context
+    /// through the pipeline does not copy it.
+    pub html_cache: Arc<Mutex<HtmlCache>>,
+    /// Merged passages reused by the next build, shared like `html_cache`
+    pub aggregate_cache: Arc<Mutex<AggregateCache>>,
 }
 
 /// Type-safe key for BuildContext in pipeline (re-exported for asset/js crates)
@@ -61,6 +86,12 @@ impl BuildContext {
             base64,
             assets_dirs: Vec::new(),
             start_passage,
+            conflict_policy: ConflictPolicy::default(),
+            format_search_path: FormatSearchPath::discover(Path::new(".")),
+            disk_cache: None,
+            format_path: None,
+            html_cache: Arc::default(),
+            aggregate_cache: Arc::default(),
         }
     }
 
@@ -74,9 +105,33 @@ impl BuildContext {
             base64,
             assets_dirs,
             start_passage: None,
+            conflict_policy: ConflictPolicy::default(),
+            format_search_path: FormatSearchPath::discover(Path::new(".")),
+            disk_cache: None,
+            format_path: None,
+            html_cache: Arc::default(),
+            aggregate_cache: Arc::default(),
         }
     }
 
+    /// Load a story format, replacing the current one
+    pub async fn load_format(
+        &mut self,
+        name: &str,
+        version: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let (path, source) =
+            crate::format::find_format(&self.format_search_path, name, version).await?;
+        let story_format = StoryFormat::parse(&source)?;
+
+        self.story_format = Some(story_format);
+        self.format_name = name.to_string();
+        self.format_version = version.to_string();
+        // Watch events carry absolute paths
+        self.format_path = Some(path.canonicalize().unwrap_or(path));
+        Ok(())
+    }
+
     /// Check if file has been modified since last cache
     pub fn is_file_modified(&self, path: &PathBuf) -> Result<bool, std::io::Error> {
         let metadata = std::fs::metadata(path)?;
@@ -108,6 +163,18 @@ impl BuildContext {
         }
     }
 
+    /// Drop the cached files at or under `path`, returning how many there were
+    ///
+    /// Files are cached under the paths they were collected as, which are
+    /// relative for relative sources, so both sides are compared absolute.
+    pub fn evict(&mut self, path: &Path) -> usize {
+        let path = crate::io::absolute_path(path);
+        let before = self.file_cache.len();
+        self.file_cache
+            .retain(|cached, _| !crate::io::absolute_path(cached).starts_with(&path));
+        before - self.file_cache.len()
+    }
+
     /// Update file cache with new content
     pub fn update_cache(
         &mut self,
@@ -129,25 +196,87 @@ impl BuildContext {
         Ok(())
     }
 
+    /// Disk cache to use for `path`
+    ///
+    /// Base64 media passages are cheaper to encode again than to store a
+    /// second copy of, so they are never cached.
+    fn disk_cache_for(&self, path: &Path) -> Option<&DiskCache> {
+        let is_media = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .and_then(get_media_passage_type)
+            .is_some();
+        if self.base64 && is_media {
+            return None;
+        }
+        self.disk_cache.as_ref()
+    }
+
+    /// Parse result of the current content of `path` from the disk cache
+    pub fn load_cached(&self, path: &Path) -> Option<CachedFile> {
+        self.disk_cache_for(path)?.load(path, self.base64)
+    }
+
+    /// Keep the parse result of `path` in the disk cache, if there is one
+    ///
+    /// `hash` is the hash of the content that was parsed, the file may have
+    /// changed again since.
+    pub fn store_cached(
+        &self,
+        path: &Path,
+        hash: &ContentHash,
+        passages: &IndexMap<String, Passage>,
+        story_data: &Option<StoryData>,
+    ) {
+        if let Some(disk_cache) = self.disk_cache_for(path) {
+            disk_cache.store(path, self.base64, hash, passages, story_data);
+        }
+    }
+
     /// Get cached passages and story data from all files
-    pub fn get_all_cached_data(&self) -> (IndexMap<String, Passage>, Option<StoryData>) {
-        let mut all_passages = IndexMap::new();
-        let mut story_data = None;
+    ///
+    /// Conflicts were already reported by the build, so duplicates resolve
+    /// last-wins and StoryData conflicts follow the configured policy quietly.
+    pub fn get_all_cached_data(
+        &self,
+    ) -> tweers_core::error::Result<(IndexMap<String, Passage>, Option<StoryData>)> {
+        let mut merger = PassageMerger::new(self.conflict_policy.lenient());
 
         for file_info in self.file_cache.values() {
-            for (name, passage) in &file_info.passages {
-                all_passages.insert(name.clone(), passage.clone());
-            }
-
-            if story_data.is_none() && file_info.story_data.is_some() {
-                story_data = file_info.story_data.clone();
-            }
+            merger.add_source(&file_info.passages, file_info.story_data.as_ref());
         }
 
-        (all_passages, story_data)
+        let merged = merger
+            .finish()
+            .map_err(|e| TweersError::conflict(e.to_string()))?;
+        Ok((merged.passages, merged.story_data))
     }
 }
 
+/// Build pipeline running `nodes` of `registry` in the given order
+pub fn build_pipeline<I, S>(
+    registry: Arc<NodeRegistry>,
+    nodes: I,
+) -> tweers_core::error::Result<PipelineBuilder>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    PipelineBuilder::new("TweersBuildPipeline", registry).add_nodes(nodes)
+}
+
+/// Pack pipeline running `nodes` of `registry` in the given order
+pub fn pack_pipeline<I, S>(
+    registry: Arc<NodeRegistry>,
+    nodes: I,
+) -> tweers_core::error::Result<PipelineBuilder>
+where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+{
+    PipelineBuilder::new("TweersPackPipeline", registry).add_nodes(nodes)
+}
+
 pub async fn build_command(
     sources: Vec<PathBuf>,
     dist: PathBuf,
@@ -163,13 +292,19 @@ pub async fn build_command(
         is_debug,
         base64,
         start_passage,
-        vec![],
-        vec![],
+        ConflictPolicy::default(),
+        FormatSearchPath::discover(Path::new(".")),
+        None,
+        build_pipeline(
+            Arc::new(default_registry()),
+            DEFAULT_BUILD_NODES.iter().copied(),
+        )?,
+        None,
     )
     .await
 }
 
-/// Build command with external node injection support
+/// Build command running the nodes of a custom pipeline
 #[allow(clippy::too_many_arguments)]
 pub async fn build_command_with_nodes(
     sources: Vec<PathBuf>,
@@ -178,123 +313,328 @@ pub async fn build_command_with_nodes(
     is_debug: bool,
     base64: bool,
     start_passage: Option<String>,
-    data_nodes: Vec<Box<dyn PipeNode + Send + Sync>>,
-    html_nodes: Vec<Box<dyn PipeNode + Send + Sync>>,
+    conflict_policy: ConflictPolicy,
+    format_search_path: FormatSearchPath,
+    cache_dir: Option<PathBuf>,
+    pipeline: PipelineBuilder,
+    reload: Option<PipelineReload>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     debug!("Starting build command");
     debug!("Sources: {:?}", sources);
     debug!("Output: {:?}", dist);
 
     let mut context = BuildContext::new(is_debug, base64, start_passage);
+    context.conflict_policy = conflict_policy;
+    context.format_search_path = format_search_path;
+    context.disk_cache = cache_dir.map(DiskCache::new);
 
-    build_once(&sources, &dist, &mut context, false, data_nodes, html_nodes).await?;
+    build_once(&sources, &dist, &mut context, false, &pipeline).await?;
 
     if watch {
         info!("Entering watch mode...");
-        watch_and_rebuild(sources, dist, context).await?;
+        watch_and_rebuild(sources, dist, context, pipeline, reload, None).await?;
     }
 
     Ok(())
 }
 
-/// Build using pipeline system
-async fn build_once(
-    sources: &[PathBuf],
-    dist: &Path,
-    context: &mut BuildContext,
-    is_rebuild: bool,
-    data_nodes: Vec<Box<dyn PipeNode + Send + Sync>>,
-    html_nodes: Vec<Box<dyn PipeNode + Send + Sync>>,
+/// Serve command - build, serve the story with `server` and rebuild on changes
+///
+/// Unlike `build --watch`, a failing first build does not end the command:
+/// the error is shown in the browser and the next change is built again.
+#[allow(clippy::too_many_arguments)]
+pub async fn serve_command(
+    sources: Vec<PathBuf>,
+    dist: PathBuf,
+    is_debug: bool,
+    base64: bool,
+    start_passage: Option<String>,
+    conflict_policy: ConflictPolicy,
+    format_search_path: FormatSearchPath,
+    cache_dir: Option<PathBuf>,
+    server: DevServer,
+    live_reload: LiveReload,
+    pipeline: PipelineBuilder,
+    reload: Option<PipelineReload>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    debug!("Starting pipeline-based build process...");
+    debug!("Starting serve command");
+    debug!("Sources: {:?}", sources);
+    debug!("Output: {:?}", dist);
 
-    // Load story format if not already loaded
-    if context.story_format.is_none() {
-        // First, we need to parse files to get StoryData
-        let files =
-            crate::io::collect_files_with_base64(sources, context.base64, is_rebuild).await?;
+    let mut context = BuildContext::new(is_debug, base64, start_passage);
+    context.conflict_policy = conflict_policy;
+    context.format_search_path = format_search_path;
+    context.disk_cache = cache_dir.map(DiskCache::new);
 
-        for file_path in &files {
-            if context.is_file_modified(file_path)? {
-                // Check if it's a text file (twee/tw)
-                if let Some(ext) = file_path.extension() {
-                    if let Some(ext_str) = ext.to_str() {
-                        if matches!(ext_str, "twee" | "tw") {
-                            let content = tokio::fs::read_to_string(file_path).await?;
-                            if let Ok(parsed) = tweers_core::core::file::parse_text_content(
-                                &file_path.to_string_lossy(),
-                                &content,
-                            ) {
-                                context.update_cache(
-                                    file_path.clone(),
-                                    parsed.passages,
-                                    parsed.story_data.clone(),
-                                )?;
-
-                                if let Some(data) = parsed.story_data {
-                                    // Load story format based on StoryData
-                                    let format_source = crate::format::find_and_load_format(
-                                        &data.format,
-                                        &data.format_version,
-                                    )
-                                    .await?;
-
-                                    let story_format =
-                                        tweers_core::core::story::StoryFormat::parse(
-                                            &format_source,
-                                        )?;
-                                    context.story_format = Some(story_format);
-                                    context.format_name = data.format.clone();
-                                    context.format_version = data.format_version.clone();
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
+    let server = tokio::spawn(async move {
+        if let Err(e) = server.run().await {
+            error!("Server stopped: {}", e);
         }
+    });
 
-        if context.story_format.is_none() {
-            return Err("Failed to load story format: no StoryData found in source files".into());
-        }
+    let result = build_once(&sources, &dist, &mut context, false, &pipeline).await;
+    if let Err(e) = &result {
+        error!("Build failed: {}", e);
     }
+    live_reload.build_finished(&result);
+
+    info!("Entering watch mode...");
+    let watched =
+        watch_and_rebuild(sources, dist, context, pipeline, reload, Some(live_reload)).await;
+    server.abort();
+    watched
+}
+
+/// Check command - run lint rules on sources without writing any output
+///
+/// Failures of the collect/parse/aggregate stages are reported as error
+/// diagnostics so that callers get a single list of findings, with one
+/// located `parse-error` per parse error.
+pub async fn check_command(
+    sources: Vec<PathBuf>,
+    start_passage: Option<String>,
+    conflict_policy: ConflictPolicy,
+) -> Result<Vec<Diagnostic>, Box<dyn std::error::Error + Send + Sync>> {
+    debug!("Starting check command");
+    debug!("Sources: {:?}", sources);
+
+    // Conflicts are reported by the lint rules, the merge only needs to pass
+    let mut context = BuildContext::new(false, false, start_passage);
+    context.conflict_policy = conflict_policy.lenient();
 
-    let mut pipeline = Pipeline::new("TweersBuildPipeline")
-        .with_external_inputs(vec![
-            "sources".to_string(),
-            "base64".to_string(),
-            "context".to_string(),
-            "output_path".to_string(),
-            "is_rebuild".to_string(),
-        ])
+    let pipeline = Pipeline::new("TweersCheckPipeline")
+        .with_external_inputs(vec!["sources".to_string(), "context".to_string()])
+        .with_external_outputs(vec!["diagnostics".to_string()])
         .add_node(Box::new(FileCollectorNode))?
         .add_node(Box::new(FileChangeDetectorNode))?
         .add_node(Box::new(FileParserNode))?
-        .add_node(Box::new(DataAggregatorNode))?;
+        .add_node(Box::new(DataAggregatorNode))?
+        .add_node(Box::new(LintNode::new(conflict_policy)))?;
+
+    let mut pipe_data = PipeMap::new();
+    pipe_data.insert_typed(tweers_core::pipeline::SOURCES, sources);
+    pipe_data.insert_typed(CONTEXT, context);
+
+    match pipeline.execute(pipe_data).await {
+        Ok(result) => Ok(result
+            .get_typed(tweers_core::pipeline::DIAGNOSTICS)
+            .cloned()
+            .unwrap_or_default()),
+        // Every located parse error is a finding of its own
+        Err(TweersError::Parse(parse)) if !parse.diagnostics.is_empty() => Ok(parse
+            .diagnostics
+            .into_iter()
+            .map(Diagnostic::from)
+            .collect()),
+        Err(e) => Ok(vec![Diagnostic::error("build", e.to_string())]),
+    }
+}
+
+/// Graph command - render the passage link graph of the sources
+pub async fn graph_command(
+    sources: Vec<PathBuf>,
+    format: GraphFormat,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    use tweers_core::api::InputSource;
+    use tweers_core::core::file::{detect_file_type, FileType};
+
+    debug!("Starting graph command");
+    debug!("Sources: {:?}", sources);
+
+    let files = crate::io::collect_files_with_base64(&sources, false, false).await?;
+    if files.is_empty() {
+        return Err("No support files found in the specified sources".into());
+    }
+
+    let mut inputs = Vec::new();
+    for file_path in files {
+        let name = file_path.to_string_lossy().to_string();
+        let input = match detect_file_type(&name) {
+            FileType::Excel => InputSource::Bytes {
+                name,
+                data: tokio::fs::read(&file_path).await?,
+                mime_type: None,
+            },
+            _ => InputSource::Text {
+                name,
+                content: tokio::fs::read_to_string(&file_path).await?,
+            },
+        };
+        inputs.push(input);
+    }
+
+    tweers_core::api::story_graph(inputs, format)
+}
+
+/// Outcome of formatting Twee sources
+#[derive(Debug, Default)]
+pub struct FormatReport {
+    /// Number of Twee files that were looked at
+    pub checked: usize,
+    /// Files that are not in canonical layout (rewritten unless checking)
+    pub changed: Vec<PathBuf>,
+    /// Files that could not be parsed, these are left untouched
+    pub errors: Vec<ParseDiagnostics>,
+}
+
+/// Format command - rewrite .twee/.tw sources into their canonical layout
+///
+/// With `check` set no file is written, the report only lists the files
+/// that would change.
+pub async fn fmt_command(
+    sources: Vec<PathBuf>,
+    check: bool,
+) -> Result<FormatReport, Box<dyn std::error::Error + Send + Sync>> {
+    use tweers_core::core::file::{detect_file_type, FileType};
+    use tweers_core::core::formatter::format_twee;
+
+    debug!("Starting fmt command");
+    debug!("Sources: {:?}", sources);
+
+    let files = crate::io::collect_files_with_base64(&sources, false, false).await?;
+    let mut report = FormatReport::default();
+
+    for file_path in files {
+        let name = file_path.to_string_lossy().to_string();
+        if detect_file_type(&name) != FileType::Twee {
+            continue;
+        }
+        report.checked += 1;
+
+        let content = tokio::fs::read_to_string(&file_path).await?;
+        let formatted = match format_twee(&content) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                report.errors.push(e.with_file(&name));
+                continue;
+            }
+        };
 
-    // Add external data processing nodes
-    for node in data_nodes {
-        pipeline = pipeline.add_node(node)?;
+        if formatted != content {
+            if !check {
+                tokio::fs::write(&file_path, formatted).await?;
+                debug!("Formatted {}", name);
+            }
+            report.changed.push(file_path);
+        }
     }
 
-    pipeline = pipeline.add_node(Box::new(HtmlGeneratorNode))?;
+    Ok(report)
+}
+
+/// Init command - create a new project with a fresh IFID
+///
+/// The story format is picked from the formats on the search path of the new
+/// project. Existing files are never overwritten. Returns the created files.
+pub async fn init_command(
+    path: PathBuf,
+    title: Option<String>,
+    template: Option<ProjectTemplate>,
+    format_dirs: Vec<PathBuf>,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error + Send + Sync>> {
+    debug!("Starting init command in {}", path.display());
+
+    let search_path = FormatSearchPath::discover(&path).with_dirs(format_dirs);
+    let installed = crate::format::installed_formats(&search_path).await?;
+    let format = scaffold::choose_format(&installed, template)?;
+    info!("Using story format {} {}", format.name, format.version);
+
+    let title = title.unwrap_or_else(|| {
+        std::path::absolute(&path)
+            .ok()
+            .and_then(|path| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| "Untitled Story".to_string())
+    });
+    let files = scaffold::project_files(&title, &scaffold::generate_ifid(), format, template);
+
+    if let Some((existing, _)) = files.iter().find(|(file, _)| path.join(file).exists()) {
+        return Err(format!("{} already exists", path.join(existing).display()).into());
+    }
 
-    // Add external HTML processing nodes
-    for node in html_nodes {
-        pipeline = pipeline.add_node(node)?;
+    let mut created = Vec::new();
+    for (file, content) in files {
+        let file = path.join(file);
+        if let Some(parent) = file.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&file, content).await?;
+        created.push(file);
     }
 
-    pipeline = pipeline.add_node(Box::new(FileWriterNode))?;
+    Ok(created)
+}
+
+/// External inputs of the build pipeline
+const BUILD_INPUTS: &[&str] = &["sources", "base64", "context", "output_path", "is_rebuild"];
+
+/// External inputs of the pack pipeline, next to the build inputs
+const PACK_INPUTS: &[&str] = &[
+    "assets_dirs",
+    "html_output_path",
+    "pack_output_path",
+    "fast_compression",
+];
+
+fn key_names(keys: &[&[&str]]) -> Vec<String> {
+    keys.concat().iter().map(|key| key.to_string()).collect()
+}
 
+/// Pipeline data holding the build inputs
+fn build_inputs(
+    sources: &[PathBuf],
+    dist: &Path,
+    context: &BuildContext,
+    is_rebuild: bool,
+) -> PipeMap {
     let mut pipe_data = PipeMap::new();
     pipe_data.insert_typed(tweers_core::pipeline::SOURCES, sources.to_vec());
     pipe_data.insert_typed(tweers_core::pipeline::BASE64, context.base64);
     pipe_data.insert_typed(CONTEXT, context.clone());
     pipe_data.insert_typed(tweers_core::pipeline::OUTPUT_PATH, dist.to_path_buf());
     pipe_data.insert_typed(tweers_core::pipeline::IS_REBUILD, is_rebuild);
+    pipe_data
+}
 
-    let result = pipeline.execute(pipe_data).await?;
+/// Warn about node outputs nothing reads
+///
+/// The default pipelines use every output, so these come from nodes listed
+/// in a configured pipeline. `Pipeline::execute` only logs them at debug
+/// level, as it runs again on every rebuild.
+fn warn_unused_outputs(pipeline: &Pipeline) -> tweers_core::error::Result<()> {
+    for unused in pipeline.validate()? {
+        warn!("Pipeline '{}': {}", pipeline.name(), unused);
+    }
+    Ok(())
+}
+
+/// Build using pipeline system
+async fn build_once(
+    sources: &[PathBuf],
+    dist: &Path,
+    context: &mut BuildContext,
+    is_rebuild: bool,
+    pipeline: &PipelineBuilder,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    debug!("Starting pipeline-based build process...");
+
+    load_story_format(sources, context, is_rebuild).await?;
+
+    debug!("Build pipeline nodes: {:?}", pipeline.node_names());
+    let pipeline = pipeline
+        .clone()
+        .with_external_inputs(key_names(&[BUILD_INPUTS]))
+        .with_external_outputs(vec!["context".to_string()])
+        .build()?;
+    if !is_rebuild {
+        warn_unused_outputs(&pipeline)?;
+    }
+
+    let result = pipeline
+        .execute(build_inputs(sources, dist, context, is_rebuild))
+        .await?;
 
     if let Some(updated_context) = result.get_typed(CONTEXT) {
         *context = updated_context.clone();
@@ -309,11 +649,83 @@ async fn build_once(
     Ok(())
 }
 
+/// Parse a twee file ahead of the pipeline and cache its passages
+///
+/// Returns the StoryData of the file, or None when it has none or does not
+/// parse, the pipeline reports parse errors later.
+async fn parse_story_file(
+    context: &mut BuildContext,
+    file_path: &Path,
+) -> Result<Option<StoryData>, Box<dyn std::error::Error + Send + Sync>> {
+    let parsed = match context.load_cached(file_path) {
+        Some(cached) => Some((cached.passages, cached.story_data)),
+        None => {
+            let bytes = tokio::fs::read(file_path).await?;
+            let hash = ContentHash::of(&bytes);
+            let content = String::from_utf8(bytes)?;
+            tweers_core::core::file::parse_text_content(&file_path.to_string_lossy(), &content)
+                .ok()
+                .map(|parsed| {
+                    context.store_cached(file_path, &hash, &parsed.passages, &parsed.story_data);
+                    (parsed.passages, parsed.story_data)
+                })
+        }
+    };
+
+    match parsed {
+        Some((passages, story_data)) => {
+            context.update_cache(file_path.to_path_buf(), passages, story_data.clone())?;
+            Ok(story_data)
+        }
+        None => Ok(None),
+    }
+}
+
+fn is_twee_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext, "twee" | "tw"))
+}
+
+/// Load the story format named in StoryData unless it is loaded already
+async fn load_story_format(
+    sources: &[PathBuf],
+    context: &mut BuildContext,
+    is_rebuild: bool,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if context.story_format.is_none() {
+        // First, we need to parse files to get StoryData
+        let files =
+            crate::io::collect_files_with_base64(sources, context.base64, is_rebuild).await?;
+
+        for file_path in &files {
+            if is_twee_file(file_path) && context.is_file_modified(file_path)? {
+                if let Some(data) = parse_story_file(context, file_path).await? {
+                    // Load story format based on StoryData
+                    context
+                        .load_format(&data.format, &data.format_version)
+                        .await?;
+                    break;
+                }
+            }
+        }
+
+        if context.story_format.is_none() {
+            return Err("Failed to load story format: no StoryData found in source files".into());
+        }
+    }
+
+    Ok(())
+}
+
 /// Watch for file changes and rebuild
 async fn watch_and_rebuild(
     sources: Vec<PathBuf>,
     dist: PathBuf,
     mut context: BuildContext,
+    mut pipeline: PipelineBuilder,
+    mut reload: Option<PipelineReload>,
+    live_reload: Option<LiveReload>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     use notify::{Config, RecommendedWatcher, Watcher};
     use std::sync::mpsc;
@@ -342,47 +754,92 @@ async fn watch_and_rebuild(
         }
     }
 
+    if let Some(reload) = &reload {
+        for dir in reload.dirs.iter().filter(|dir| dir.is_dir()) {
+            watcher.watch(dir, RecursiveMode::Recursive)?;
+            debug!("Watching pipeline directory: {:?}", dir);
+        }
+    }
+
+    // The story format lives outside the sources, watch the directory of
+    // format.js so that editors replacing the file are noticed as well
+    let mut format_dir = None;
+    watch_format_dir(&mut watcher, &context, &mut format_dir);
+
     debug!("File watcher initialized. Waiting for changes...");
 
     let mut pending_changes: HashSet<PathBuf> = HashSet::new();
+    let mut pending_reload = false;
+    let mut pending_format = false;
     let mut last_event_time = std::time::Instant::now();
 
     loop {
         match rx.recv_timeout(Duration::from_millis(100)) {
-            Ok(Ok(event)) => match event.kind {
-                EventKind::Create(_) | EventKind::Modify(_) => {
-                    let relevant_paths: Vec<_> = event
-                        .paths
-                        .iter()
-                        .filter(|path| is_support_file_with_base64(path, context.base64))
-                        .cloned()
-                        .collect();
-
-                    if !relevant_paths.is_empty() {
-                        for path in relevant_paths {
-                            pending_changes.insert(path);
-                        }
-                        last_event_time = std::time::Instant::now();
-                    }
+            Ok(Ok(event)) => {
+                if is_format_change(&event, &context) {
+                    pending_format = true;
+                    last_event_time = std::time::Instant::now();
+                    continue;
                 }
-                _ => {}
-            },
+
+                if reload
+                    .as_ref()
+                    .is_some_and(|reload| reload.is_affected_by(&event))
+                {
+                    pending_reload = true;
+                    last_event_time = std::time::Instant::now();
+                    continue;
+                }
+
+                let changes = watch_changes(&event, &mut context);
+                if !changes.is_empty() {
+                    pending_changes.extend(changes);
+                    last_event_time = std::time::Instant::now();
+                }
+            }
             Ok(Err(e)) => {
                 warn!("Watch error: {}", e);
             }
             Err(mpsc::RecvTimeoutError::Timeout) => {
-                if !pending_changes.is_empty()
+                if (!pending_changes.is_empty() || pending_reload || pending_format)
                     && last_event_time.elapsed() >= Duration::from_millis(200)
                 {
                     let changed_files: Vec<_> = pending_changes.iter().cloned().collect();
                     pending_changes.clear();
 
-                    info!("Detected changes in source files: {:?}", changed_files);
+                    if !changed_files.is_empty() {
+                        info!("Detected changes in source files: {:?}", changed_files);
+                    }
+                    if let Some(reload) = reload.as_mut().filter(|_| pending_reload) {
+                        pending_reload = false;
+                        info!("Detected changes in {:?}, reloading pipeline", reload.dirs);
+                        match (reload.reload)() {
+                            Ok(reloaded) => pipeline = reloaded,
+                            Err(e) => error!("Failed to reload pipeline: {}", e),
+                        }
+                    }
 
-                    match build_once(&sources, &dist, &mut context, true, vec![], vec![]).await {
+                    let result = if std::mem::take(&mut pending_format) {
+                        info!("Detected changes in story format, reloading");
+                        let (name, version) =
+                            (context.format_name.clone(), context.format_version.clone());
+                        context.load_format(&name, &version).await
+                    } else {
+                        Ok(())
+                    };
+                    let result = match result {
+                        Ok(()) => build_once(&sources, &dist, &mut context, true, &pipeline).await,
+                        Err(e) => Err(e),
+                    };
+                    watch_format_dir(&mut watcher, &context, &mut format_dir);
+
+                    match &result {
                         Ok(()) => debug!("Rebuild completed successfully"),
                         Err(e) => error!("Rebuild failed: {}", e),
                     }
+                    if let Some(live_reload) = &live_reload {
+                        live_reload.build_finished(&result);
+                    }
                 }
             }
             Err(mpsc::RecvTimeoutError::Disconnected) => {
@@ -395,6 +852,38 @@ async fn watch_and_rebuild(
     Ok(())
 }
 
+/// Watch the directory of the loaded story format, moving the watch when
+/// the format was loaded from elsewhere
+fn watch_format_dir(
+    watcher: &mut notify::RecommendedWatcher,
+    context: &BuildContext,
+    watched: &mut Option<PathBuf>,
+) {
+    use notify::Watcher;
+
+    let dir = context
+        .format_path
+        .as_ref()
+        .and_then(|path| path.parent())
+        .map(Path::to_path_buf);
+    if dir == *watched {
+        return;
+    }
+
+    if let Some(old) = watched.take() {
+        let _ = watcher.unwatch(&old);
+    }
+    if let Some(dir) = dir {
+        match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                debug!("Watching story format directory: {:?}", dir);
+                *watched = Some(dir);
+            }
+            Err(e) => warn!("Failed to watch story format directory {:?}: {}", dir, e),
+        }
+    }
+}
+
 pub async fn pack_command(
     sources: Vec<PathBuf>,
     assets_dirs: Vec<PathBuf>,
@@ -402,104 +891,114 @@ pub async fn pack_command(
     fast_compression: bool,
     is_debug: bool,
 ) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
+    let registry = Arc::new(default_registry());
     pack_command_with_nodes(
         sources,
         assets_dirs,
         output_path,
         fast_compression,
         is_debug,
-        vec![],
+        ConflictPolicy::default(),
+        FormatSearchPath::discover(Path::new(".")),
+        None,
+        build_pipeline(registry.clone(), DEFAULT_BUILD_NODES.iter().copied())?,
+        pack_pipeline(registry, Vec::<String>::new())?,
     )
     .await
 }
 
-/// Pack command with external node injection support
+/// Pack command running the nodes of custom build and pack pipelines
+#[allow(clippy::too_many_arguments)]
 pub async fn pack_command_with_nodes(
     sources: Vec<PathBuf>,
     assets_dirs: Vec<PathBuf>,
     output_path: PathBuf,
     fast_compression: bool,
     is_debug: bool,
-    pack_nodes: Vec<Box<dyn PipeNode + Send + Sync>>,
+    conflict_policy: ConflictPolicy,
+    format_search_path: FormatSearchPath,
+    cache_dir: Option<PathBuf>,
+    build: PipelineBuilder,
+    pack: PipelineBuilder,
 ) -> Result<PathBuf, Box<dyn std::error::Error + Send + Sync>> {
     debug!("Starting pack command");
 
     let mut context = BuildContext::with_assets(is_debug, true, assets_dirs.clone());
+    context.conflict_policy = conflict_policy;
+    context.format_search_path = format_search_path;
+    context.disk_cache = cache_dir.map(DiskCache::new);
 
     let temp_dir = std::env::temp_dir().join(format!("tweers_pack_{}", std::process::id()));
     std::fs::create_dir_all(&temp_dir)?;
 
     let temp_html = temp_dir.join("temp_index.html");
-    build_once(&sources, &temp_html, &mut context, false, vec![], vec![]).await?;
-
-    let (all_passages, _) = context.get_all_cached_data();
-    let story_title = all_passages
-        .get("StoryTitle")
-        .map(|p| p.content.trim().to_string())
-        .filter(|title| !title.is_empty())
-        .unwrap_or_else(|| "story".to_string());
-
-    let actual_output_path = if output_path == Path::new("package.zip") {
-        PathBuf::from(format!("{story_title}.zip"))
+    let is_default_output = output_path == Path::new("package.zip");
+    let archive_path = if is_default_output {
+        temp_dir.join("package.zip")
     } else {
-        output_path
+        output_path.clone()
     };
 
-    pack_once(
-        &assets_dirs,
-        &temp_html,
-        &actual_output_path,
-        fast_compression,
-        &context,
-        pack_nodes,
-    )
-    .await?;
+    load_story_format(&sources, &mut context, false).await?;
 
-    if temp_dir.exists() {
-        std::fs::remove_dir_all(&temp_dir)?;
-    }
-
-    Ok(actual_output_path)
-}
-
-/// Pack using pipeline system with external nodes
-async fn pack_once(
-    assets_dirs: &[PathBuf],
-    html_output_path: &Path,
-    archive_output_path: &Path,
-    fast_compression: bool,
-    context: &BuildContext,
-    pack_nodes: Vec<Box<dyn PipeNode + Send + Sync>>,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Build and pack nodes run as one pipeline, so that in concurrent mode
+    // the pack nodes only wait for the build nodes they depend on
     debug!("Starting pipeline-based pack process...");
-
-    let mut pipeline = Pipeline::new("TweersPackPipeline").with_external_inputs(vec![
-        "assets_dirs".to_string(),
-        "html_output_path".to_string(),
-        "pack_output_path".to_string(),
-        "fast_compression".to_string(),
-        "context".to_string(),
-    ]);
-
-    for node in pack_nodes {
-        pipeline = pipeline.add_node(node)?;
-    }
-
-    let mut pipe_data = PipeMap::new();
-    pipe_data.insert_typed(tweers_core::pipeline::ASSETS_DIRS, assets_dirs.to_vec());
-    pipe_data.insert_typed(
-        tweers_core::pipeline::HTML_OUTPUT_PATH,
-        html_output_path.to_path_buf(),
+    debug!(
+        "Pack pipeline nodes: {:?} + {:?}",
+        build.node_names(),
+        pack.node_names()
     );
+    let pipeline = Pipeline::new("TweersPackPipeline")
+        .with_external_inputs(key_names(&[BUILD_INPUTS, PACK_INPUTS]))
+        .with_external_outputs(vec!["context".to_string()])
+        .with_execution_mode(pack.execution_mode())
+        .append(build.clone().build()?)
+        .append(pack.clone().build()?);
+    warn_unused_outputs(&pipeline)?;
+
+    let mut pipe_data = build_inputs(&sources, &temp_html, &context, false);
+    pipe_data.insert_typed(tweers_core::pipeline::ASSETS_DIRS, assets_dirs);
+    pipe_data.insert_typed(tweers_core::pipeline::HTML_OUTPUT_PATH, temp_html);
     pipe_data.insert_typed(
         tweers_core::pipeline::PACK_OUTPUT_PATH,
-        archive_output_path.to_path_buf(),
+        archive_path.clone(),
     );
     pipe_data.insert_typed(tweers_core::pipeline::FAST_COMPRESSION, fast_compression);
-    pipe_data.insert_typed(CONTEXT, context.clone());
 
-    let _result = pipeline.execute(pipe_data).await?;
+    let result = pipeline.execute(pipe_data).await?;
+    if let Some(updated_context) = result.get_typed(CONTEXT) {
+        context = updated_context.clone();
+    }
+
+    if !archive_path.exists() {
+        std::fs::remove_dir_all(&temp_dir)?;
+        return Err(format!(
+            "Pack pipeline finished without writing an archive to {}",
+            output_path.display()
+        )
+        .into());
+    }
+
+    let actual_output_path = if is_default_output {
+        let (all_passages, _) = context.get_all_cached_data()?;
+        let story_title = all_passages
+            .get("StoryTitle")
+            .map(|p| p.content.trim().to_string())
+            .filter(|title| !title.is_empty())
+            .unwrap_or_else(|| "story".to_string());
+        let path = PathBuf::from(format!("{story_title}.zip"));
+        std::fs::copy(&archive_path, &path)?;
+        path
+    } else {
+        output_path
+    };
 
     info!("Pack pipeline completed successfully");
-    Ok(())
+
+    if temp_dir.exists() {
+        std::fs::remove_dir_all(&temp_dir)?;
+    }
+
+    Ok(actual_output_path)
 }
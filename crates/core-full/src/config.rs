@@ -0,0 +1,185 @@
+// Project configuration file (tweers.toml)
+
+use crate::format::FormatSearchPath;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+use tweers_core::config::constants;
+use tweers_core::core::conflict::{ConflictPolicy, DuplicatePolicy, StoryDataPolicy};
+
+/// Settings shared by every command of a project
+///
+/// Relative paths are resolved against the directory holding the config
+/// file, so commands behave the same from any subdirectory.
+///
+/// ```toml
+/// sources = ["src"]
+/// format-dirs = ["formats"]
+/// script-dirs = ["scripts"]
+/// cache = true
+/// duplicates = "last-wins"
+/// story-data = "error"
+///
+/// [build]
+/// output = "dist/index.html"
+/// start-passage = "Start"
+///
+/// [pack]
+/// assets = ["assets"]
+/// fast-compression = true
+/// concurrent = true
+/// pipeline = ["asset_compressor", "archive_creator"]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct ProjectConfig {
+    /// Directory holding the config file
+    #[serde(skip)]
+    pub root: PathBuf,
+    pub sources: Vec<PathBuf>,
+    /// Story format directories, searched before the default ones
+    pub format_dirs: Vec<PathBuf>,
+    /// Directories holding `data/` and `html/` processing scripts
+    pub script_dirs: Vec<PathBuf>,
+    /// Keep parse results in `.tweers/cache` between runs
+    pub cache: bool,
+    /// How to handle duplicate passage names: error, warn or last-wins
+    pub duplicates: Option<DuplicatePolicy>,
+    /// How to handle conflicting StoryData/StoryTitle: error, first or last
+    pub story_data: Option<StoryDataPolicy>,
+    pub build: BuildSection,
+    pub pack: PackSection,
+}
+
+/// `[build]` settings
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct BuildSection {
+    pub output: Option<PathBuf>,
+    pub debug: bool,
+    pub base64: bool,
+    pub start_passage: Option<String>,
+    /// Registered node names to run instead of the default build pipeline
+    pub pipeline: Option<Vec<String>>,
+    /// Run independent pipeline nodes concurrently
+    ///
+    /// The default build nodes and JS script nodes all read and write the
+    /// build context, so they still run one after another. Only custom nodes
+    /// that share no keys with them overlap.
+    pub concurrent: bool,
+}
+
+/// `[pack]` settings
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PackSection {
+    pub output: Option<PathBuf>,
+    pub assets: Vec<PathBuf>,
+    pub fast_compression: bool,
+    pub debug: bool,
+    /// Registered node names to run instead of the default pack pipeline
+    pub pipeline: Option<Vec<String>>,
+    /// Run independent nodes concurrently, such as asset compression next to
+    /// HTML generation
+    pub concurrent: bool,
+}
+
+impl ProjectConfig {
+    /// Find the config file in `start` or the closest ancestor that has one
+    pub fn discover(
+        start: &Path,
+    ) -> Result<Option<Self>, Box<dyn std::error::Error + Send + Sync>> {
+        for dir in start.ancestors() {
+            let path = dir.join(constants::CONFIG_FILE);
+            if path.is_file() {
+                debug!("Using project config: {}", path.display());
+                return Self::load(&path).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Read a config file
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let mut config = Self::parse(&content)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        config.root = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        Ok(config)
+    }
+
+    /// Parse config file content, paths are left relative
+    pub fn parse(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    /// Resolve a path from the config file against its directory
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        self.root.join(path)
+    }
+
+    pub fn sources(&self) -> Vec<PathBuf> {
+        self.resolve_all(&self.sources)
+    }
+
+    /// Configured format directories followed by the project-local one
+    pub fn format_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = self.resolve_all(&self.format_dirs);
+        if !self.root.as_os_str().is_empty() {
+            dirs.push(self.root.join(constants::PROJECT_FORMAT_DIR));
+        }
+        dirs
+    }
+
+    /// Story format search path of the project
+    ///
+    /// `dirs` and the configured format directories come first, followed by
+    /// the default directories with the project-local one of the project root.
+    pub fn format_search_path(&self, dirs: Vec<PathBuf>) -> FormatSearchPath {
+        FormatSearchPath::discover(&self.root)
+            .with_dirs([dirs, self.resolve_all(&self.format_dirs)].concat())
+    }
+
+    /// Conflict policy of the project
+    ///
+    /// `duplicates` and `story_data` come from the command line and take
+    /// precedence over the configured policies.
+    pub fn conflict_policy(
+        &self,
+        duplicates: Option<DuplicatePolicy>,
+        story_data: Option<StoryDataPolicy>,
+    ) -> ConflictPolicy {
+        let default = ConflictPolicy::default();
+        ConflictPolicy {
+            duplicates: duplicates.or(self.duplicates).unwrap_or(default.duplicates),
+            story_data: story_data.or(self.story_data).unwrap_or(default.story_data),
+        }
+    }
+
+    pub fn script_dirs(&self) -> Vec<PathBuf> {
+        self.resolve_all(&self.script_dirs)
+    }
+
+    /// Parse cache directory, when the cache is enabled
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        self.cache.then(|| self.root.join(constants::CACHE_DIR))
+    }
+
+    pub fn build_output(&self) -> Option<PathBuf> {
+        self.build.output.as_deref().map(|path| self.resolve(path))
+    }
+
+    pub fn pack_output(&self) -> Option<PathBuf> {
+        self.pack.output.as_deref().map(|path| self.resolve(path))
+    }
+
+    pub fn assets_dirs(&self) -> Vec<PathBuf> {
+        self.resolve_all(&self.pack.assets)
+    }
+
+    fn resolve_all(&self, paths: &[PathBuf]) -> Vec<PathBuf> {
+        paths.iter().map(|path| self.resolve(path)).collect()
+    }
+}
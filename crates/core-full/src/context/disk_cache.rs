@@ -0,0 +1,136 @@
+/// Persistent parse cache shared between runs
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use tweers_core::core::story::{Passage, StoryData};
+
+/// Parse result of one file as stored on disk
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedFile {
+    pub passages: IndexMap<String, Passage>,
+    pub story_data: Option<StoryData>,
+}
+
+/// Hash of file content, taken from the exact bytes that were parsed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentHash([u8; 32]);
+
+impl ContentHash {
+    pub fn of(content: &[u8]) -> Self {
+        Self(Sha256::digest(content).into())
+    }
+}
+
+/// Parsed files stored on disk, keyed by file content rather than mtime
+///
+/// Entries of a file live in a directory named by a hash of the
+/// tweers-core-full and tweers-core versions, the file path and the base64
+/// flag, and are named by the hash of the file content. A checkout that only
+/// touches mtimes still hits, while upgrading tweers or its parser never
+/// reuses stale results. Storing an entry removes the
+/// older entries of the same file. Failures to read or write entries are
+/// logged and treated as cache misses.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Cached parse result of the current content of `path`
+    pub fn load(&self, path: &Path, base64: bool) -> Option<CachedFile> {
+        let content = std::fs::read(path).ok()?;
+        let entry = self.entry_path(path, base64, &ContentHash::of(&content));
+
+        let json = std::fs::read(&entry).ok()?;
+        match serde_json::from_slice(&json) {
+            Ok(cached) => Some(cached),
+            Err(e) => {
+                debug!("Ignoring unreadable cache entry {:?}: {}", entry, e);
+                None
+            }
+        }
+    }
+
+    /// Store the parse result of `path`, parsed from content hashing to `hash`
+    pub fn store(
+        &self,
+        path: &Path,
+        base64: bool,
+        hash: &ContentHash,
+        passages: &IndexMap<String, Passage>,
+        story_data: &Option<StoryData>,
+    ) {
+        if let Err(e) = self.try_store(path, base64, hash, passages, story_data) {
+            warn!("Failed to cache parse result of {:?}: {}", path, e);
+        }
+    }
+
+    fn try_store(
+        &self,
+        path: &Path,
+        base64: bool,
+        hash: &ContentHash,
+        passages: &IndexMap<String, Passage>,
+        story_data: &Option<StoryData>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let entry = self.entry_path(path, base64, hash);
+        let json = serde_json::to_vec(&CachedFile {
+            passages: passages.clone(),
+            story_data: story_data.clone(),
+        })?;
+
+        // Write to a temporary file first, so that concurrent builds never
+        // read a half-written entry
+        let file_dir = entry.parent().expect("entries are in a file directory");
+        std::fs::create_dir_all(file_dir)?;
+        let temp = entry.with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&temp, json)?;
+        if let Err(e) = std::fs::rename(&temp, &entry) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e.into());
+        }
+        debug!("Cached parse result of {:?}", path);
+
+        // Older content of the same file is not coming back often enough to
+        // be worth keeping
+        for old in std::fs::read_dir(file_dir)? {
+            let old = old?.path();
+            if old != entry && old.extension().is_some_and(|ext| ext == "json") {
+                let _ = std::fs::remove_file(&old);
+            }
+        }
+        Ok(())
+    }
+
+    fn entry_path(&self, path: &Path, base64: bool, hash: &ContentHash) -> PathBuf {
+        let mut hasher = Sha256::new();
+        for part in [
+            env!("CARGO_PKG_VERSION").as_bytes(),
+            // The parser producing the cached results
+            tweers_core::VERSION.as_bytes(),
+            path.to_string_lossy().as_bytes(),
+            &[base64 as u8],
+        ] {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+
+        self.dir
+            .join(hex(&hasher.finalize()))
+            .join(format!("{}.json", hex(&hash.0)))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
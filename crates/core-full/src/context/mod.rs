@@ -1,10 +1,12 @@
 /// Context module for core-full
 pub mod cache;
 pub mod config;
+pub mod disk_cache;
 pub mod format;
 
 pub use cache::{FileCache, FileInfo};
 pub use config::ConcreteBuildConfig;
+pub use disk_cache::{CachedFile, ContentHash, DiskCache};
 pub use format::ConcreteFormatInfo;
 
 use tweers_core::context::{BuildConfig, BuildContext as BuildContextTrait, FormatInfo};
@@ -1,7 +1,8 @@
 // Story format discovery and loading module
 
-use std::path::Path;
-use tracing::{debug, warn};
+use semver::Version;
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
 use tweers_core::config::constants;
 use tweers_core::core::story::StoryFormat;
 
@@ -13,79 +14,153 @@ pub async fn load_format_from_path(
     Ok(content)
 }
 
-/// Find and load story format from file system
-/// Returns the format.js content as a String
-pub async fn find_and_load_format(
-    story_format: &str,
-    version: &str,
-) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
-    let exe_path = constants::EXECUTABLE_PATH
-        .get()
-        .ok_or("Executable path not initialized")?;
+/// A story format found in the formats directory
+#[derive(Debug, Clone)]
+pub struct InstalledFormat {
+    /// Name of the directory holding `format.js`
+    pub dir_name: String,
+    /// Path of `format.js`
+    pub path: PathBuf,
+    pub name: String,
+    pub version: String,
+}
 
-    let parent_dir = exe_path
-        .parent()
-        .ok_or("Failed to get parent directory".to_string())?;
-    let format_dir = parent_dir.join(constants::STORY_FORMAT_DIR);
+/// Ordered list of directories that story formats are looked up in
+///
+/// Earlier directories take precedence: a format is taken from the first
+/// directory that has a compatible version, even if a later directory has a
+/// higher one.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatSearchPath {
+    dirs: Vec<PathBuf>,
+}
 
-    debug!(
-        "Searching for story format '{}' version '{}' in directory: {}",
-        story_format,
-        version,
-        format_dir.display()
-    );
+impl FormatSearchPath {
+    /// The default search path for a project:
+    ///
+    /// 1. `<project>/.storyformats`
+    /// 2. every directory in `TWEERS_FORMAT_PATH`
+    /// 3. `<user config dir>/tweers/story-format`
+    /// 4. `<exe dir>/story-format`, the bundled formats
+    pub fn discover(project_dir: &Path) -> Self {
+        let mut dirs = vec![project_dir.join(constants::PROJECT_FORMAT_DIR)];
+
+        if let Some(paths) = std::env::var_os(constants::FORMAT_PATH_ENV) {
+            dirs.extend(std::env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()));
+        }
+        if let Some(user_dir) = user_format_dir() {
+            dirs.push(user_dir);
+        }
+        if let Some(bundled) = bundled_format_dir() {
+            dirs.push(bundled);
+        }
+
+        Self { dirs }
+    }
 
-    if !format_dir.exists() {
-        return Err(format!(
-            "Story formats directory not found: {}",
-            format_dir.display()
-        )
-        .into());
+    /// Search the given directories before every other one
+    pub fn with_dirs(mut self, dirs: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut first: Vec<PathBuf> = dirs.into_iter().collect();
+        first.append(&mut self.dirs);
+        self.dirs = first;
+        self
     }
 
-    // Try direct directory name match first
-    let expected_dir_name = format!("{}-{}", story_format.to_lowercase(), version);
-    let target_dir = format_dir.join(&expected_dir_name);
+    pub fn dirs(&self) -> &[PathBuf] {
+        &self.dirs
+    }
+}
 
-    debug!("Looking for directory: {}", target_dir.display());
+/// Per-user formats directory, where `tweers format add` installs by default
+pub fn user_format_dir() -> Option<PathBuf> {
+    Some(
+        dirs::config_dir()?
+            .join("tweers")
+            .join(constants::STORY_FORMAT_DIR),
+    )
+}
 
-    if target_dir.exists() && target_dir.is_dir() {
-        let format_file = target_dir.join("format.js");
-        if format_file.exists() {
-            debug!(
-                "Found format directory by name pattern: {}",
-                expected_dir_name
-            );
-            return load_format_from_path(&format_file).await;
-        } else {
-            warn!(
-                "Directory '{}' exists but missing format.js file",
-                expected_dir_name
-            );
-        }
+/// Directory of the formats bundled next to the executable
+fn bundled_format_dir() -> Option<PathBuf> {
+    let exe_path = constants::EXECUTABLE_PATH.get()?;
+    Some(exe_path.parent()?.join(constants::STORY_FORMAT_DIR))
+}
+
+/// List every story format on the search path, in order of precedence
+pub async fn installed_formats(
+    search_path: &FormatSearchPath,
+) -> Result<Vec<InstalledFormat>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut installed = Vec::new();
+    for dir in search_path.dirs().iter().filter(|dir| dir.is_dir()) {
+        installed.extend(
+            scan_formats(dir)
+                .await?
+                .into_iter()
+                .map(|(format, _)| format),
+        );
     }
+    Ok(installed)
+}
 
-    // Scan all directories to find matching format
-    let mut entries = tokio::fs::read_dir(&format_dir)
+/// A story format directory and the format it holds
+#[derive(Debug, Clone)]
+pub struct FormatEntry {
+    /// Directory holding `format.js`
+    pub dir: PathBuf,
+    /// The parsed format, or why it cannot be used
+    pub format: Result<StoryFormat, String>,
+}
+
+/// Read and parse the `format.js` of a story format directory
+///
+/// Returns the parsed format along with the raw `format.js` content.
+pub async fn load_format_dir(dir: &Path) -> Result<(StoryFormat, String), String> {
+    let format_file = dir.join("format.js");
+    if !format_file.exists() {
+        return Err("missing format.js file".to_string());
+    }
+
+    let content = load_format_from_path(&format_file)
         .await
-        .map_err(|_| format!("Failed to read directory: {}", format_dir.display()))?;
+        .map_err(|e| format!("failed to load format.js: {e}"))?;
+    let format =
+        StoryFormat::parse(&content).map_err(|e| format!("failed to parse format.js: {e}"))?;
+    if format.name.is_none() {
+        return Err("format.js has no name".to_string());
+    }
 
-    let mut found_formats = Vec::new();
-    let mut entry_count = 0;
+    Ok((format, content))
+}
+
+/// Load every subdirectory of a formats directory, sorted by name
+async fn read_format_dirs(
+    format_dir: &Path,
+) -> Result<
+    Vec<(PathBuf, Result<(StoryFormat, String), String>)>,
+    Box<dyn std::error::Error + Send + Sync>,
+> {
+    let mut entries = tokio::fs::read_dir(format_dir)
+        .await
+        .map_err(|_| format!("Failed to read directory: {}", format_dir.display()))?;
 
+    let mut dirs = Vec::new();
     while let Some(entry) = entries.next_entry().await? {
-        entry_count += 1;
         let entry_path = entry.path();
-
-        if !entry_path.is_dir() {
-            continue;
+        if entry_path.is_dir() {
+            dirs.push(entry_path);
         }
+    }
+    dirs.sort();
 
-        let dir_name = entry_path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("");
+    debug!(
+        "Found {} entries in format directory: {}",
+        dirs.len(),
+        format_dir.display()
+    );
 
+    let mut loaded = Vec::with_capacity(dirs.len());
+    for dir in dirs {
+        let dir_name = dir.file_name().and_then(|n| n.to_str()).unwrap_or("");
         if !dir_name.contains('-') {
             warn!(
                 "Story format directory '{}' does not follow the standard naming pattern 'format-version'",
@@ -93,96 +168,331 @@ pub async fn find_and_load_format(
             );
         }
 
-        let format_file = entry_path.join("format.js");
+        let format = load_format_dir(&dir).await;
+        loaded.push((dir, format));
+    }
 
-        if !format_file.exists() {
-            warn!(
-                "Story format directory '{}' is missing format.js file",
-                dir_name
-            );
-            continue;
-        }
+    Ok(loaded)
+}
 
-        // Load and parse the format to check name and version
-        match load_format_from_path(&format_file).await {
-            Ok(content) => match StoryFormat::parse(&content) {
-                Ok(story_format_struct) => {
-                    debug!(
-                        "Successfully loaded story format from: {}",
-                        format_file.display()
-                    );
-                    debug!(
-                        "Format name: {:?}, version: {}",
-                        story_format_struct.name, story_format_struct.version
-                    );
-
-                    let name_match = match story_format_struct.name {
-                        Some(ref name) => {
-                            let matches = name.eq_ignore_ascii_case(story_format);
-                            debug!(
-                                "Name comparison: '{}' vs '{}' = {}",
-                                name, story_format, matches
-                            );
-                            matches
-                        }
-                        None => {
-                            debug!("Story format has no name field");
-                            continue;
-                        }
-                    };
-
-                    if name_match && story_format_struct.version == version {
-                        debug!(
-                            "Found exact match: name='{}', version='{}'",
-                            story_format, version
-                        );
-                        return Ok(content);
-                    } else {
-                        found_formats.push((
-                            dir_name.to_string(),
-                            story_format_struct.name.clone(),
-                            story_format_struct.version.clone(),
-                        ));
-                    }
-                }
-                Err(e) => {
-                    warn!(
-                        "Failed to parse story format from {}: {}",
-                        format_file.display(),
-                        e
-                    );
-                    continue;
-                }
-            },
-            Err(e) => {
-                warn!(
-                    "Failed to load story format from {}: {}",
-                    format_file.display(),
-                    e
+/// List the story formats of a formats directory, broken ones included
+pub async fn list_format_dir(
+    format_dir: &Path,
+) -> Result<Vec<FormatEntry>, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(read_format_dirs(format_dir)
+        .await?
+        .into_iter()
+        .map(|(dir, format)| FormatEntry {
+            dir,
+            format: format.map(|(format, _)| format),
+        })
+        .collect())
+}
+
+/// Load every usable `format.js` below the formats directory
+///
+/// Directories without a `format.js`, formats that fail to parse and formats
+/// without a name are skipped with a warning.
+async fn scan_formats(
+    format_dir: &Path,
+) -> Result<Vec<(InstalledFormat, String)>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut formats = Vec::new();
+
+    for (dir, loaded) in read_format_dirs(format_dir).await? {
+        let dir_name = dir
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("")
+            .to_string();
+
+        match loaded {
+            Ok((format, content)) => {
+                debug!(
+                    "Format name: {:?}, version: {}",
+                    format.name, format.version
                 );
-                continue;
+                formats.push((
+                    InstalledFormat {
+                        dir_name,
+                        path: dir.join("format.js"),
+                        name: format.name.unwrap_or_default(),
+                        version: format.version,
+                    },
+                    content,
+                ));
             }
+            Err(e) => warn!("Skipping story format directory '{}': {}", dir_name, e),
+        }
+    }
+
+    Ok(formats)
+}
+
+/// Copy a story format directory into a formats directory
+///
+/// The format is validated first and installed as `<name>-<version>`. An
+/// existing installation of the same version is only replaced with `force`.
+pub async fn install_format(
+    source: &Path,
+    format_dir: &Path,
+    force: bool,
+) -> Result<InstalledFormat, Box<dyn std::error::Error + Send + Sync>> {
+    let (format, _) = load_format_dir(source)
+        .await
+        .map_err(|e| format!("Invalid story format in {}: {}", source.display(), e))?;
+    let name = format.name.unwrap_or_default();
+    let dir_name = format!("{}-{}", name.to_lowercase(), format.version);
+    let target = format_dir.join(&dir_name);
+
+    if target.exists() {
+        if !force {
+            return Err(format!(
+                "{} {} is already installed in {}",
+                name,
+                format.version,
+                target.display()
+            )
+            .into());
         }
+        tokio::fs::remove_dir_all(&target).await?;
     }
 
+    copy_dir(source, &target).await?;
     debug!(
-        "Found {} entries in format directory: {}",
-        entry_count,
-        format_dir.display()
+        "Installed story format {} {} to {}",
+        name,
+        format.version,
+        target.display()
     );
 
-    if !found_formats.is_empty() {
-        debug!("Available formats found:");
-        for (dir, name, ver) in &found_formats {
+    Ok(InstalledFormat {
+        dir_name,
+        path: target.join("format.js"),
+        name,
+        version: format.version,
+    })
+}
+
+/// Delete an installed story format, the first match on the search path
+pub async fn remove_format(
+    search_path: &FormatSearchPath,
+    name: &str,
+    version: &str,
+) -> Result<InstalledFormat, Box<dyn std::error::Error + Send + Sync>> {
+    let format = installed_formats(search_path)
+        .await?
+        .into_iter()
+        .find(|format| format.name.eq_ignore_ascii_case(name) && format.version == version)
+        .ok_or_else(|| format!("Story format '{name}' version '{version}' is not installed"))?;
+
+    let dir = format
+        .path
+        .parent()
+        .ok_or("Story format has no directory")?;
+    tokio::fs::remove_dir_all(dir).await?;
+    debug!(
+        "Removed story format {} {} from {}",
+        format.name,
+        format.version,
+        dir.display()
+    );
+
+    Ok(format)
+}
+
+async fn copy_dir(source: &Path, target: &Path) -> std::io::Result<()> {
+    tokio::fs::create_dir_all(target).await?;
+    let mut entries = tokio::fs::read_dir(source).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let target = target.join(entry.file_name());
+        if path.is_dir() {
+            Box::pin(copy_dir(&path, &target)).await?;
+        } else {
+            tokio::fs::copy(&path, &target).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse a story format version, missing minor and patch numbers count as 0
+pub fn parse_version(version: &str) -> Option<Version> {
+    let version = version.trim().trim_start_matches('v');
+    if let Ok(parsed) = Version::parse(version) {
+        return Some(parsed);
+    }
+
+    // Accept "2" and "2.36", optionally followed by a pre-release or build
+    let split = version.find(['-', '+']).unwrap_or(version.len());
+    let (core, suffix) = version.split_at(split);
+    let parts = core.split('.').count();
+    if parts >= 3 {
+        return None;
+    }
+    let padded = format!("{}{}{}", core, ".0".repeat(3 - parts), suffix);
+    Version::parse(&padded).ok()
+}
+
+/// Why a story format version was selected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionMatch {
+    /// The installed version equals the requested one
+    Exact,
+    /// The highest installed version of the same major at or above the requested one
+    Compatible,
+}
+
+/// Pick the installed format to use for a requested name and version
+///
+/// Follows the Twine 2 rule: among formats with the same name and major
+/// version, the highest one at or above the requested version wins.
+/// Pre-releases are only considered when the request itself is a pre-release
+/// of the same major.minor.patch. Versions that are not semver only match
+/// exactly.
+pub fn resolve_format<'a>(
+    installed: &'a [InstalledFormat],
+    name: &str,
+    version: &str,
+) -> Option<(&'a InstalledFormat, VersionMatch)> {
+    let same_name = || {
+        installed
+            .iter()
+            .filter(move |format| format.name.eq_ignore_ascii_case(name))
+    };
+
+    let Some(requested) = parse_version(version) else {
+        return same_name()
+            .find(|format| format.version == version)
+            .map(|format| (format, VersionMatch::Exact));
+    };
+
+    let selected = same_name()
+        .filter_map(|format| Some((format, parse_version(&format.version)?)))
+        .filter(|(_, candidate)| {
+            candidate.major == requested.major
+                && *candidate >= requested
+                && (candidate.pre.is_empty()
+                    || (!requested.pre.is_empty()
+                        && (candidate.major, candidate.minor, candidate.patch)
+                            == (requested.major, requested.minor, requested.patch)))
+        })
+        .max_by(|(_, a), (_, b)| a.cmp(b))?;
+
+    let kind = if selected.1 == requested {
+        VersionMatch::Exact
+    } else {
+        VersionMatch::Compatible
+    };
+    Some((selected.0, kind))
+}
+
+/// Find and load story format from the search path
+/// Returns the format.js content as a String
+pub async fn find_and_load_format(
+    search_path: &FormatSearchPath,
+    story_format: &str,
+    version: &str,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let (_, source) = find_format(search_path, story_format, version).await?;
+    Ok(source)
+}
+
+/// Find and load story format from the search path
+/// Returns the path of format.js along with its content
+pub async fn find_format(
+    search_path: &FormatSearchPath,
+    story_format: &str,
+    version: &str,
+) -> Result<(PathBuf, String), Box<dyn std::error::Error + Send + Sync>> {
+    debug!(
+        "Searching for story format '{}' version '{}' in: {:?}",
+        story_format,
+        version,
+        search_path.dirs()
+    );
+
+    let mut found_formats = Vec::new();
+    for format_dir in search_path.dirs() {
+        if !format_dir.is_dir() {
             debug!(
-                "  Directory: '{}' -> Name: {:?}, Version: '{}'",
-                dir, name, ver
+                "Skipping missing format directory: {}",
+                format_dir.display()
             );
+            continue;
+        }
+
+        // A `name-version` directory is taken without parsing every format
+        let expected_dir_name = format!("{}-{}", story_format.to_lowercase(), version);
+        let target_dir = format_dir.join(&expected_dir_name);
+        if target_dir.is_dir() {
+            let format_file = target_dir.join("format.js");
+            if format_file.exists() {
+                info!(
+                    "Using story format {} {} from '{}': exact match",
+                    story_format,
+                    version,
+                    format_file.display()
+                );
+                let source = load_format_from_path(&format_file).await?;
+                return Ok((format_file, source));
+            }
+            warn!(
+                "Directory '{}' exists but missing format.js file",
+                expected_dir_name
+            );
+        }
+
+        let (installed, contents): (Vec<_>, Vec<_>) =
+            scan_formats(format_dir).await?.into_iter().unzip();
+
+        if let Some((format, kind)) = resolve_format(&installed, story_format, version) {
+            match kind {
+                VersionMatch::Exact => info!(
+                    "Using story format {} {} from '{}': exact match",
+                    format.name,
+                    format.version,
+                    format.path.display()
+                ),
+                VersionMatch::Compatible => info!(
+                    "Using story format {} {} from '{}': highest installed {}.x at or above requested {}",
+                    format.name,
+                    format.version,
+                    format.path.display(),
+                    parse_version(&format.version).map_or(0, |v| v.major),
+                    version
+                ),
+            }
+            let index = installed
+                .iter()
+                .position(|candidate| candidate.path == format.path)
+                .expect("resolved format is installed");
+            return Ok((format.path.clone(), contents[index].clone()));
+        }
+
+        found_formats.extend(installed.iter().map(|format| {
+            format!(
+                "{} {} ({})",
+                format.name,
+                format.version,
+                format.path.display()
+            )
+        }));
+    }
+
+    if !found_formats.is_empty() {
+        debug!("Available formats found:");
+        for format in &found_formats {
+            debug!("  {}", format);
         }
     }
 
+    let searched: Vec<_> = search_path
+        .dirs()
+        .iter()
+        .map(|dir| dir.display().to_string())
+        .collect();
     Err(format!(
-        "Story format '{story_format}' version '{version}' not found. Available formats: {found_formats:?}"
+        "Story format '{story_format}' version '{version}' not found in {searched:?}. Available formats: {found_formats:?}"
     )
     .into())
 }
@@ -9,7 +9,7 @@ pub use filters::{FileFilter, SupportFileFilter};
 
 use crate::api::InputSource;
 use std::path::{Path, PathBuf};
-use tweers_core::util::file::{get_media_passage_type, is_support_file};
+use tweers_core::util::file::{get_media_passage_type, get_mime_type, is_support_file};
 
 /// Loaded sources ready for core
 pub struct LoadedSources {
@@ -75,7 +75,7 @@ async fn load_file(
         texts.push((name, text));
     } else {
         // Binary file (images, etc.)
-        let mime_type = get_mime_type(ext);
+        let mime_type = get_mime_type(ext).map(str::to_string);
         bytes.push((name, content, mime_type));
     }
 
@@ -98,25 +98,6 @@ async fn load_url(
     Ok(())
 }
 
-/// Get MIME type from file extension
-fn get_mime_type(ext: &str) -> Option<String> {
-    let mime = match ext.to_lowercase().as_str() {
-        "png" => "image/png",
-        "jpg" | "jpeg" => "image/jpeg",
-        "gif" => "image/gif",
-        "webp" => "image/webp",
-        "svg" => "image/svg+xml",
-        "mp3" => "audio/mpeg",
-        "wav" => "audio/wav",
-        "ogg" => "audio/ogg",
-        "mp4" => "video/mp4",
-        "webm" => "video/webm",
-        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
-        _ => return None,
-    };
-    Some(mime.to_string())
-}
-
 /// Collect files from sources with base64 support
 /// Refactored to use the new FileCollector with filter pattern
 pub async fn collect_files_with_base64(
@@ -145,3 +126,12 @@ pub fn is_support_file_with_base64(path: &Path, base64: bool) -> bool {
 
     false
 }
+
+/// `path` made absolute against the working directory, the way file watch
+/// events report paths
+///
+/// Unlike `canonicalize`, this does not need the path to exist, so removed
+/// files still compare equal to the paths they were collected as.
+pub fn absolute_path(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
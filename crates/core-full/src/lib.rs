@@ -1,9 +1,12 @@
 pub mod api;
 pub mod commands;
+pub mod config;
 pub mod context;
 pub mod format;
 pub mod io;
 pub mod pipeline;
+pub mod scaffold;
+pub mod serve;
 pub mod watch;
 
 // Re-export commonly used types
@@ -11,4 +11,4 @@ pub use tweers_core::pipeline::{PipeMap, PipeNode, Pipeline};
 pub use nodes::*;
 
 // Re-export registration function
-pub use register::register_nodes;
+pub use register::{default_registry, register_nodes, DEFAULT_BUILD_NODES};
@@ -1,21 +1,30 @@
 // Basic pipeline nodes for I/O operations
 
-use crate::commands::BuildContext;
-use crate::commands::CONTEXT;
+use crate::commands::{FileInfo, CONTEXT};
+use crate::context::ContentHash;
 use crate::io::collect_files_with_base64;
 use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
 use indexmap::IndexMap;
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 use tracing::{debug, info, warn};
+use tweers_core::core::conflict::PassageMerger;
 use tweers_core::core::file::{
     detect_file_type, inject_tweers_paths, parse_bytes_content, parse_text_content, FileType,
+    TWEERS_PATHS_PASSAGE,
 };
 use tweers_core::core::output::HtmlOutputHandler;
 use tweers_core::core::story::{Passage, StoryData};
 use tweers_core::error::{Result, TweersError};
 use tweers_core::pipeline::{PipeMap, PipeNode};
 use tweers_core::util::file::{get_media_passage_type, get_mime_type_prefix};
+use tweers_core::util::sort::compare_paths;
 
 /// File collector node - collect source files to be processed
 pub struct FileCollectorNode;
@@ -27,7 +36,11 @@ impl PipeNode for FileCollectorNode {
     }
 
     fn input(&self) -> Vec<String> {
-        vec!["sources".to_string(), "base64?".to_string()]
+        vec![
+            "sources".to_string(),
+            "base64?".to_string(),
+            "is_rebuild?".to_string(),
+        ]
     }
 
     fn output(&self) -> Vec<String> {
@@ -88,21 +101,33 @@ impl PipeNode for FileChangeDetectorNode {
             .get_typed(tweers_core::pipeline::FILES)
             .ok_or_else(|| TweersError::missing_input("files"))?;
 
-        let context = data
+        let mut context = data
             .get_typed(CONTEXT)
-            .ok_or_else(|| TweersError::missing_input("context"))?;
+            .ok_or_else(|| TweersError::missing_input("context"))?
+            .clone();
 
         let mut modified_files = Vec::new();
 
         for file_path in files {
-            if context.is_file_modified(file_path)? {
-                debug!("File modified: {:?}", file_path);
-                modified_files.push(file_path.clone());
+            if !context.is_file_modified(file_path)? {
+                continue;
+            }
+
+            // Files whose content was parsed before are taken from the disk
+            // cache and need no parsing
+            if let Some(cached) = context.load_cached(file_path) {
+                debug!("File unchanged since cached: {:?}", file_path);
+                context.update_cache(file_path.clone(), cached.passages, cached.story_data)?;
+                continue;
             }
+
+            debug!("File modified: {:?}", file_path);
+            modified_files.push(file_path.clone());
         }
 
         debug!("Found {} modified files", modified_files.len());
         data.insert_typed(tweers_core::pipeline::MODIFIED_FILES, modified_files);
+        data.insert_typed(CONTEXT, context);
         Ok(data)
     }
 }
@@ -117,7 +142,11 @@ impl PipeNode for FileParserNode {
     }
 
     fn input(&self) -> Vec<String> {
-        vec!["modified_files".to_string(), "context".to_string()]
+        vec![
+            "modified_files".to_string(),
+            "context".to_string(),
+            "is_rebuild?".to_string(),
+        ]
     }
 
     fn output(&self) -> Vec<String> {
@@ -137,20 +166,46 @@ impl PipeNode for FileParserNode {
             .ok_or_else(|| TweersError::missing_input("context"))?
             .clone();
 
-        let mut parsed_files = Vec::new();
-
-        for file_path in modified_files {
+        // Files are parsed concurrently, at most `parse_workers()` at a time
+        let workers = Arc::new(Semaphore::new(parse_workers()));
+        let mut tasks = JoinSet::new();
+        for file_path in modified_files.iter().cloned() {
             if !is_rebuild {
                 info!("Parsing file: {:?}", file_path);
             } else {
                 debug!("Parsing file: {:?}", file_path);
             }
 
-            let (passages, story_data) = self.parse_file(file_path, &context).await?;
+            let workers = workers.clone();
+            let base64 = context.base64;
+            tasks.spawn(async move {
+                let _permit = workers.acquire_owned().await;
+                let parsed = Self::parse_file(&file_path, base64).await;
+                (file_path, parsed)
+            });
+        }
+
+        let mut results = Vec::with_capacity(modified_files.len());
+        while let Some(joined) = tasks.join_next().await {
+            results.push(
+                joined
+                    .map_err(|e| TweersError::other(format!("File parser task failed: {}", e)))?,
+            );
+        }
+        // Tasks finish in any order, sort so that passages and the reported
+        // error do not depend on timing
+        results.sort_by(|(a, _), (b, _)| compare_paths(a, b));
+
+        let mut parsed_files = Vec::new();
+        for (file_path, parsed) in results {
+            let (passages, story_data, hash) = parsed?;
 
+            if let Some(hash) = hash {
+                context.store_cached(&file_path, &hash, &passages, &story_data);
+            }
             context.update_cache(file_path.clone(), passages.clone(), story_data.clone())?;
 
-            parsed_files.push((file_path.clone(), passages, story_data));
+            parsed_files.push((file_path, passages, story_data));
         }
 
         data.insert_typed(tweers_core::pipeline::PARSED_DATA, parsed_files);
@@ -159,12 +214,16 @@ impl PipeNode for FileParserNode {
     }
 }
 
+/// Passages and StoryData parsed from a file, with the hash of the content
+/// they were parsed from when the file was read
+type ParsedFile = (
+    IndexMap<String, Passage>,
+    Option<StoryData>,
+    Option<ContentHash>,
+);
+
 impl FileParserNode {
-    async fn parse_file(
-        &self,
-        file_path: &PathBuf,
-        context: &BuildContext,
-    ) -> tweers_core::error::Result<(IndexMap<String, Passage>, Option<StoryData>)> {
+    async fn parse_file(file_path: &Path, base64: bool) -> tweers_core::error::Result<ParsedFile> {
         let file_type = file_path
             .extension()
             .and_then(|e| e.to_str())
@@ -174,53 +233,63 @@ impl FileParserNode {
         match file_type {
             FileType::JavaScript | FileType::Css | FileType::Twee | FileType::Unknown => {
                 // Handle media files specially when base64 is enabled
-                if context.base64 {
+                if base64 {
                     if let Some(ext) = file_path.extension().and_then(|e| e.to_str()) {
                         if let Some(media_type) = get_media_passage_type(ext) {
-                            return self.parse_media_file(file_path, ext, media_type).await;
+                            return Self::parse_media_file(file_path, ext, media_type).await;
                         }
                     }
                 }
 
                 // Read as text and use shared logic
-                let content = tokio::fs::read_to_string(file_path).await?;
+                let bytes = tokio::fs::read(file_path).await?;
+                let hash = ContentHash::of(&bytes);
+                let content = String::from_utf8(bytes)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
                 let filename = file_path.to_string_lossy();
-                let parsed = parse_text_content(&filename, &content).map_err(|e| {
-                    TweersError::parse(format!("Failed to parse {}: {}", file_path.display(), e))
-                })?;
-                Ok((parsed.passages, parsed.story_data))
+                let parsed = parse_text_content(&filename, &content)
+                    .map_err(|e| TweersError::parse_failure(&file_path.display().to_string(), e))?;
+                Ok((parsed.passages, parsed.story_data, Some(hash)))
             }
             FileType::Excel => {
                 // Read as bytes and use shared logic
                 let bytes = tokio::fs::read(file_path).await?;
-                let filename = file_path.to_string_lossy();
-                let parsed = parse_bytes_content(&filename, &bytes).map_err(|e| {
-                    TweersError::parse(format!("Failed to parse {}: {}", file_path.display(), e))
-                })?;
-                Ok((parsed.passages, parsed.story_data))
+                let hash = ContentHash::of(&bytes);
+                let filename = file_path.to_string_lossy().to_string();
+                let parsed = blocking(move || parse_bytes_content(&filename, &bytes))
+                    .await?
+                    .map_err(|e| {
+                        TweersError::parse(format!(
+                            "Failed to parse {}: {}",
+                            file_path.display(),
+                            e
+                        ))
+                    })?;
+                Ok((parsed.passages, parsed.story_data, Some(hash)))
             }
             FileType::Media => {
-                if context.base64 {
+                if base64 {
                     if let Some(ext) = file_path.extension().and_then(|e| e.to_str()) {
                         if let Some(media_type) = get_media_passage_type(ext) {
-                            return self.parse_media_file(file_path, ext, media_type).await;
+                            return Self::parse_media_file(file_path, ext, media_type).await;
                         }
                     }
                 }
                 // Non-base64 media files - return empty
-                Ok((IndexMap::new(), None))
+                Ok((IndexMap::new(), None, None))
             }
         }
     }
 
     async fn parse_media_file(
-        &self,
-        file_path: &PathBuf,
+        file_path: &Path,
         ext: &str,
         media_type: &str,
-    ) -> tweers_core::error::Result<(IndexMap<String, Passage>, Option<StoryData>)> {
+    ) -> tweers_core::error::Result<ParsedFile> {
         let binary_content = tokio::fs::read(file_path).await?;
-        let base64_content = general_purpose::STANDARD.encode(&binary_content);
+        let hash = ContentHash::of(&binary_content);
+        let base64_content =
+            blocking(move || general_purpose::STANDARD.encode(&binary_content)).await?;
         let mime_prefix = get_mime_type_prefix(ext).unwrap_or("");
         let full_content = format!("{mime_prefix}{base64_content}");
         let passage_name = normalize_media_path(&file_path.to_string_lossy());
@@ -234,16 +303,141 @@ impl FileParserNode {
             content: full_content,
             source_file: Some(file_path.to_string_lossy().to_string()),
             source_line: Some(1),
+            spans: None,
         };
         passages.insert(passage_name, passage);
-        Ok((passages, None))
+        Ok((passages, None, Some(hash)))
     }
 }
 
+/// Upper bound of files parsed at the same time
+fn parse_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(NonZeroUsize::get)
+        .unwrap_or(4)
+}
+
+/// Run CPU-bound work on a blocking thread, keeping the runtime responsive
+async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| TweersError::other(format!("Blocking task failed: {}", e)))
+}
+
 fn normalize_media_path(path: &str) -> String {
     path.replace('\\', "/")
 }
 
+/// Passages of the last aggregation, patched in place when a rebuild only
+/// changes passage content
+///
+/// Merging copies every passage out of the file cache. When the same files
+/// are aggregated again, the merge had no conflicts, no passage is defined
+/// by more than one file and every changed file still defines the same
+/// passages, none of them StoryData or StoryTitle, only the passages of the
+/// changed files are copied.
+#[derive(Debug, Default)]
+pub struct AggregateCache {
+    /// Aggregated files in order, with the modification time they were
+    /// aggregated at and the passages they define
+    files: Vec<(PathBuf, SystemTime, Vec<String>)>,
+    passages: Arc<IndexMap<String, Passage>>,
+    story_data: Option<StoryData>,
+    patchable: bool,
+}
+
+impl AggregateCache {
+    fn new(
+        files: &[PathBuf],
+        file_cache: &IndexMap<PathBuf, FileInfo>,
+        passages: IndexMap<String, Passage>,
+        story_data: Option<StoryData>,
+        conflicts: bool,
+    ) -> Self {
+        let files: Vec<(PathBuf, SystemTime, Vec<String>)> = files
+            .iter()
+            .filter_map(|path| file_cache.get(path))
+            .map(|info| {
+                let names = info.passages.keys().cloned().collect();
+                (info.path.clone(), info.modified, names)
+            })
+            .collect();
+
+        // Patching a duplicate could replace the passage that won the merge
+        let mut defined = HashSet::new();
+        let duplicates = files
+            .iter()
+            .flat_map(|(_, _, names)| names)
+            .filter(|name| !matches!(name.as_str(), "StoryData" | "StoryTitle"))
+            .any(|name| !defined.insert(name));
+        let patchable = !conflicts && !duplicates;
+
+        Self {
+            files,
+            passages: Arc::new(passages),
+            story_data,
+            patchable,
+        }
+    }
+
+    /// Copy the passages of changed files in, if nothing else changed
+    fn patch(&mut self, files: &[PathBuf], file_cache: &IndexMap<PathBuf, FileInfo>) -> bool {
+        if !self.patchable {
+            return false;
+        }
+
+        let cached: Vec<&FileInfo> = files
+            .iter()
+            .filter_map(|path| file_cache.get(path))
+            .collect();
+        if cached.len() != self.files.len() {
+            return false;
+        }
+
+        let mut changed = Vec::new();
+        for (info, (path, modified, names)) in cached.iter().zip(&self.files) {
+            if info.path != *path {
+                return false;
+            }
+            if info.modified == *modified {
+                continue;
+            }
+
+            let same_passages = info.story_data.is_none()
+                && info.passages.keys().eq(names.iter())
+                && !names
+                    .iter()
+                    .any(|name| name == "StoryData" || name == "StoryTitle");
+            if !same_passages {
+                return false;
+            }
+            changed.push(*info);
+        }
+
+        let passages = Arc::make_mut(&mut self.passages);
+        // Added again after patching
+        passages.shift_remove(TWEERS_PATHS_PASSAGE);
+        for info in changed {
+            for (name, passage) in &info.passages {
+                match passages.get_mut(name) {
+                    Some(slot) => *slot = passage.clone(),
+                    None => {
+                        passages.insert(name.clone(), passage.clone());
+                    }
+                }
+            }
+        }
+        for (entry, info) in self.files.iter_mut().zip(&cached) {
+            entry.1 = info.modified;
+        }
+        true
+    }
+}
+
 /// Data aggregator node - aggregate all parsed data
 pub struct DataAggregatorNode;
 
@@ -282,24 +476,37 @@ impl PipeNode for DataAggregatorNode {
             .get_typed(CONTEXT)
             .ok_or_else(|| TweersError::missing_input("context"))?;
 
-        let mut all_passages = IndexMap::new();
-        let mut story_data = None;
+        let mut aggregate = context.aggregate_cache.lock().unwrap();
+        if aggregate.patch(files, &context.file_cache) {
+            debug!("Patched changed passages into the last aggregation");
+        } else {
+            let mut merger = PassageMerger::new(context.conflict_policy);
 
-        for file_path in files {
-            if let Some(file_info) = context.file_cache.get(file_path) {
-                for (name, passage) in &file_info.passages {
-                    if all_passages.contains_key(name) {
-                        warn!("Duplicate passage name: {}", name);
-                    }
-                    all_passages.insert(name.clone(), passage.clone());
+            for file_path in files {
+                if let Some(file_info) = context.file_cache.get(file_path) {
+                    merger.add_source(&file_info.passages, file_info.story_data.as_ref());
                 }
+            }
 
-                if story_data.is_none() && file_info.story_data.is_some() {
-                    story_data = file_info.story_data.clone();
-                }
+            let merged = merger
+                .finish()
+                .map_err(|e| TweersError::conflict(e.to_string()))?;
+            for conflict in &merged.conflicts {
+                warn!("{}", conflict);
             }
+
+            *aggregate = AggregateCache::new(
+                files,
+                &context.file_cache,
+                merged.passages,
+                merged.story_data,
+                !merged.conflicts.is_empty(),
+            );
         }
 
+        let mut story_data = aggregate.story_data.clone();
+        let all_passages = Arc::make_mut(&mut aggregate.passages);
+
         if let Some(mut data_obj) = story_data.clone() {
             if let Some(title_passage) = all_passages.get("StoryTitle") {
                 data_obj.name = Some(title_passage.content.clone());
@@ -330,16 +537,70 @@ impl PipeNode for DataAggregatorNode {
             return Err(TweersError::other("No passages found in any files"));
         }
 
-        inject_tweers_paths(&mut all_passages, source_roots, files);
+        inject_tweers_paths(all_passages, source_roots, files);
 
         debug!("Total passages aggregated: {}", all_passages.len());
 
+        let all_passages = aggregate.passages.clone();
+        drop(aggregate);
         data.insert_typed(tweers_core::pipeline::ALL_PASSAGES, all_passages);
         data.insert_typed(tweers_core::pipeline::STORY_DATA, story_data);
         Ok(data)
     }
 }
 
+/// Story format loader node - switch to the format named in the aggregated StoryData
+///
+/// The format is loaded before the pipeline runs from the first StoryData
+/// found. When the aggregated one names another format, for example after
+/// StoryData moved to another file, that format replaces it before HTML
+/// generation.
+pub struct StoryFormatLoaderNode;
+
+#[async_trait]
+impl PipeNode for StoryFormatLoaderNode {
+    fn name(&self) -> String {
+        "StoryFormatLoader".to_string()
+    }
+
+    fn input(&self) -> Vec<String> {
+        vec!["story_data".to_string(), "context".to_string()]
+    }
+
+    fn output(&self) -> Vec<String> {
+        vec!["context".to_string()]
+    }
+
+    async fn process(&self, mut data: PipeMap) -> Result<PipeMap> {
+        let story_data = data
+            .get_typed(tweers_core::pipeline::STORY_DATA)
+            .ok_or_else(|| TweersError::missing_input("story_data"))?;
+        let context = data
+            .get_typed(CONTEXT)
+            .ok_or_else(|| TweersError::missing_input("context"))?;
+
+        let Some(story_data) = story_data.as_ref().filter(|story_data| {
+            story_data.format != context.format_name
+                || story_data.format_version != context.format_version
+        }) else {
+            return Ok(data);
+        };
+
+        info!(
+            "Story format changed to {} {}, reloading",
+            story_data.format, story_data.format_version
+        );
+        let mut context = context.clone();
+        context
+            .load_format(&story_data.format, &story_data.format_version)
+            .await
+            .map_err(|e| TweersError::format(e.to_string()))?;
+
+        data.insert_typed(CONTEXT, context);
+        Ok(data)
+    }
+}
+
 /// HTML generator node - generate final HTML output
 pub struct HtmlGeneratorNode;
 
@@ -354,11 +615,12 @@ impl PipeNode for HtmlGeneratorNode {
             "all_passages".to_string(),
             "story_data".to_string(),
             "context".to_string(),
+            "is_rebuild?".to_string(),
         ]
     }
 
     fn output(&self) -> Vec<String> {
-        vec!["html_content".to_string(), "context".to_string()]
+        vec!["html_content".to_string()]
     }
 
     async fn process(&self, mut data: PipeMap) -> Result<PipeMap> {
@@ -378,12 +640,12 @@ impl PipeNode for HtmlGeneratorNode {
 
         let context = data
             .get_typed(CONTEXT)
-            .ok_or_else(|| TweersError::missing_input("context"))?
-            .clone();
+            .ok_or_else(|| TweersError::missing_input("context"))?;
 
         debug!("Generating HTML for {} passages", all_passages.len());
 
-        let html_content = HtmlOutputHandler::generate_html(
+        // Passages unchanged since the last build are not rendered again
+        let html_content = HtmlOutputHandler::generate_html_cached(
             all_passages,
             story_data,
             context
@@ -391,6 +653,7 @@ impl PipeNode for HtmlGeneratorNode {
                 .as_ref()
                 .ok_or_else(|| TweersError::invalid_config("Story format not loaded"))?,
             context.is_debug,
+            &mut context.html_cache.lock().unwrap(),
         )?;
 
         if !is_rebuild {
@@ -398,7 +661,6 @@ impl PipeNode for HtmlGeneratorNode {
         }
 
         data.insert_typed(tweers_core::pipeline::HTML_CONTENT, html_content);
-        data.insert_typed(CONTEXT, context);
         Ok(data)
     }
 }
@@ -413,7 +675,11 @@ impl PipeNode for FileWriterNode {
     }
 
     fn input(&self) -> Vec<String> {
-        vec!["html_content".to_string(), "output_path".to_string()]
+        vec![
+            "html_content".to_string(),
+            "output_path".to_string(),
+            "is_rebuild?".to_string(),
+        ]
     }
 
     fn output(&self) -> Vec<String> {
@@ -433,6 +699,11 @@ impl PipeNode for FileWriterNode {
             .get_typed(tweers_core::pipeline::OUTPUT_PATH)
             .ok_or_else(|| TweersError::missing_input("output_path"))?;
 
+        if let Some(parent) = output_path.parent() {
+            if !parent.as_os_str().is_empty() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+        }
         tokio::fs::write(output_path, html_content).await?;
 
         if !is_rebuild {
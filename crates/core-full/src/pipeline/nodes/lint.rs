@@ -0,0 +1,68 @@
+// Lint node for the check command
+
+use crate::commands::CONTEXT;
+use async_trait::async_trait;
+use tracing::debug;
+use tweers_core::analysis::lint;
+use tweers_core::core::conflict::ConflictPolicy;
+use tweers_core::error::{Result, TweersError};
+use tweers_core::pipeline::{PipeMap, PipeNode};
+
+/// Lint node - run lint rules on aggregated data instead of generating HTML
+///
+/// Duplicates and StoryData conflicts are reported as `policy` would treat
+/// them in a build.
+#[derive(Default)]
+pub struct LintNode {
+    policy: ConflictPolicy,
+}
+
+impl LintNode {
+    pub fn new(policy: ConflictPolicy) -> Self {
+        Self { policy }
+    }
+}
+
+#[async_trait]
+impl PipeNode for LintNode {
+    fn name(&self) -> String {
+        "Lint".to_string()
+    }
+
+    fn input(&self) -> Vec<String> {
+        vec![
+            "files".to_string(),
+            "story_data".to_string(),
+            "context".to_string(),
+        ]
+    }
+
+    fn output(&self) -> Vec<String> {
+        vec!["diagnostics".to_string()]
+    }
+
+    async fn process(&self, mut data: PipeMap) -> Result<PipeMap> {
+        let files = data
+            .get_typed(tweers_core::pipeline::FILES)
+            .ok_or_else(|| TweersError::missing_input("files"))?;
+
+        let story_data = data
+            .get_typed(tweers_core::pipeline::STORY_DATA)
+            .ok_or_else(|| TweersError::missing_input("story_data"))?;
+
+        let context = data
+            .get_typed(CONTEXT)
+            .ok_or_else(|| TweersError::missing_input("context"))?;
+
+        let sources = files
+            .iter()
+            .filter_map(|file| context.file_cache.get(file))
+            .map(|file_info| (&file_info.passages, file_info.story_data.as_ref()));
+
+        let diagnostics = lint(sources, story_data.as_ref(), self.policy);
+
+        debug!("Lint produced {} diagnostics", diagnostics.len());
+        data.insert_typed(tweers_core::pipeline::DIAGNOSTICS, diagnostics);
+        Ok(data)
+    }
+}
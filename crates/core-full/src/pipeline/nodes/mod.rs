@@ -1,5 +1,7 @@
 // Pipeline nodes for I/O operations
 
 pub mod basic;
+pub mod lint;
 
 pub use basic::*;
+pub use lint::*;
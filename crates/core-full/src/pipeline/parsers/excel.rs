@@ -35,6 +35,7 @@ impl FileParser for ExcelFileParser {
                 content: result.javascript,
                 source_file: Some(file_path.to_string_lossy().to_string()),
                 source_line: Some(1),
+                spans: None,
             };
             passages.insert(js_passage.name.clone(), js_passage);
         }
@@ -49,6 +50,7 @@ impl FileParser for ExcelFileParser {
                 content: result.html,
                 source_file: Some(file_path.to_string_lossy().to_string()),
                 source_line: Some(1),
+                spans: None,
             };
             passages.insert(html_passage.name.clone(), html_passage);
         }
@@ -54,6 +54,7 @@ impl FileParser for MediaFileParser {
             content: full_content,
             source_file: Some(file_path.to_string_lossy().to_string()),
             source_line: Some(1),
+            spans: None,
         };
         passages.insert(passage_name, passage);
         Ok((passages, None))
@@ -42,6 +42,7 @@ impl FileParser for TextFileParser {
             content,
             source_file: Some(file_path.to_string_lossy().to_string()),
             source_line: Some(1),
+            spans: None,
         };
         passages.insert(passage_name, passage);
         Ok((passages, None))
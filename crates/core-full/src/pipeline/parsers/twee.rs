@@ -20,9 +20,8 @@ impl FileParser for TweeFileParser {
         file_path: &Path,
     ) -> Result<(IndexMap<String, Passage>, Option<StoryData>)> {
         let content = tokio::fs::read_to_string(file_path).await?;
-        let parsed = parse_text_content(&file_path.to_string_lossy(), &content).map_err(|e| {
-            TweersError::parse(format!("Failed to parse {}: {}", file_path.display(), e))
-        })?;
+        let parsed = parse_text_content(&file_path.to_string_lossy(), &content)
+            .map_err(|e| TweersError::parse_failure(&file_path.display().to_string(), e))?;
 
         Ok((parsed.passages, parsed.story_data))
     }
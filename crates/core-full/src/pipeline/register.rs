@@ -3,8 +3,20 @@ use tweers_core::pipeline::NodeRegistry;
 
 use super::nodes::basic::{
     DataAggregatorNode, FileChangeDetectorNode, FileCollectorNode, FileParserNode, FileWriterNode,
-    HtmlGeneratorNode,
+    HtmlGeneratorNode, StoryFormatLoaderNode,
 };
+use super::nodes::lint::LintNode;
+
+/// Node names of the build pipeline, in execution order
+pub const DEFAULT_BUILD_NODES: &[&str] = &[
+    "file_collector",
+    "file_change_detector",
+    "file_parser",
+    "data_aggregator",
+    "story_format_loader",
+    "html_generator",
+    "file_writer",
+];
 
 /// Register all core-full pipeline nodes
 pub fn register_nodes(registry: &mut NodeRegistry) {
@@ -16,5 +28,14 @@ pub fn register_nodes(registry: &mut NodeRegistry) {
 
     // Data processing nodes
     registry.register("data_aggregator", || Box::new(DataAggregatorNode));
+    registry.register("story_format_loader", || Box::new(StoryFormatLoaderNode));
     registry.register("html_generator", || Box::new(HtmlGeneratorNode));
+    registry.register("lint", || Box::new(LintNode::default()));
+}
+
+/// Registry holding the core-full pipeline nodes
+pub fn default_registry() -> NodeRegistry {
+    let mut registry = NodeRegistry::new();
+    register_nodes(&mut registry);
+    registry
 }
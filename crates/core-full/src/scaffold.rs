@@ -0,0 +1,162 @@
+// New project scaffolding
+
+use crate::format::{parse_version, InstalledFormat};
+use indexmap::IndexMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use tweers_core::core::html::TwineHtmlParser;
+use tweers_core::core::story::{Passage, StoryData};
+
+/// Starter content for a new project
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectTemplate {
+    SugarCube,
+    Harlowe,
+}
+
+impl ProjectTemplate {
+    /// Name of the story format the template is written for
+    pub fn format_name(&self) -> &'static str {
+        match self {
+            Self::SugarCube => "SugarCube",
+            Self::Harlowe => "Harlowe",
+        }
+    }
+
+    fn start_passage(&self) -> &'static str {
+        match self {
+            Self::SugarCube => "Your story begins here.\n\n<<set $visited to true>>",
+            Self::Harlowe => "Your story begins here.\n\n(set: $visited to true)",
+        }
+    }
+
+    fn stylesheet(&self) -> &'static str {
+        match self {
+            Self::SugarCube => {
+                "/* Story stylesheet, see https://www.motoslave.net/sugarcube/2/docs/#css */\n"
+            }
+            Self::Harlowe => {
+                "/* Story stylesheet, see https://twine2.neocities.org/#type_style */\n"
+            }
+        }
+    }
+
+    fn script(&self) -> &'static str {
+        match self {
+            Self::SugarCube => {
+                "// Story JavaScript, see https://www.motoslave.net/sugarcube/2/docs/#config-api\n"
+            }
+            Self::Harlowe => "// Story JavaScript, runs once when the story starts\n",
+        }
+    }
+}
+
+impl FromStr for ProjectTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "sugarcube" => Ok(Self::SugarCube),
+            "harlowe" => Ok(Self::Harlowe),
+            _ => Err(format!(
+                "Unknown template '{s}' (expected sugarcube or harlowe)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ProjectTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SugarCube => write!(f, "sugarcube"),
+            Self::Harlowe => write!(f, "harlowe"),
+        }
+    }
+}
+
+/// Generate a Twine IFID, an uppercase version 4 UUID
+pub fn generate_ifid() -> String {
+    uuid::Uuid::new_v4().to_string().to_uppercase()
+}
+
+/// Pick the story format of a new project from the installed formats
+///
+/// A template asks for its own format, otherwise SugarCube is preferred and
+/// any other installed format is used as a fallback. The highest installed
+/// version of the chosen format wins.
+pub fn choose_format(
+    installed: &[InstalledFormat],
+    template: Option<ProjectTemplate>,
+) -> Result<&InstalledFormat, String> {
+    let name = match template {
+        Some(template) => template.format_name(),
+        None if installed.is_empty() => return Err("No story formats installed".to_string()),
+        None => installed
+            .iter()
+            .find(|format| format.name.eq_ignore_ascii_case("SugarCube"))
+            .unwrap_or(&installed[0])
+            .name
+            .as_str(),
+    };
+
+    installed
+        .iter()
+        .filter(|format| format.name.eq_ignore_ascii_case(name))
+        .max_by_key(|format| parse_version(&format.version))
+        .ok_or_else(|| format!("Story format '{name}' is not installed"))
+}
+
+/// Files of a new project, relative to the project root
+pub fn project_files(
+    title: &str,
+    ifid: &str,
+    format: &InstalledFormat,
+    template: Option<ProjectTemplate>,
+) -> Vec<(PathBuf, String)> {
+    // Every project gets a start passage, templates add format markup to it
+    let start = template.map_or("Your story begins here.", |template| {
+        template.start_passage()
+    });
+    let mut passages = IndexMap::new();
+    passages.insert(
+        "Start".to_string(),
+        Passage {
+            name: "Start".to_string(),
+            tags: None,
+            position: None,
+            size: None,
+            content: start.to_string(),
+            source_file: None,
+            source_line: None,
+            spans: None,
+        },
+    );
+
+    let story_data = StoryData {
+        name: Some(title.to_string()),
+        ifid: ifid.to_string(),
+        format: format.name.clone(),
+        format_version: format.version.clone(),
+        start: Some("Start".to_string()),
+        tag_colors: None,
+        zoom: None,
+    };
+
+    let mut files = vec![(
+        PathBuf::from("src").join("story.twee"),
+        TwineHtmlParser::to_twee(&passages, &story_data) + "\n",
+    )];
+    if let Some(template) = template {
+        files.push((
+            PathBuf::from("src").join("style.css"),
+            template.stylesheet().to_string(),
+        ));
+        files.push((
+            PathBuf::from("src").join("script.js"),
+            template.script().to_string(),
+        ));
+    }
+
+    files
+}
@@ -0,0 +1,485 @@
+// Local development server with live reload
+//
+// A minimal HTTP/1.1 server for `tweers serve`: it serves the built story and
+// its assets, and pushes build results to the page over Server-Sent Events.
+
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+use tweers_core::util::file::get_mime_type;
+
+/// Path of the event stream the live reload snippet listens to
+pub const EVENTS_PATH: &str = "/__tweers/events";
+
+/// Script added to served pages
+///
+/// Reloads the page after a successful rebuild and shows build errors over
+/// the page until the next successful one.
+pub const LIVE_RELOAD_SNIPPET: &str = r#"<script>
+(function () {
+  var overlay = null;
+  var events = new EventSource("/__tweers/events");
+  events.addEventListener("reload", function () {
+    location.reload();
+  });
+  events.addEventListener("build-error", function (event) {
+    if (!overlay) {
+      overlay = document.createElement("pre");
+      overlay.id = "tweers-error-overlay";
+      overlay.style.cssText = "position:fixed;inset:0;z-index:2147483647;margin:0;" +
+        "padding:2em;overflow:auto;background:rgba(24,0,0,0.92);color:#ff8a80;" +
+        "font:14px/1.5 monospace;white-space:pre-wrap";
+      overlay.onclick = function () { overlay.remove(); overlay = null; };
+      document.body.appendChild(overlay);
+    }
+    overlay.textContent = "Build failed\n\n" + JSON.parse(event.data);
+  });
+})();
+</script>"#;
+
+/// Page served until the first build succeeds
+const WAITING_PAGE: &str = "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>tweers serve</title></head>\n<body><p>Waiting for a successful build...</p></body></html>\n";
+
+/// Longest request head accepted, requests only carry a path
+const MAX_REQUEST_HEAD: usize = 16 * 1024;
+
+/// Add the live reload snippet before the closing body tag
+pub fn inject_live_reload(html: &str) -> String {
+    match html.rfind("</body>") {
+        Some(index) => format!(
+            "{}{}\n{}",
+            &html[..index],
+            LIVE_RELOAD_SNIPPET,
+            &html[index..]
+        ),
+        None => format!("{}\n{}", html, LIVE_RELOAD_SNIPPET),
+    }
+}
+
+/// Result of the last build, as pushed to the pages
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildStatus {
+    Succeeded,
+    Failed(String),
+}
+
+/// Broadcasts build results to every connected page
+#[derive(Clone)]
+pub struct LiveReload {
+    sender: broadcast::Sender<BuildStatus>,
+    last: Arc<Mutex<BuildStatus>>,
+}
+
+impl Default for LiveReload {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LiveReload {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(16);
+        Self {
+            sender,
+            last: Arc::new(Mutex::new(BuildStatus::Succeeded)),
+        }
+    }
+
+    /// Report the result of a build, reloading or showing the error on every page
+    pub fn build_finished<E: std::fmt::Display>(&self, result: &Result<(), E>) {
+        let status = match result {
+            Ok(()) => BuildStatus::Succeeded,
+            Err(e) => BuildStatus::Failed(e.to_string()),
+        };
+        *self.last.lock().unwrap() = status.clone();
+        // Nobody listening is fine, pages pick up the status when they connect
+        let _ = self.sender.send(status);
+    }
+
+    /// Result of the last build
+    pub fn status(&self) -> BuildStatus {
+        self.last.lock().unwrap().clone()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<BuildStatus> {
+        self.sender.subscribe()
+    }
+}
+
+/// HTTP server for the built story, its assets and the live reload events
+pub struct DevServer {
+    listener: TcpListener,
+    state: ServerState,
+}
+
+struct ServerState {
+    html: PathBuf,
+    /// URL prefixes and the directories served under them
+    dirs: Vec<(String, PathBuf)>,
+    live_reload: LiveReload,
+}
+
+impl DevServer {
+    /// Listen on `addr`, serving `html` at `/`
+    pub async fn bind(
+        addr: SocketAddr,
+        html: PathBuf,
+        live_reload: LiveReload,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        Ok(Self {
+            listener,
+            state: ServerState {
+                html,
+                dirs: Vec::new(),
+                live_reload,
+            },
+        })
+    }
+
+    /// Serve the files of `dir` under `/prefix/`, or at the root for an empty prefix
+    ///
+    /// Directories are searched in the order they were added.
+    pub fn with_dir(mut self, prefix: impl Into<String>, dir: PathBuf) -> Self {
+        let prefix = prefix.into().trim_matches('/').to_string();
+        self.state.dirs.push((prefix, dir));
+        self
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Accept connections until the task is dropped
+    pub async fn run(self) -> io::Result<()> {
+        let state = Arc::new(self.state);
+        loop {
+            let (stream, peer) = self.listener.accept().await?;
+            let state = state.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle_connection(stream, &state).await {
+                    debug!("Connection from {} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+}
+
+async fn handle_connection(stream: TcpStream, state: &ServerState) -> io::Result<()> {
+    let mut stream = BufReader::new(stream);
+
+    let mut request_line = String::new();
+    stream.read_line(&mut request_line).await?;
+    // Only the range is needed of the headers, but all have to be read
+    // before answering
+    let mut head_len = request_line.len();
+    let mut range = None;
+    loop {
+        let mut line = String::new();
+        let read = stream.read_line(&mut line).await?;
+        head_len += read;
+        if read == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("range") {
+                range = Some(value.trim().to_string());
+            }
+        }
+        if head_len > MAX_REQUEST_HEAD {
+            return respond(
+                stream.get_mut(),
+                "431 Request Header Fields Too Large",
+                "text/plain",
+                b"",
+                false,
+            )
+            .await;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return respond(
+            stream.get_mut(),
+            "400 Bad Request",
+            "text/plain",
+            b"Bad request",
+            true,
+        )
+        .await;
+    };
+    let with_body = method != "HEAD";
+    if method != "GET" && method != "HEAD" {
+        return respond(
+            stream.get_mut(),
+            "405 Method Not Allowed",
+            "text/plain",
+            b"Method not allowed",
+            true,
+        )
+        .await;
+    }
+
+    let path = target.split(['?', '#']).next().unwrap_or("/");
+    let Some(path) = percent_decode(path) else {
+        return respond(
+            stream.get_mut(),
+            "400 Bad Request",
+            "text/plain",
+            b"Bad request",
+            with_body,
+        )
+        .await;
+    };
+    debug!("{} {}", method, path);
+
+    if path == EVENTS_PATH {
+        return stream_events(stream.into_inner(), &state.live_reload).await;
+    }
+
+    if path == "/" || path == "/index.html" {
+        // The snippet is only added to the served page, the built file
+        // stays as it is deployed
+        let html = match tokio::fs::read(&state.html).await {
+            Ok(body) => String::from_utf8_lossy(&body).into_owned(),
+            Err(_) => WAITING_PAGE.to_string(),
+        };
+        let body = inject_live_reload(&html).into_bytes();
+        return respond(
+            stream.get_mut(),
+            "200 OK",
+            "text/html; charset=utf-8",
+            &body,
+            with_body,
+        )
+        .await;
+    }
+
+    match state.resolve(&path) {
+        Some(file) => match send_file(stream.get_mut(), &file, range.as_deref(), with_body).await {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                warn!("Failed to read {}: {}", file.display(), e);
+                respond(
+                    stream.get_mut(),
+                    "500 Internal Server Error",
+                    "text/plain",
+                    b"Failed to read file",
+                    with_body,
+                )
+                .await
+            }
+        },
+        None => {
+            respond(
+                stream.get_mut(),
+                "404 Not Found",
+                "text/plain",
+                b"Not found",
+                with_body,
+            )
+            .await
+        }
+    }
+}
+
+impl ServerState {
+    /// File of a served directory for a URL path
+    fn resolve(&self, path: &str) -> Option<PathBuf> {
+        let relative = Path::new(path.trim_start_matches('/'));
+        // Only plain names, so that no request can leave the served
+        // directories, and no hidden files such as `.git`
+        if !relative.components().all(|component| match component {
+            Component::Normal(name) => !name.to_string_lossy().starts_with('.'),
+            _ => false,
+        }) {
+            return None;
+        }
+
+        self.dirs.iter().find_map(|(prefix, dir)| {
+            let inner = if prefix.is_empty() {
+                relative
+            } else {
+                relative.strip_prefix(prefix).ok()?
+            };
+            let file = dir.join(inner);
+            file.is_file().then_some(file)
+        })
+    }
+}
+
+/// Send the current and every following build result as Server-Sent Events
+async fn stream_events(mut stream: TcpStream, live_reload: &LiveReload) -> io::Result<()> {
+    let mut events = live_reload.subscribe();
+
+    stream
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-store\r\nConnection: keep-alive\r\n\r\n",
+        )
+        .await?;
+    // Pages opened while the build is broken show the error right away
+    if let BuildStatus::Failed(message) = live_reload.status() {
+        stream
+            .write_all(event_message(&BuildStatus::Failed(message)).as_bytes())
+            .await?;
+    }
+    stream.flush().await?;
+
+    loop {
+        let status = match events.recv().await {
+            Ok(status) => status,
+            Err(broadcast::error::RecvError::Lagged(_)) => live_reload.status(),
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        stream.write_all(event_message(&status).as_bytes()).await?;
+        stream.flush().await?;
+    }
+}
+
+fn event_message(status: &BuildStatus) -> String {
+    match status {
+        BuildStatus::Succeeded => "event: reload\ndata: \n\n".to_string(),
+        BuildStatus::Failed(message) => {
+            // JSON keeps the message on one line, as event data must be
+            let data = serde_json::to_string(message).unwrap_or_else(|_| "\"\"".to_string());
+            format!("event: build-error\ndata: {}\n\n", data)
+        }
+    }
+}
+
+async fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+    with_body: bool,
+) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    if with_body {
+        stream.write_all(body).await?;
+    }
+    stream.flush().await
+}
+
+/// Decode `%XX` escapes of a URL path
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = path.get(i + 1..i + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+fn content_type(path: &Path) -> String {
+    let mime = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .and_then(get_mime_type)
+        .unwrap_or("application/octet-stream");
+    if mime.starts_with("text/") {
+        format!("{}; charset=utf-8", mime)
+    } else {
+        mime.to_string()
+    }
+}
+
+/// Byte range of a `Range` header within a file of `len` bytes
+///
+/// Only single ranges are supported, other forms are answered with the
+/// whole file as HTTP allows. `Err` means the range lies outside the file.
+fn parse_range(header: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header.strip_prefix("bytes=")?.trim();
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 {
+                return Some(Err(()));
+            }
+            (len.saturating_sub(suffix), len.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, len.saturating_sub(1)),
+        (start, end) => {
+            let (start, end): (u64, u64) = (start.parse().ok()?, end.parse().ok()?);
+            if end < start {
+                return None;
+            }
+            (start, end.min(len.saturating_sub(1)))
+        }
+    };
+    if start >= len {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end)))
+}
+
+/// Stream a file, or the requested range of it, without reading it whole
+///
+/// Browsers need ranges to seek in audio and video.
+async fn send_file(
+    stream: &mut TcpStream,
+    file: &Path,
+    range: Option<&str>,
+    with_body: bool,
+) -> io::Result<()> {
+    let mut source = tokio::fs::File::open(file).await?;
+    let len = source.metadata().await?.len();
+    let content_type = content_type(file);
+
+    let (status, start, end) = match range.and_then(|range| parse_range(range, len)) {
+        Some(Ok((start, end))) => ("206 Partial Content", start, end),
+        Some(Err(())) => {
+            let head = format!(
+                "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                len
+            );
+            stream.write_all(head.as_bytes()).await?;
+            return stream.flush().await;
+        }
+        None => ("200 OK", 0, len.saturating_sub(1)),
+    };
+    let body_len = if len == 0 { 0 } else { end - start + 1 };
+
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nCache-Control: no-store\r\nConnection: close\r\n",
+        status, content_type, body_len
+    );
+    if status.starts_with("206") {
+        head.push_str(&format!(
+            "Content-Range: bytes {}-{}/{}\r\n",
+            start, end, len
+        ));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes()).await?;
+
+    if with_body && body_len > 0 {
+        source.seek(io::SeekFrom::Start(start)).await?;
+        tokio::io::copy(&mut source.take(body_len), stream).await?;
+    }
+    stream.flush().await
+}
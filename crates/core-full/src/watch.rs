@@ -1,3 +1,103 @@
 // File watching module
+//
+// The watch loop itself is in commands.rs, this decides what a file system
+// event means for the next rebuild.
 
-// Watch functionality is implemented in commands.rs
+use crate::commands::BuildContext;
+use crate::io::{absolute_path, is_support_file_with_base64};
+use notify::event::ModifyKind;
+use notify::{Event, EventKind};
+use std::path::PathBuf;
+use tracing::debug;
+use tweers_core::pipeline::PipelineBuilder;
+
+/// Creates the pipeline for the next rebuild
+pub type ReloadPipeline =
+    Box<dyn FnMut() -> Result<PipelineBuilder, Box<dyn std::error::Error + Send + Sync>> + Send>;
+
+/// Directories outside the sources that a watched build depends on
+///
+/// When files in them change, the pipeline is created again before the
+/// rebuild, so that nodes pick up what they load from there, such as
+/// processing scripts.
+pub struct PipelineReload {
+    pub dirs: Vec<PathBuf>,
+    pub reload: ReloadPipeline,
+}
+
+impl PipelineReload {
+    pub fn new(
+        dirs: Vec<PathBuf>,
+        reload: impl FnMut() -> Result<PipelineBuilder, Box<dyn std::error::Error + Send + Sync>>
+            + Send
+            + 'static,
+    ) -> Self {
+        Self {
+            // Watch events carry absolute paths
+            dirs: dirs.iter().map(|dir| absolute_path(dir)).collect(),
+            reload: Box::new(reload),
+        }
+    }
+
+    /// Whether `event` changes one of the directories
+    ///
+    /// Reads and metadata changes are left out, loading the files for the
+    /// rebuild would trigger another one otherwise.
+    pub fn is_affected_by(&self, event: &Event) -> bool {
+        changes_files(event)
+            && event
+                .paths
+                .iter()
+                .any(|path| self.dirs.iter().any(|dir| path.starts_with(dir)))
+    }
+}
+
+/// Whether `event` changes the `format.js` the story format was loaded from
+///
+/// The story format is loaded again before the next rebuild then.
+pub fn is_format_change(event: &Event, context: &BuildContext) -> bool {
+    context
+        .format_path
+        .as_ref()
+        .is_some_and(|format| changes_files(event) && event.paths.contains(format))
+}
+
+fn changes_files(event: &Event) -> bool {
+    match event.kind {
+        EventKind::Modify(ModifyKind::Metadata(_)) => false,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => true,
+        _ => false,
+    }
+}
+
+/// Paths of a watch event that need a rebuild
+///
+/// Removed files, and the old names of renamed ones, are evicted from the
+/// file cache so their passages do not outlive them. New directories count
+/// as changes, since files created with them may not get events of their own.
+pub fn watch_changes(event: &Event, context: &mut BuildContext) -> Vec<PathBuf> {
+    let is_new = match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(_)) => true,
+        EventKind::Modify(_) | EventKind::Remove(_) => false,
+        _ => return Vec::new(),
+    };
+
+    let mut changes = Vec::new();
+    for path in &event.paths {
+        if path.is_dir() {
+            if is_new {
+                debug!("Directory added: {:?}", path);
+                changes.push(path.clone());
+            }
+        } else if !path.exists() {
+            let evicted = context.evict(path);
+            if evicted > 0 {
+                debug!("Removed {:?}, evicted {} cached files", path, evicted);
+                changes.push(path.clone());
+            }
+        } else if is_support_file_with_base64(path, context.base64) {
+            changes.push(path.clone());
+        }
+    }
+    changes
+}
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+use tweers_core::core::conflict::{DuplicatePolicy, StoryDataPolicy};
+use tweers_core_full::config::ProjectConfig;
+
+const CONFIG: &str = r#"
+sources = ["src", "/shared/story"]
+format-dirs = ["formats"]
+script-dirs = ["scripts"]
+cache = true
+duplicates = "last-wins"
+
+[build]
+output = "dist/index.html"
+base64 = true
+start-passage = "Intro"
+
+[pack]
+assets = ["assets"]
+fast-compression = true
+pipeline = ["archive_creator"]
+"#;
+
+#[test]
+fn test_parse_and_resolve_paths() {
+    let mut config = ProjectConfig::parse(CONFIG).unwrap();
+    config.root = PathBuf::from("/project");
+
+    assert_eq!(
+        config.sources(),
+        vec![
+            PathBuf::from("/project/src"),
+            PathBuf::from("/shared/story")
+        ]
+    );
+    assert_eq!(
+        config.format_dirs(),
+        vec![
+            PathBuf::from("/project/formats"),
+            PathBuf::from("/project/.storyformats")
+        ]
+    );
+    assert_eq!(
+        config
+            .format_search_path(vec![PathBuf::from("/cli")])
+            .dirs()[..3],
+        [
+            PathBuf::from("/cli"),
+            PathBuf::from("/project/formats"),
+            PathBuf::from("/project/.storyformats")
+        ]
+    );
+    assert_eq!(
+        config.build_output(),
+        Some(PathBuf::from("/project/dist/index.html"))
+    );
+    assert_eq!(config.pack_output(), None);
+    assert_eq!(
+        config.cache_dir(),
+        Some(PathBuf::from("/project/.tweers/cache"))
+    );
+    assert!(config.pack.fast_compression);
+    assert_eq!(config.build.pipeline, None);
+    assert_eq!(
+        config.pack.pipeline,
+        Some(vec!["archive_creator".to_string()])
+    );
+
+    assert!(config.build.base64 && !config.build.debug);
+    assert_eq!(config.build.start_passage.as_deref(), Some("Intro"));
+    assert_eq!(config.assets_dirs(), vec![PathBuf::from("/project/assets")]);
+}
+
+#[test]
+fn test_conflict_policy_flags_override_config() {
+    let config = ProjectConfig::parse(CONFIG).unwrap();
+
+    let policy = config.conflict_policy(None, None);
+    assert_eq!(policy.duplicates, DuplicatePolicy::LastWins);
+    assert_eq!(policy.story_data, StoryDataPolicy::First);
+
+    let policy = config.conflict_policy(Some(DuplicatePolicy::Error), Some(StoryDataPolicy::Last));
+    assert_eq!(policy.duplicates, DuplicatePolicy::Error);
+    assert_eq!(policy.story_data, StoryDataPolicy::Last);
+
+    assert!(ProjectConfig::parse("duplicates = \"ignore\"\n").is_err());
+}
+
+#[test]
+fn test_unknown_keys_are_rejected() {
+    let err = ProjectConfig::parse("[build]\noutptu = \"index.html\"\n").unwrap_err();
+    assert!(err.to_string().contains("outptu"));
+}
+
+#[test]
+fn test_discover_walks_up() {
+    let root = std::env::temp_dir().join(format!("tweers_config_{}", std::process::id()));
+    let nested = root.join("src").join("chapter");
+    std::fs::create_dir_all(&nested).unwrap();
+    std::fs::write(root.join("tweers.toml"), "sources = [\"src\"]\n").unwrap();
+
+    let config = ProjectConfig::discover(&nested).unwrap().unwrap();
+    assert_eq!(config.root, root);
+    assert_eq!(config.sources(), vec![root.join("src")]);
+
+    std::fs::write(root.join("tweers.toml"), "sources = \"src\"\n").unwrap();
+    assert!(ProjectConfig::discover(&nested).is_err());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,124 @@
+// Parse results reused from the on-disk cache
+use std::path::PathBuf;
+use std::sync::Arc;
+use tweers_core::core::conflict::ConflictPolicy;
+use tweers_core::pipeline::TimingsObserver;
+use tweers_core_full::commands::{build_command_with_nodes, build_pipeline};
+use tweers_core_full::context::{ContentHash, DiskCache};
+use tweers_core_full::format::FormatSearchPath;
+use tweers_core_full::pipeline::{default_registry, DEFAULT_BUILD_NODES};
+
+fn workspace_dir() -> PathBuf {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    manifest_dir
+        .parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+/// Build `source` with a fresh context, returning the number of parsed files
+async fn build(source: PathBuf, output: PathBuf, cache_dir: PathBuf) -> Option<usize> {
+    let timings = Arc::new(TimingsObserver::new());
+    let pipeline = build_pipeline(
+        Arc::new(default_registry()),
+        DEFAULT_BUILD_NODES.iter().copied(),
+    )
+    .unwrap()
+    .with_observer(timings.clone());
+
+    build_command_with_nodes(
+        vec![source],
+        output,
+        false,
+        false,
+        false,
+        None,
+        ConflictPolicy::default(),
+        FormatSearchPath::default().with_dirs([workspace_dir().join("test/story-format")]),
+        Some(cache_dir),
+        pipeline,
+        None,
+    )
+    .await
+    .expect("build failed");
+
+    timings.report().files_changed
+}
+
+#[tokio::test]
+async fn test_cold_build_reuses_cached_parse_results() {
+    let root = workspace_dir().join("target/test-disk-cache");
+    let cache_dir = root.join(".tweers/cache");
+    let sources = root.join("src");
+    let chapter = sources.join("chapter.twee");
+    let output = root.join("dist/index.html");
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(&sources).unwrap();
+    std::fs::copy(
+        workspace_dir().join("test/story/A.twee"),
+        sources.join("00-story.twee"),
+    )
+    .unwrap();
+    std::fs::write(&chapter, ":: Chapter\nFirst draft\n").unwrap();
+
+    // The StoryData file sorts first and is parsed while loading the story
+    // format, the pipeline only parses the chapter
+    let parsed = build(sources.clone(), output.clone(), cache_dir.clone()).await;
+    assert_eq!(parsed, Some(1));
+    assert_eq!(std::fs::read_dir(&cache_dir).unwrap().count(), 2);
+    let first = std::fs::read_to_string(&output).unwrap();
+
+    // Rewriting the same content only changes the mtime
+    for file in std::fs::read_dir(&sources).unwrap() {
+        let path = file.unwrap().path();
+        std::fs::write(&path, std::fs::read(&path).unwrap()).unwrap();
+    }
+    let parsed = build(sources.clone(), output.clone(), cache_dir.clone()).await;
+    assert_eq!(parsed, Some(0));
+    assert_eq!(std::fs::read_to_string(&output).unwrap(), first);
+
+    std::fs::write(&chapter, ":: Chapter\nSecond draft\n").unwrap();
+    let parsed = build(sources.clone(), output.clone(), cache_dir.clone()).await;
+    assert_eq!(parsed, Some(1));
+    assert!(std::fs::read_to_string(&output)
+        .unwrap()
+        .contains("Second draft"));
+
+    // Only the latest content of each file is kept
+    for file_dir in std::fs::read_dir(&cache_dir).unwrap() {
+        let entries = std::fs::read_dir(file_dir.unwrap().path()).unwrap();
+        assert_eq!(entries.count(), 1);
+    }
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn test_entries_depend_on_content_and_base64() {
+    let root = workspace_dir().join("target/test-disk-cache-entries");
+    let source = root.join("story.twee");
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(&source, ":: Start\nHello\n").unwrap();
+
+    let cache = DiskCache::new(root.join("cache"));
+    assert!(cache.load(&source, false).is_none());
+
+    let hash = ContentHash::of(b":: Start\nHello\n");
+    cache.store(&source, false, &hash, &Default::default(), &None);
+    assert!(cache.load(&source, false).is_some());
+    assert!(cache.load(&source, true).is_none());
+
+    std::fs::write(&source, ":: Start\nChanged\n").unwrap();
+    assert!(cache.load(&source, false).is_none());
+
+    // A result stored after the file changed again is filed under the
+    // content it was parsed from
+    cache.store(&source, false, &hash, &Default::default(), &None);
+    assert!(cache.load(&source, false).is_none());
+    std::fs::write(&source, ":: Start\nHello\n").unwrap();
+    assert!(cache.load(&source, false).is_some());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
@@ -0,0 +1,194 @@
+use std::path::{Path, PathBuf};
+use tweers_core_full::format::{
+    find_and_load_format, install_format, installed_formats, list_format_dir, parse_version,
+    remove_format, resolve_format, FormatSearchPath, InstalledFormat, VersionMatch,
+};
+
+fn installed(formats: &[(&str, &str)]) -> Vec<InstalledFormat> {
+    formats
+        .iter()
+        .map(|(name, version)| InstalledFormat {
+            dir_name: format!("{}-{}", name.to_lowercase(), version),
+            path: PathBuf::from(format!("{}-{}/format.js", name.to_lowercase(), version)),
+            name: name.to_string(),
+            version: version.to_string(),
+        })
+        .collect()
+}
+
+fn resolve<'a>(formats: &'a [InstalledFormat], name: &str, version: &str) -> Option<&'a str> {
+    resolve_format(formats, name, version).map(|(format, _)| format.version.as_str())
+}
+
+#[test]
+fn test_parse_version_pads_missing_parts() {
+    assert_eq!(parse_version("2.36").unwrap().to_string(), "2.36.0");
+    assert_eq!(parse_version("3").unwrap().to_string(), "3.0.0");
+    assert_eq!(
+        parse_version("2.37-beta.1").unwrap().to_string(),
+        "2.37.0-beta.1"
+    );
+    assert!(parse_version("latest").is_none());
+}
+
+#[test]
+fn test_resolve_highest_compatible_version() {
+    let formats = installed(&[
+        ("SugarCube", "2.36.1"),
+        ("SugarCube", "2.37.3"),
+        ("SugarCube", "1.0.35"),
+        ("SugarCube", "3.0.0"),
+        ("Harlowe", "3.3.9"),
+    ]);
+
+    let (format, kind) = resolve_format(&formats, "sugarcube", "2.36.1").unwrap();
+    assert_eq!(format.version, "2.37.3");
+    assert_eq!(kind, VersionMatch::Compatible);
+
+    assert_eq!(resolve(&formats, "SugarCube", "2.20"), Some("2.37.3"));
+    assert_eq!(resolve(&formats, "SugarCube", "1.0.0"), Some("1.0.35"));
+    assert_eq!(resolve(&formats, "SugarCube", "2.38.0"), None);
+    assert_eq!(resolve(&formats, "Chapbook", "1.0.0"), None);
+
+    let (_, kind) = resolve_format(&formats, "Harlowe", "3.3.9").unwrap();
+    assert_eq!(kind, VersionMatch::Exact);
+}
+
+#[test]
+fn test_resolve_pre_releases() {
+    let formats = installed(&[
+        ("SugarCube", "2.37.0"),
+        ("SugarCube", "2.38.0-beta.2"),
+        ("SugarCube", "2.38.0-beta.1"),
+    ]);
+
+    // Pre-releases are never picked for a release request
+    assert_eq!(resolve(&formats, "SugarCube", "2.36.1"), Some("2.37.0"));
+    // A pre-release request accepts later pre-releases of the same version
+    assert_eq!(
+        resolve(&formats, "SugarCube", "2.38.0-beta.1"),
+        Some("2.38.0-beta.2")
+    );
+    assert_eq!(resolve(&formats, "SugarCube", "2.38.0-beta.3"), None);
+}
+
+#[test]
+fn test_resolve_non_semver_matches_exactly() {
+    let formats = installed(&[("Custom", "nightly"), ("Custom", "1.0.0")]);
+
+    assert_eq!(resolve(&formats, "Custom", "nightly"), Some("nightly"));
+    assert_eq!(resolve(&formats, "Custom", "weekly"), None);
+}
+
+fn write_format(dir: &Path, name: &str, version: &str) {
+    let format_dir = dir.join(format!("{}-{}", name.to_lowercase(), version));
+    std::fs::create_dir_all(&format_dir).unwrap();
+    std::fs::write(
+        format_dir.join("format.js"),
+        format!(
+            r#"window.storyFormat({{"name":"{name}","version":"{version}","source":"{dir}"}});"#,
+            dir = dir.file_name().unwrap().to_string_lossy()
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_search_path_order() {
+    let project = PathBuf::from("/story");
+    let search_path = FormatSearchPath::discover(&project).with_dirs([PathBuf::from("/cli")]);
+
+    assert_eq!(search_path.dirs()[0], PathBuf::from("/cli"));
+    assert_eq!(search_path.dirs()[1], project.join(".storyformats"));
+}
+
+#[tokio::test]
+async fn test_first_directory_with_a_match_wins() {
+    let root = std::env::temp_dir().join(format!("tweers_format_path_{}", std::process::id()));
+    let local = root.join("local");
+    let shared = root.join("shared");
+    write_format(&local, "SugarCube", "2.36.1");
+    write_format(&shared, "SugarCube", "2.37.3");
+    write_format(&shared, "Harlowe", "3.3.9");
+
+    let search_path = FormatSearchPath::default().with_dirs([
+        root.join("missing"),
+        local.clone(),
+        shared.clone(),
+    ]);
+
+    let source = find_and_load_format(&search_path, "SugarCube", "2.30.0")
+        .await
+        .unwrap();
+    assert!(source.contains(r#""source":"local""#));
+    let source = find_and_load_format(&search_path, "Harlowe", "3.0.0")
+        .await
+        .unwrap();
+    assert!(source.contains(r#""source":"shared""#));
+    assert!(find_and_load_format(&search_path, "SugarCube", "2.38.0")
+        .await
+        .is_err());
+
+    let installed = installed_formats(&search_path).await.unwrap();
+    assert_eq!(installed.len(), 3);
+    assert_eq!(installed[0].version, "2.36.1");
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_exact_directory_is_used_without_scanning() {
+    let root = std::env::temp_dir().join(format!("tweers_format_exact_{}", std::process::id()));
+    let exact = root.join("sugarcube-2.37.3");
+    std::fs::create_dir_all(&exact).unwrap();
+    // Not a format the scan would recognise, only its directory name matches
+    std::fs::write(exact.join("format.js"), "/* exact */").unwrap();
+    write_format(&root, "SugarCube", "2.37.4");
+
+    let search_path = FormatSearchPath::default().with_dirs([root.clone()]);
+    let source = find_and_load_format(&search_path, "SugarCube", "2.37.3")
+        .await
+        .unwrap();
+    assert_eq!(source, "/* exact */");
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_install_list_and_remove() {
+    let root = std::env::temp_dir().join(format!("tweers_format_install_{}", std::process::id()));
+    let source = root.join("download");
+    let target = root.join("formats");
+    write_format(&source, "Paperthin", "1.0.0");
+    std::fs::create_dir_all(target.join("broken")).unwrap();
+
+    let installed = install_format(&source.join("paperthin-1.0.0"), &target, false)
+        .await
+        .unwrap();
+    assert_eq!(installed.dir_name, "paperthin-1.0.0");
+    assert!(
+        install_format(&source.join("paperthin-1.0.0"), &target, false)
+            .await
+            .is_err()
+    );
+    assert!(install_format(&source, &target, false).await.is_err());
+
+    let entries = list_format_dir(&target).await.unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(
+        entries[0].format.as_ref().unwrap_err(),
+        "missing format.js file"
+    );
+    assert_eq!(
+        entries[1].format.as_ref().unwrap().name.as_deref(),
+        Some("Paperthin")
+    );
+
+    let search_path = FormatSearchPath::default().with_dirs([target.clone()]);
+    remove_format(&search_path, "paperthin", "1.0.0")
+        .await
+        .unwrap();
+    assert!(!target.join("paperthin-1.0.0").exists());
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
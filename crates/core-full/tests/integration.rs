@@ -1,6 +1,7 @@
 // End-to-end integration test for build pipeline
 use std::fs;
 use std::path::PathBuf;
+use tweers_core::core::conflict::{ConflictPolicy, DuplicatePolicy};
 use tweers_core::pipeline::{BASE64, SOURCES};
 use tweers_core_full::commands::BuildContext;
 use tweers_core_full::commands::CONTEXT;
@@ -160,3 +161,304 @@ Next
             && item["name"] == "theme.css"
     }));
 }
+
+#[tokio::test]
+async fn test_check_command_reports_diagnostics() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let workspace_dir = manifest_dir.parent().unwrap().parent().unwrap();
+    let temp_dir = workspace_dir.join("target/test-check");
+
+    if temp_dir.exists() {
+        fs::remove_dir_all(&temp_dir).expect("failed to clean test dir");
+    }
+    fs::create_dir_all(&temp_dir).expect("failed to create test dir");
+
+    fs::write(
+        temp_dir.join("main.twee"),
+        r#":: StoryData
+{
+    "ifid": "D674C58C-DEFA-4F70-B7A2-27742230C0FC",
+    "format": "SugarCube",
+    "format-version": "2.37.3"
+}
+
+:: StoryTitle
+Test
+
+:: Start
+Hello
+
+:: Menu [widgte]
+<<widget "x">><</widget>>
+"#,
+    )
+    .expect("failed to write main.twee");
+
+    fs::write(temp_dir.join("extra.twee"), ":: Start\nAgain\n")
+        .expect("failed to write extra.twee");
+
+    // Duplicates that would fail the build are still reported one by one
+    let policy = ConflictPolicy {
+        duplicates: DuplicatePolicy::Error,
+        ..ConflictPolicy::default()
+    };
+    let diagnostics =
+        tweers_core_full::commands::check_command(vec![temp_dir.clone()], None, policy)
+            .await
+            .expect("check failed");
+
+    let codes: Vec<_> = diagnostics.iter().map(|d| d.code).collect();
+    assert!(codes.contains(&"unknown-special-tag"), "{codes:?}");
+    assert!(diagnostics
+        .iter()
+        .any(|d| d.code == "duplicate-passage" && d.is_error()));
+
+    let policy = ConflictPolicy {
+        duplicates: DuplicatePolicy::LastWins,
+        ..ConflictPolicy::default()
+    };
+    let diagnostics =
+        tweers_core_full::commands::check_command(vec![temp_dir.clone()], None, policy)
+            .await
+            .expect("check failed");
+    assert!(!diagnostics.iter().any(|d| d.code == "duplicate-passage"));
+}
+
+#[tokio::test]
+async fn test_data_aggregator_patches_changed_passages() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let workspace_dir = manifest_dir.parent().unwrap().parent().unwrap();
+    let temp_dir = workspace_dir.join("target/test-aggregate-patch");
+
+    if temp_dir.exists() {
+        fs::remove_dir_all(&temp_dir).expect("failed to clean test dir");
+    }
+    fs::create_dir_all(&temp_dir).expect("failed to create test dir");
+
+    let main = temp_dir.join("main.twee");
+    let scene = temp_dir.join("scene.tw");
+    fs::write(
+        &main,
+        r#":: StoryData
+{
+    "ifid": "12345678-1234-1234-1234-123456789012",
+    "format": "SugarCube",
+    "format-version": "2.37.3"
+}
+
+:: StoryTitle
+Test
+
+:: Start
+Hello
+"#,
+    )
+    .expect("failed to write main.twee");
+    fs::write(&scene, ":: Scene\nWorld\n").expect("failed to write scene.tw");
+
+    let files = vec![main.clone(), scene.clone()];
+    let pipeline = Pipeline::new("test_aggregate_patch")
+        .add_node(Box::new(FileParserNode))
+        .expect("Failed to add FileParserNode")
+        .add_node(Box::new(DataAggregatorNode))
+        .expect("Failed to add DataAggregatorNode");
+
+    let run = |context: BuildContext, modified: Vec<PathBuf>| {
+        let mut input = PipeMap::new();
+        input.insert_typed(tweers_core::pipeline::MODIFIED_FILES, modified);
+        input.insert_typed(tweers_core::pipeline::FILES, files.clone());
+        input.insert_typed(SOURCES, vec![temp_dir.clone()]);
+        input.insert_typed(CONTEXT, context);
+        pipeline.execute(input)
+    };
+
+    let first = run(BuildContext::new(false, false, None), files.clone())
+        .await
+        .expect("first build failed");
+    let context = first.get_typed(CONTEXT).expect("missing context").clone();
+    drop(first);
+
+    fs::write(&scene, ":: Scene\nChanged\n").expect("failed to rewrite scene.tw");
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(5);
+    fs::File::options()
+        .write(true)
+        .open(&scene)
+        .and_then(|file| file.set_modified(later))
+        .expect("failed to touch scene.tw");
+
+    let patched = run(context, vec![scene.clone()])
+        .await
+        .expect("patched build failed");
+    let fresh = run(BuildContext::new(false, false, None), files.clone())
+        .await
+        .expect("fresh build failed");
+
+    let patched = patched
+        .get_typed(tweers_core::pipeline::ALL_PASSAGES)
+        .expect("missing all_passages");
+    let fresh = fresh
+        .get_typed(tweers_core::pipeline::ALL_PASSAGES)
+        .expect("missing all_passages");
+
+    assert_eq!(patched["Scene"].content, "Changed");
+    assert!(patched
+        .iter()
+        .map(|(name, passage)| (name, &passage.content))
+        .eq(fresh.iter().map(|(name, passage)| (name, &passage.content))));
+}
+
+#[tokio::test]
+async fn test_data_aggregator_keeps_duplicate_winner_on_rebuild() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let workspace_dir = manifest_dir.parent().unwrap().parent().unwrap();
+    let temp_dir = workspace_dir.join("target/test-aggregate-duplicate");
+
+    if temp_dir.exists() {
+        fs::remove_dir_all(&temp_dir).expect("failed to clean test dir");
+    }
+    fs::create_dir_all(&temp_dir).expect("failed to create test dir");
+
+    let main = temp_dir.join("main.twee");
+    let a = temp_dir.join("a.twee");
+    let b = temp_dir.join("b.twee");
+    fs::write(
+        &main,
+        r#":: StoryData
+{
+    "ifid": "12345678-1234-1234-1234-123456789012",
+    "format": "SugarCube",
+    "format-version": "2.37.3"
+}
+
+:: StoryTitle
+Test
+
+:: Start
+[[Ending]]
+"#,
+    )
+    .expect("failed to write main.twee");
+    fs::write(&a, ":: Ending\nFrom a\n").expect("failed to write a.twee");
+    fs::write(&b, ":: Ending\nFrom b\n").expect("failed to write b.twee");
+
+    let files = vec![main.clone(), a.clone(), b.clone()];
+    let pipeline = Pipeline::new("test_aggregate_duplicate")
+        .add_node(Box::new(FileParserNode))
+        .expect("Failed to add FileParserNode")
+        .add_node(Box::new(DataAggregatorNode))
+        .expect("Failed to add DataAggregatorNode");
+
+    let run = |context: BuildContext, modified: Vec<PathBuf>| {
+        let mut input = PipeMap::new();
+        input.insert_typed(tweers_core::pipeline::MODIFIED_FILES, modified);
+        input.insert_typed(tweers_core::pipeline::FILES, files.clone());
+        input.insert_typed(SOURCES, vec![temp_dir.clone()]);
+        input.insert_typed(CONTEXT, context);
+        pipeline.execute(input)
+    };
+
+    let first = run(BuildContext::new(false, false, None), files.clone())
+        .await
+        .expect("first build failed");
+    let context = first.get_typed(CONTEXT).expect("missing context").clone();
+    drop(first);
+
+    // Touch only the file whose Ending lost the merge
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(5);
+    fs::File::options()
+        .write(true)
+        .open(&a)
+        .and_then(|file| file.set_modified(later))
+        .expect("failed to touch a.twee");
+
+    let rebuilt = run(context, vec![a.clone()]).await.expect("rebuild failed");
+    let rebuilt = rebuilt
+        .get_typed(tweers_core::pipeline::ALL_PASSAGES)
+        .expect("missing all_passages");
+
+    assert_eq!(rebuilt["Ending"].content, "From b");
+}
+
+#[tokio::test]
+async fn test_story_format_loader_switches_to_aggregated_format() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let workspace_dir = manifest_dir.parent().unwrap().parent().unwrap();
+    let story = workspace_dir.join("test/story/A.twee");
+    let search_path = tweers_core_full::format::FormatSearchPath::default()
+        .with_dirs([workspace_dir.join("test/story-format")]);
+
+    let mut context = BuildContext::new(false, false, None);
+    context.format_search_path = search_path;
+    context
+        .load_format("Harlowe", "3.3.9")
+        .await
+        .expect("failed to load Harlowe");
+
+    let pipeline = Pipeline::new("test_story_format_loader")
+        .add_node(Box::new(FileParserNode))
+        .expect("Failed to add FileParserNode")
+        .add_node(Box::new(DataAggregatorNode))
+        .expect("Failed to add DataAggregatorNode")
+        .add_node(Box::new(StoryFormatLoaderNode))
+        .expect("Failed to add StoryFormatLoaderNode");
+
+    let mut input = PipeMap::new();
+    input.insert_typed(tweers_core::pipeline::MODIFIED_FILES, vec![story.clone()]);
+    input.insert_typed(tweers_core::pipeline::FILES, vec![story]);
+    input.insert_typed(SOURCES, vec![workspace_dir.join("test/story")]);
+    input.insert_typed(CONTEXT, context);
+
+    let output = pipeline.execute(input).await.expect("pipeline failed");
+    let context = output.get_typed(CONTEXT).expect("missing context");
+
+    assert_eq!(context.format_name, "SugarCube");
+    assert_eq!(context.format_version, "2.37.3");
+    assert_eq!(
+        context
+            .story_format
+            .as_ref()
+            .and_then(|format| format.name.as_deref()),
+        Some("SugarCube")
+    );
+}
+
+#[tokio::test]
+async fn test_check_command_locates_each_parse_error() {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let workspace_dir = manifest_dir.parent().unwrap().parent().unwrap();
+    let temp_dir = workspace_dir.join("target/test-check-parse-errors");
+
+    if temp_dir.exists() {
+        fs::remove_dir_all(&temp_dir).expect("failed to clean test dir");
+    }
+    fs::create_dir_all(&temp_dir).expect("failed to create test dir");
+
+    let story = temp_dir.join("story.twee");
+    fs::write(
+        &story,
+        ":: Start [tag\nHello\n\n::\nLost body\n\n:: Next\nWorld\n",
+    )
+    .expect("failed to write story.twee");
+
+    let diagnostics = tweers_core_full::commands::check_command(
+        vec![temp_dir.clone()],
+        None,
+        ConflictPolicy::default(),
+    )
+    .await
+    .expect("check failed");
+
+    let locations: Vec<_> = diagnostics
+        .iter()
+        .map(|d| (d.code, d.file.as_deref(), d.line))
+        .collect();
+    let file = story.to_string_lossy();
+    assert_eq!(
+        locations,
+        vec![
+            ("parse-error", Some(file.as_ref()), Some(1)),
+            ("parse-error", Some(file.as_ref()), Some(4)),
+        ]
+    );
+    assert!(diagnostics.iter().all(|d| d.column.is_some()));
+}
@@ -28,3 +28,46 @@ async fn test_twee_parser() {
     );
     assert!(story_data.is_some());
 }
+
+#[tokio::test]
+async fn test_parser_node_keeps_path_order() {
+    use tweers_core::pipeline::{PipeMap, PipeNode, MODIFIED_FILES, PARSED_DATA};
+    use tweers_core::util::sort::compare_paths;
+    use tweers_core_full::commands::{BuildContext, CONTEXT};
+    use tweers_core_full::pipeline::FileParserNode;
+
+    let dir = std::env::temp_dir().join(format!("tweers_parser_order_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut files = Vec::new();
+    for i in 0..40 {
+        let path = dir.join(format!("chapter{}.twee", i));
+        std::fs::write(&path, format!(":: Passage {}\nText {}\n", i, i)).unwrap();
+        files.push(path);
+    }
+    let image = dir.join("cover.png");
+    std::fs::write(&image, [0x89, b'P', b'N', b'G']).unwrap();
+    files.push(image);
+
+    let mut data = PipeMap::new();
+    data.insert_typed(MODIFIED_FILES, files.clone());
+    data.insert_typed(CONTEXT, BuildContext::new(false, true, None));
+    let result = FileParserNode.process(data).await.unwrap();
+
+    let parsed: Vec<PathBuf> = result
+        .get_typed(PARSED_DATA)
+        .unwrap()
+        .iter()
+        .map(|(path, _, _)| path.clone())
+        .collect();
+    files.sort_by(|a, b| compare_paths(a, b));
+    assert_eq!(parsed, files);
+
+    let context = result.get_typed(CONTEXT).unwrap();
+    let cover = &context.file_cache[&dir.join("cover.png")];
+    let passage = cover.passages.values().next().unwrap();
+    assert_eq!(passage.tags.as_deref(), Some("image"));
+    assert!(passage.content.ends_with("iVBORw=="));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
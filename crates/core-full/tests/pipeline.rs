@@ -0,0 +1,348 @@
+// Build pipelines assembled from registered node names
+use async_trait::async_trait;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tweers_core::core::conflict::ConflictPolicy;
+use tweers_core::core::story::Passage;
+use tweers_core::error::{Result, TweersError};
+use tweers_core::pipeline::{
+    ExecutionMode, PipeMap, PipeNode, Pipeline, TimingsObserver, ALL_PASSAGES,
+};
+use tweers_core_full::commands::{build_command_with_nodes, build_pipeline};
+use tweers_core_full::format::FormatSearchPath;
+use tweers_core_full::pipeline::{default_registry, DEFAULT_BUILD_NODES};
+
+/// Adds a passage after aggregation, before the HTML is generated
+struct InjectPassageNode;
+
+#[async_trait]
+impl PipeNode for InjectPassageNode {
+    fn name(&self) -> String {
+        "InjectPassage".to_string()
+    }
+
+    fn input(&self) -> Vec<String> {
+        vec!["all_passages".to_string()]
+    }
+
+    fn output(&self) -> Vec<String> {
+        vec!["all_passages".to_string()]
+    }
+
+    async fn process(&self, mut data: PipeMap) -> Result<PipeMap> {
+        let mut passages = data
+            .get_typed(ALL_PASSAGES)
+            .cloned()
+            .ok_or_else(|| TweersError::missing_input("all_passages"))?;
+        Arc::make_mut(&mut passages).insert(
+            "Injected".to_string(),
+            Passage {
+                name: "Injected".to_string(),
+                tags: None,
+                position: None,
+                size: None,
+                content: "Added by a custom node".to_string(),
+                source_file: None,
+                source_line: None,
+                spans: None,
+            },
+        );
+        data.insert_typed(ALL_PASSAGES, passages);
+        Ok(data)
+    }
+}
+
+fn workspace_dir() -> PathBuf {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    manifest_dir
+        .parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+#[tokio::test]
+async fn test_custom_node_between_aggregator_and_generator() {
+    let workspace_dir = workspace_dir();
+    let output = workspace_dir.join("target/test-custom-pipeline/index.html");
+
+    let mut registry = default_registry();
+    registry.register("inject_passage", || Box::new(InjectPassageNode));
+
+    let mut nodes: Vec<&str> = DEFAULT_BUILD_NODES.to_vec();
+    let position = nodes
+        .iter()
+        .position(|&name| name == "html_generator")
+        .unwrap();
+    nodes.insert(position, "inject_passage");
+    let pipeline = build_pipeline(Arc::new(registry), nodes).unwrap();
+
+    build_command_with_nodes(
+        vec![workspace_dir.join("test/story/A.twee")],
+        output.clone(),
+        false,
+        false,
+        false,
+        None,
+        ConflictPolicy::default(),
+        FormatSearchPath::default().with_dirs([workspace_dir.join("test/story-format")]),
+        None,
+        pipeline,
+        None,
+    )
+    .await
+    .expect("build failed");
+
+    let html = std::fs::read_to_string(&output).unwrap();
+    assert!(html.contains("name=\"Injected\""));
+    assert!(html.contains("Added by a custom node"));
+
+    std::fs::remove_dir_all(output.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_unknown_node_is_rejected() {
+    let err = build_pipeline(Arc::new(default_registry()), ["file_collector", "missing"])
+        .err()
+        .expect("unknown node accepted");
+    assert!(err.to_string().contains("missing"));
+}
+
+#[tokio::test]
+async fn test_misordered_pipeline_fails_before_running() {
+    let workspace_dir = workspace_dir();
+    let output = workspace_dir.join("target/test-misordered-pipeline/index.html");
+
+    let pipeline = build_pipeline(
+        Arc::new(default_registry()),
+        [
+            "file_collector",
+            "file_change_detector",
+            "file_parser",
+            "html_generator",
+            "data_aggregator",
+            "file_writer",
+        ],
+    )
+    .unwrap();
+
+    let err = build_command_with_nodes(
+        vec![workspace_dir.join("test/story/A.twee")],
+        output.clone(),
+        false,
+        false,
+        false,
+        None,
+        ConflictPolicy::default(),
+        FormatSearchPath::default().with_dirs([workspace_dir.join("test/story-format")]),
+        None,
+        pipeline,
+        None,
+    )
+    .await
+    .unwrap_err()
+    .to_string();
+
+    assert!(err.contains("'all_passages'"), "{err}");
+    assert!(err.contains("'HtmlGenerator'"), "{err}");
+    assert!(!output.exists());
+}
+
+/// Waits until its partner node runs at the same time, then writes its key
+struct RendezvousNode {
+    key: &'static str,
+    barrier: Arc<tokio::sync::Barrier>,
+}
+
+#[async_trait]
+impl PipeNode for RendezvousNode {
+    fn name(&self) -> String {
+        format!("Rendezvous({})", self.key)
+    }
+
+    fn input(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn output(&self) -> Vec<String> {
+        vec![self.key.to_string()]
+    }
+
+    async fn process(&self, mut data: PipeMap) -> Result<PipeMap> {
+        self.barrier.wait().await;
+        #[allow(deprecated)]
+        data.insert(self.key, true);
+        Ok(data)
+    }
+}
+
+#[tokio::test]
+async fn test_concurrent_mode_overlaps_independent_nodes() {
+    let barrier = Arc::new(tokio::sync::Barrier::new(2));
+    let pipeline = Pipeline::new("test")
+        .with_execution_mode(ExecutionMode::Concurrent)
+        .add_node(Box::new(RendezvousNode {
+            key: "left",
+            barrier: barrier.clone(),
+        }))
+        .unwrap()
+        .add_node(Box::new(RendezvousNode {
+            key: "right",
+            barrier,
+        }))
+        .unwrap();
+
+    let result = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        pipeline.execute(PipeMap::new()),
+    )
+    .await
+    .expect("independent nodes did not run concurrently")
+    .unwrap();
+
+    #[allow(deprecated)]
+    let merged = (result.get::<bool>("left"), result.get::<bool>("right"));
+    assert_eq!(merged, (Some(&true), Some(&true)));
+}
+
+/// Writes a key it does not declare as an output
+struct UndeclaredWriteNode;
+
+#[async_trait]
+impl PipeNode for UndeclaredWriteNode {
+    fn name(&self) -> String {
+        "UndeclaredWrite".to_string()
+    }
+
+    fn input(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn output(&self) -> Vec<String> {
+        vec![]
+    }
+
+    async fn process(&self, mut data: PipeMap) -> Result<PipeMap> {
+        #[allow(deprecated)]
+        data.insert("hidden", true);
+        Ok(data)
+    }
+}
+
+#[tokio::test]
+async fn test_concurrent_mode_rejects_undeclared_writes() {
+    let pipeline = Pipeline::new("test")
+        .with_execution_mode(ExecutionMode::Concurrent)
+        .add_node(Box::new(UndeclaredWriteNode))
+        .unwrap();
+
+    let Err(err) = pipeline.execute(PipeMap::new()).await else {
+        panic!("undeclared write was not reported");
+    };
+    let err = err.to_string();
+    assert!(err.contains("'UndeclaredWrite'"), "{err}");
+    assert!(err.contains("'hidden'"), "{err}");
+}
+
+/// Writes the name of the span it runs in
+struct SpanNameNode;
+
+#[async_trait]
+impl PipeNode for SpanNameNode {
+    fn name(&self) -> String {
+        "SpanName".to_string()
+    }
+
+    fn input(&self) -> Vec<String> {
+        vec![]
+    }
+
+    fn output(&self) -> Vec<String> {
+        vec!["span".to_string()]
+    }
+
+    async fn process(&self, mut data: PipeMap) -> Result<PipeMap> {
+        let span = tracing::Span::current()
+            .metadata()
+            .map(|metadata| metadata.name());
+        #[allow(deprecated)]
+        data.insert("span", span);
+        Ok(data)
+    }
+}
+
+#[tokio::test]
+async fn test_nodes_run_inside_their_span() {
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry());
+
+    for mode in [ExecutionMode::Sequential, ExecutionMode::Concurrent] {
+        let pipeline = Pipeline::new("test")
+            .with_execution_mode(mode)
+            .add_node(Box::new(SpanNameNode))
+            .unwrap();
+        let result = pipeline.execute(PipeMap::new()).await.unwrap();
+
+        #[allow(deprecated)]
+        let span = result.get::<Option<&str>>("span");
+        assert_eq!(span, Some(&Some("pipeline_node")), "{mode:?}");
+    }
+}
+
+#[tokio::test]
+async fn test_timings_observer_reports_every_node() {
+    let workspace_dir = workspace_dir();
+    let output = workspace_dir.join("target/test-timings-pipeline/index.html");
+
+    let timings = Arc::new(TimingsObserver::new());
+    let pipeline = build_pipeline(
+        Arc::new(default_registry()),
+        DEFAULT_BUILD_NODES.iter().copied(),
+    )
+    .unwrap()
+    .with_observer(timings.clone());
+
+    build_command_with_nodes(
+        vec![workspace_dir.join("test/story/A.twee")],
+        output.clone(),
+        false,
+        false,
+        false,
+        None,
+        ConflictPolicy::default(),
+        FormatSearchPath::default().with_dirs([workspace_dir.join("test/story-format")]),
+        None,
+        pipeline,
+        None,
+    )
+    .await
+    .expect("build failed");
+
+    let report = timings.report();
+    assert_eq!(report.pipeline, "TweersBuildPipeline");
+    let nodes: Vec<&str> = report
+        .nodes
+        .iter()
+        .map(|timing| timing.node.as_str())
+        .collect();
+    assert_eq!(
+        nodes,
+        vec![
+            "FileCollector",
+            "FileChangeDetector",
+            "FileParser",
+            "DataAggregator",
+            "StoryFormatLoader",
+            "HtmlGenerator",
+            "FileWriter"
+        ]
+    );
+    assert_eq!(report.files_collected, Some(1));
+    assert!(report.passages.is_some_and(|passages| passages > 0));
+    assert_eq!(
+        report.html_bytes,
+        Some(std::fs::metadata(&output).unwrap().len() as usize)
+    );
+
+    std::fs::remove_dir_all(output.parent().unwrap()).unwrap();
+}
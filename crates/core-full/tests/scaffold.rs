@@ -0,0 +1,82 @@
+use std::path::PathBuf;
+use tweers_core::core::parser::TweeParser;
+use tweers_core_full::format::InstalledFormat;
+use tweers_core_full::scaffold::{choose_format, generate_ifid, project_files, ProjectTemplate};
+
+fn installed() -> Vec<InstalledFormat> {
+    [
+        ("Harlowe", "3.3.9"),
+        ("SugarCube", "2.9.0"),
+        ("SugarCube", "2.37.3"),
+    ]
+    .into_iter()
+    .map(|(name, version)| InstalledFormat {
+        dir_name: format!("{}-{}", name.to_lowercase(), version),
+        path: PathBuf::from("format.js"),
+        name: name.to_string(),
+        version: version.to_string(),
+    })
+    .collect()
+}
+
+#[test]
+fn test_generate_ifid() {
+    let ifid = generate_ifid();
+
+    assert_eq!(ifid.len(), 36);
+    assert_eq!(ifid, ifid.to_uppercase());
+    assert_eq!(&ifid[14..15], "4");
+    assert_ne!(ifid, generate_ifid());
+}
+
+#[test]
+fn test_choose_format() {
+    let installed = installed();
+
+    let format = choose_format(&installed, None).unwrap();
+    assert_eq!(
+        (format.name.as_str(), format.version.as_str()),
+        ("SugarCube", "2.37.3")
+    );
+
+    let format = choose_format(&installed, Some(ProjectTemplate::Harlowe)).unwrap();
+    assert_eq!(format.name, "Harlowe");
+
+    assert!(choose_format(&installed[2..], Some(ProjectTemplate::Harlowe)).is_err());
+    assert!(choose_format(&[], None).is_err());
+}
+
+#[test]
+fn test_project_files_parse() {
+    let installed = installed();
+    let files = project_files(
+        "My Story",
+        "D674C58C-DEFA-4F70-B7A2-27742230C0FC",
+        &installed[2],
+        Some(ProjectTemplate::SugarCube),
+    );
+
+    assert_eq!(files.len(), 3);
+    let (passages, story_data) = TweeParser::parse(&files[0].1).unwrap();
+    let story_data = story_data.unwrap();
+    assert_eq!(story_data.name.as_deref(), Some("My Story"));
+    assert_eq!(story_data.format_version, "2.37.3");
+    assert_eq!(story_data.start.as_deref(), Some("Start"));
+    assert!(passages.contains_key("Start"));
+}
+
+#[test]
+fn test_project_files_without_template_have_a_start() {
+    let installed = installed();
+    let files = project_files(
+        "My Story",
+        "D674C58C-DEFA-4F70-B7A2-27742230C0FC",
+        &installed[2],
+        None,
+    );
+
+    assert_eq!(files.len(), 1);
+    let (passages, story_data) = TweeParser::parse(&files[0].1).unwrap();
+    assert_eq!(story_data.unwrap().start.as_deref(), Some("Start"));
+    assert!(passages.contains_key("Start"));
+}
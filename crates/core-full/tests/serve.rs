@@ -0,0 +1,195 @@
+// Development server and live reload
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tweers_core_full::serve::{inject_live_reload, DevServer, LiveReload, LIVE_RELOAD_SNIPPET};
+
+async fn get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    response
+}
+
+#[test]
+fn test_snippet_goes_before_closing_body() {
+    let html = inject_live_reload("<html><body><p>Story</p></body></html>");
+    assert!(html.starts_with("<html><body><p>Story</p><script>"));
+    assert!(html.ends_with("</script>\n</body></html>"));
+    assert!(inject_live_reload("<p>Story</p>").ends_with(LIVE_RELOAD_SNIPPET));
+}
+
+#[tokio::test]
+async fn test_serves_story_and_assets() {
+    let root = std::env::temp_dir().join(format!("tweers_serve_{}", std::process::id()));
+    let site = root.join("dist");
+    let assets = root.join("images");
+    std::fs::create_dir_all(&site).unwrap();
+    std::fs::create_dir_all(&assets).unwrap();
+    std::fs::write(root.join("secret.txt"), "secret").unwrap();
+    std::fs::write(site.join("style.css"), "body {}").unwrap();
+    std::fs::write(assets.join("cover image.png"), "png").unwrap();
+
+    let html: PathBuf = site.join("index.html");
+    let server = DevServer::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        html.clone(),
+        LiveReload::new(),
+    )
+    .await
+    .unwrap()
+    .with_dir("", site.clone())
+    .with_dir("images", assets.clone());
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+
+    // Before the first build there is a page waiting for it
+    let response = get(addr, "/").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("/__tweers/events"));
+
+    // The snippet is added when serving, the built file is left untouched
+    std::fs::write(&html, "<html><body>Story</body></html>").unwrap();
+    let response = get(addr, "/").await;
+    assert!(response.contains("<html><body>Story<script>"));
+    assert!(response.ends_with("</script>\n</body></html>"));
+    assert_eq!(
+        std::fs::read_to_string(&html).unwrap(),
+        "<html><body>Story</body></html>"
+    );
+
+    let response = get(addr, "/style.css?v=1").await;
+    assert!(response.contains("Content-Type: text/css"));
+    assert!(response.ends_with("body {}"));
+    let response = get(addr, "/images/cover%20image.png").await;
+    assert!(response.contains("Content-Type: image/png"));
+
+    std::fs::write(site.join(".env"), "secret").unwrap();
+    std::fs::create_dir_all(site.join(".git")).unwrap();
+    std::fs::write(site.join(".git/config"), "secret").unwrap();
+    for path in [
+        "/../secret.txt",
+        "/%2e%2e/secret.txt",
+        "/missing.png",
+        "/.env",
+        "/.git/config",
+    ] {
+        assert!(
+            get(addr, path).await.starts_with("HTTP/1.1 404"),
+            "{}",
+            path
+        );
+    }
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_serves_byte_ranges() {
+    let root = std::env::temp_dir().join(format!("tweers_serve_range_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::write(root.join("music.mp3"), "0123456789").unwrap();
+
+    let server = DevServer::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        root.join("index.html"),
+        LiveReload::new(),
+    )
+    .await
+    .unwrap()
+    .with_dir("", root.clone());
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+
+    let get_range = |range: &'static str| async move {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let request = format!("GET /music.mp3 HTTP/1.1\r\nRange: {}\r\n\r\n", range);
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    };
+
+    let response = get(addr, "/music.mp3").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("Accept-Ranges: bytes"));
+    assert!(response.contains("Content-Type: audio/mpeg\r\n"));
+    assert!(response.ends_with("0123456789"));
+
+    for (range, content_range, body) in [
+        ("bytes=2-5", "bytes 2-5/10", "2345"),
+        ("bytes=7-", "bytes 7-9/10", "789"),
+        ("bytes=-3", "bytes 7-9/10", "789"),
+        ("bytes=8-100", "bytes 8-9/10", "89"),
+    ] {
+        let response = get_range(range).await;
+        assert!(
+            response.starts_with("HTTP/1.1 206 Partial Content"),
+            "{}",
+            range
+        );
+        assert!(
+            response.contains(&format!("Content-Range: {}\r\n", content_range)),
+            "{}",
+            range
+        );
+        assert!(
+            response.ends_with(&format!("\r\n\r\n{}", body)),
+            "{}",
+            range
+        );
+    }
+
+    let response = get_range("bytes=10-").await;
+    assert!(response.starts_with("HTTP/1.1 416"));
+    assert!(response.contains("Content-Range: bytes */10"));
+
+    std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_build_results_are_pushed_as_events() {
+    let live_reload = LiveReload::new();
+    let server = DevServer::bind(
+        "127.0.0.1:0".parse().unwrap(),
+        PathBuf::from("missing.html"),
+        live_reload.clone(),
+    )
+    .await
+    .unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+
+    live_reload.build_finished(&Err::<(), _>("first build failed"));
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream
+        .write_all(b"GET /__tweers/events HTTP/1.1\r\n\r\n")
+        .await
+        .unwrap();
+    let mut lines = BufReader::new(stream).lines();
+    let mut next_event = async || {
+        let mut event = Vec::new();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            if line.is_empty() && !event.is_empty() {
+                break;
+            }
+            if line.starts_with("event:") || line.starts_with("data:") {
+                event.push(line);
+            }
+        }
+        event
+    };
+
+    // Pages connecting while the build is broken get the error right away
+    assert_eq!(
+        next_event().await,
+        vec!["event: build-error", "data: \"first build failed\""]
+    );
+
+    live_reload.build_finished(&Ok::<(), String>(()));
+    assert_eq!(next_event().await, vec!["event: reload", "data: "]);
+}
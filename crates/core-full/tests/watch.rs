@@ -0,0 +1,183 @@
+// Watch events and the file cache
+use indexmap::IndexMap;
+use notify::event::{
+    AccessKind, CreateKind, DataChange, MetadataKind, ModifyKind, RemoveKind, RenameMode,
+};
+use notify::{Event, EventKind};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tweers_core_full::commands::{build_pipeline, BuildContext};
+use tweers_core_full::pipeline::default_registry;
+use tweers_core_full::watch::{is_format_change, watch_changes, PipelineReload};
+
+#[test]
+fn test_removed_and_renamed_files_are_evicted() {
+    let dir = std::env::temp_dir().join(format!("tweers_watch_{}", std::process::id()));
+    let chapters = dir.join("chapters");
+    std::fs::create_dir_all(&chapters).unwrap();
+    let kept = dir.join("kept.twee");
+    let removed = dir.join("removed.twee");
+    let renamed = dir.join("renamed.twee");
+    let chapter = chapters.join("one.twee");
+    for file in [&kept, &removed, &renamed, &chapter] {
+        std::fs::write(file, ":: Passage\nText\n").unwrap();
+    }
+
+    let mut context = BuildContext::default();
+    for file in [&kept, &removed, &renamed, &chapter] {
+        context
+            .update_cache(file.clone(), IndexMap::new(), None)
+            .unwrap();
+    }
+
+    std::fs::remove_file(&removed).unwrap();
+    let event = Event::new(EventKind::Remove(RemoveKind::File)).add_path(removed.clone());
+    assert_eq!(watch_changes(&event, &mut context), vec![removed.clone()]);
+    assert!(!context.file_cache.contains_key(&removed));
+
+    let new_name = dir.join("new-name.twee");
+    std::fs::rename(&renamed, &new_name).unwrap();
+    let event = Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+        .add_path(renamed.clone())
+        .add_path(new_name.clone());
+    assert_eq!(
+        watch_changes(&event, &mut context),
+        vec![renamed.clone(), new_name.clone()]
+    );
+    assert!(!context.file_cache.contains_key(&renamed));
+
+    // Removing a directory evicts every file under it
+    std::fs::remove_dir_all(&chapters).unwrap();
+    let event = Event::new(EventKind::Remove(RemoveKind::Folder)).add_path(chapters.clone());
+    assert_eq!(watch_changes(&event, &mut context), vec![chapters.clone()]);
+    assert_eq!(context.file_cache.keys().collect::<Vec<_>>(), vec![&kept]);
+
+    // A new directory triggers a rebuild, even before its files show up
+    std::fs::create_dir_all(&chapters).unwrap();
+    let event = Event::new(EventKind::Create(CreateKind::Folder)).add_path(chapters.clone());
+    assert_eq!(watch_changes(&event, &mut context), vec![chapters.clone()]);
+
+    // Files that were never part of the build are ignored
+    let notes = dir.join("notes.md");
+    let event = Event::new(EventKind::Remove(RemoveKind::File)).add_path(notes);
+    assert!(watch_changes(&event, &mut context).is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_files_collected_from_relative_sources_are_evicted() {
+    // Sources given on the command line are relative, watch events are not
+    let dir = PathBuf::from(format!(
+        "target/tweers_watch_relative_{}",
+        std::process::id()
+    ));
+    let chapters = dir.join("chapters");
+    std::fs::create_dir_all(&chapters).unwrap();
+    let removed = dir.join("removed.twee");
+    let chapter = chapters.join("one.twee");
+    for file in [&removed, &chapter] {
+        std::fs::write(file, ":: Passage\nText\n").unwrap();
+    }
+
+    let mut context = BuildContext::default();
+    for file in [&removed, &chapter] {
+        context
+            .update_cache(file.clone(), IndexMap::new(), None)
+            .unwrap();
+    }
+
+    let absolute = |path: &PathBuf| std::env::current_dir().unwrap().join(path);
+    std::fs::remove_file(&removed).unwrap();
+    let event = Event::new(EventKind::Remove(RemoveKind::File)).add_path(absolute(&removed));
+    assert_eq!(
+        watch_changes(&event, &mut context),
+        vec![absolute(&removed)]
+    );
+    assert!(!context.file_cache.contains_key(&removed));
+
+    std::fs::remove_dir_all(&chapters).unwrap();
+    let event = Event::new(EventKind::Remove(RemoveKind::Folder)).add_path(absolute(&chapters));
+    assert_eq!(
+        watch_changes(&event, &mut context),
+        vec![absolute(&chapters)]
+    );
+    assert!(context.file_cache.is_empty());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_pipeline_reload_ignores_reads() {
+    let scripts = PathBuf::from("/project/scripts");
+    let mut reload = PipelineReload::new(vec![scripts.clone()], || {
+        Ok(build_pipeline(
+            Arc::new(default_registry()),
+            ["file_collector"],
+        )?)
+    });
+
+    let script = scripts.join("data/i18n.js");
+    let event = |kind| Event::new(kind).add_path(script.clone());
+    assert!(reload.is_affected_by(&event(EventKind::Create(CreateKind::File))));
+    assert!(
+        reload.is_affected_by(&event(EventKind::Modify(ModifyKind::Data(
+            DataChange::Content
+        ))))
+    );
+    assert!(reload.is_affected_by(&event(EventKind::Remove(RemoveKind::File))));
+
+    // Loading the scripts for a rebuild must not cause another one
+    assert!(!reload.is_affected_by(&event(EventKind::Access(AccessKind::Read))));
+    assert!(
+        !reload.is_affected_by(&event(EventKind::Modify(ModifyKind::Metadata(
+            MetadataKind::AccessTime
+        ))))
+    );
+
+    let other =
+        Event::new(EventKind::Create(CreateKind::File)).add_path("/project/src/a.twee".into());
+    assert!(!reload.is_affected_by(&other));
+
+    // Script directories relative to the working directory match too
+    let relative = PipelineReload::new(vec![PathBuf::from("scripts")], || {
+        Ok(build_pipeline(
+            Arc::new(default_registry()),
+            ["file_collector"],
+        )?)
+    });
+    let script = std::env::current_dir()
+        .unwrap()
+        .join("scripts/data/i18n.js");
+    assert!(
+        relative.is_affected_by(&Event::new(EventKind::Create(CreateKind::File)).add_path(script))
+    );
+
+    let pipeline = (reload.reload)().unwrap();
+    assert_eq!(pipeline.node_names(), ["file_collector"]);
+}
+
+#[test]
+fn test_format_changes_are_detected() {
+    let format = PathBuf::from("/formats/sugarcube-2.37.3/format.js");
+    let event = |kind, path: &PathBuf| Event::new(kind).add_path(path.clone());
+    let modify = EventKind::Modify(ModifyKind::Data(DataChange::Content));
+
+    // Nothing to reload before a story format is loaded
+    let mut context = BuildContext::default();
+    assert!(!is_format_change(&event(modify, &format), &context));
+
+    context.format_path = Some(format.clone());
+    assert!(is_format_change(&event(modify, &format), &context));
+    assert!(is_format_change(
+        &event(EventKind::Create(CreateKind::File), &format),
+        &context
+    ));
+    assert!(!is_format_change(
+        &event(EventKind::Access(AccessKind::Read), &format),
+        &context
+    ));
+
+    let icon = PathBuf::from("/formats/sugarcube-2.37.3/icon.svg");
+    assert!(!is_format_change(&event(modify, &icon), &context));
+}
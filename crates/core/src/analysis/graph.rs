@@ -0,0 +1,366 @@
+//! Passage link graph and structural checks
+
+use super::is_special_passage;
+use super::links::{Link, extract_links};
+use crate::core::output::default_start_passage;
+use crate::core::story::{Passage, StoryData};
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+/// A passage and its outgoing links
+#[derive(Debug, Clone, Serialize)]
+pub struct PassageNode {
+    pub name: String,
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_line: Option<u32>,
+    /// Special passages (StoryData, scripts, widgets, ...) are never reported as orphans
+    pub special: bool,
+    pub links: Vec<Link>,
+}
+
+/// Kind of problem found in the link graph
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IssueKind {
+    /// A link points to a passage that does not exist
+    BrokenLink { target: String },
+    /// No other passage links to this passage
+    Orphan,
+    /// The passage has incoming links but cannot be reached from the start passage
+    Unreachable,
+    /// The start passage named in StoryData does not exist
+    MissingStart { start: String },
+}
+
+/// A finding reported by the link graph
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GraphIssue {
+    #[serde(flatten)]
+    pub kind: IssueKind,
+    /// Passage the finding belongs to
+    pub passage: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_line: Option<u32>,
+}
+
+impl fmt::Display for GraphIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.source_file {
+            write!(f, "{}", file)?;
+            if let Some(line) = self.source_line {
+                write!(f, ":{}", line)?;
+            }
+            write!(f, ": ")?;
+        }
+
+        match &self.kind {
+            IssueKind::BrokenLink { target } => write!(
+                f,
+                "Passage '{}' links to missing passage '{}'",
+                self.passage, target
+            ),
+            IssueKind::Orphan => write!(f, "Passage '{}' is never linked to", self.passage),
+            IssueKind::Unreachable => write!(
+                f,
+                "Passage '{}' is unreachable from the start passage",
+                self.passage
+            ),
+            IssueKind::MissingStart { start } => {
+                write!(f, "Start passage '{}' does not exist", start)
+            }
+        }
+    }
+}
+
+/// Link graph built from parsed passages
+#[derive(Debug, Clone)]
+pub struct StoryGraph {
+    nodes: IndexMap<String, PassageNode>,
+    start: Option<String>,
+    missing_start: Option<String>,
+    tag_colors: HashMap<String, String>,
+}
+
+impl StoryGraph {
+    /// Build the graph from passages as returned by `api::parse`
+    ///
+    /// The start passage is resolved the same way HTML generation does:
+    /// `StoryData.start`, then [`default_start_passage`].
+    pub fn new(passages: &IndexMap<String, Passage>, story_data: Option<&StoryData>) -> Self {
+        let nodes: IndexMap<String, PassageNode> = passages
+            .iter()
+            .map(|(name, passage)| {
+                let node = PassageNode {
+                    name: name.clone(),
+                    tags: passage
+                        .tags
+                        .as_deref()
+                        .map(|tags| tags.split_whitespace().map(str::to_string).collect())
+                        .unwrap_or_default(),
+                    source_file: passage.source_file.clone(),
+                    source_line: passage.source_line,
+                    special: is_special_passage(passage),
+                    links: extract_links(&passage.content),
+                };
+                (name.clone(), node)
+            })
+            .collect();
+
+        let mut missing_start = None;
+        let start = match story_data.and_then(|data| data.start.as_ref()) {
+            Some(start) if nodes.contains_key(start) => Some(start.clone()),
+            Some(start) => {
+                missing_start = Some(start.clone());
+                None
+            }
+            None => default_start_passage(passages.values()).map(str::to_string),
+        };
+
+        Self {
+            nodes,
+            start,
+            missing_start,
+            tag_colors: story_data
+                .and_then(|data| data.tag_colors.clone())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Resolved start passage name
+    pub fn start(&self) -> Option<&str> {
+        self.start.as_deref()
+    }
+
+    /// Tag colors from `StoryData`
+    pub fn tag_colors(&self) -> &HashMap<String, String> {
+        &self.tag_colors
+    }
+
+    /// Color of a passage: the color of its first tag that has one
+    pub fn color(&self, node: &PassageNode) -> Option<&str> {
+        node.tags
+            .iter()
+            .find_map(|tag| self.tag_colors.get(tag))
+            .map(String::as_str)
+    }
+
+    pub fn node(&self, name: &str) -> Option<&PassageNode> {
+        self.nodes.get(name)
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &PassageNode> {
+        self.nodes.values()
+    }
+
+    /// Links pointing at the given passage, with the passage they come from
+    pub fn incoming(&self, name: &str) -> Vec<(&PassageNode, &Link)> {
+        self.nodes
+            .values()
+            .flat_map(|node| {
+                node.links
+                    .iter()
+                    .filter(move |link| link.target == name)
+                    .map(move |link| (node, link))
+            })
+            .collect()
+    }
+
+    /// Passages reachable from the start passage or any special passage
+    pub fn reachable(&self) -> IndexSet<String> {
+        let mut visited = IndexSet::new();
+        let mut queue: VecDeque<&str> = self
+            .start
+            .as_deref()
+            .into_iter()
+            .chain(
+                self.nodes
+                    .values()
+                    .filter(|node| node.special)
+                    .map(|node| node.name.as_str()),
+            )
+            .collect();
+
+        while let Some(name) = queue.pop_front() {
+            let Some(node) = self.nodes.get(name) else {
+                continue;
+            };
+            if !visited.insert(name.to_string()) {
+                continue;
+            }
+            for link in &node.links {
+                if !visited.contains(&link.target) {
+                    queue.push_back(&link.target);
+                }
+            }
+        }
+
+        visited
+    }
+
+    /// Links whose target passage does not exist
+    pub fn broken_links(&self) -> Vec<GraphIssue> {
+        self.nodes
+            .values()
+            .flat_map(|node| {
+                node.links
+                    .iter()
+                    .filter(|link| !self.nodes.contains_key(&link.target))
+                    .map(move |link| {
+                        Self::issue(
+                            node,
+                            IssueKind::BrokenLink {
+                                target: link.target.clone(),
+                            },
+                        )
+                    })
+            })
+            .collect()
+    }
+
+    /// Story passages that no other passage links to
+    pub fn orphans(&self) -> Vec<GraphIssue> {
+        self.nodes
+            .values()
+            .filter(|node| self.is_story_passage(node) && !self.has_incoming(&node.name))
+            .map(|node| Self::issue(node, IssueKind::Orphan))
+            .collect()
+    }
+
+    /// Story passages with incoming links that still cannot be reached from the start passage
+    pub fn unreachable(&self) -> Vec<GraphIssue> {
+        if self.start.is_none() {
+            return Vec::new();
+        }
+
+        let reachable = self.reachable();
+        self.nodes
+            .values()
+            .filter(|node| {
+                self.is_story_passage(node)
+                    && self.has_incoming(&node.name)
+                    && !reachable.contains(&node.name)
+            })
+            .map(|node| Self::issue(node, IssueKind::Unreachable))
+            .collect()
+    }
+
+    /// All findings: missing start passage, broken links, orphans and unreachable passages
+    pub fn issues(&self) -> Vec<GraphIssue> {
+        let mut issues = Vec::new();
+
+        if let Some(start) = &self.missing_start {
+            let data_node = self.nodes.get("StoryData");
+            issues.push(GraphIssue {
+                kind: IssueKind::MissingStart {
+                    start: start.clone(),
+                },
+                passage: "StoryData".to_string(),
+                source_file: data_node.and_then(|node| node.source_file.clone()),
+                source_line: data_node.and_then(|node| node.source_line),
+            });
+        }
+
+        issues.extend(self.broken_links());
+        issues.extend(self.orphans());
+        issues.extend(self.unreachable());
+        issues
+    }
+
+    fn is_story_passage(&self, node: &PassageNode) -> bool {
+        !node.special && self.start.as_deref() != Some(node.name.as_str())
+    }
+
+    fn has_incoming(&self, name: &str) -> bool {
+        self.nodes
+            .values()
+            .filter(|node| node.name != name)
+            .any(|node| node.links.iter().any(|link| link.target == name))
+    }
+
+    fn issue(node: &PassageNode, kind: IssueKind) -> GraphIssue {
+        GraphIssue {
+            kind,
+            passage: node.name.clone(),
+            source_file: node.source_file.clone(),
+            source_line: node.source_line,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::file::parse_text_content;
+
+    fn graph(content: &str) -> StoryGraph {
+        let parsed = parse_text_content("story.twee", content).expect("parse should succeed");
+        StoryGraph::new(&parsed.passages, parsed.story_data.as_ref())
+    }
+
+    #[test]
+    fn test_broken_links_carry_source_location() {
+        let graph = graph(":: Start\n[[Next]]\n\n:: Next\n[[Ending]]\n");
+        let broken = graph.broken_links();
+
+        assert_eq!(broken.len(), 1);
+        assert_eq!(
+            broken[0].kind,
+            IssueKind::BrokenLink {
+                target: "Ending".to_string()
+            }
+        );
+        assert_eq!(broken[0].passage, "Next");
+        assert_eq!(broken[0].source_file.as_deref(), Some("story.twee"));
+        assert_eq!(broken[0].source_line, Some(4));
+    }
+
+    #[test]
+    fn test_orphans_and_unreachable() {
+        let graph = graph(
+            ":: Start\n[[A]]\n\n:: A\nEnd\n\n:: Lonely\n[[Island]]\n\n:: Island\n[[Lonely]]\n\n:: Forgotten\nNobody links here\n\n:: Menu [widget]\n[[A]]\n",
+        );
+
+        let orphans: Vec<_> = graph.orphans().into_iter().map(|i| i.passage).collect();
+        let unreachable: Vec<_> = graph.unreachable().into_iter().map(|i| i.passage).collect();
+
+        assert_eq!(orphans, vec!["Forgotten"]);
+        assert_eq!(unreachable, vec!["Lonely", "Island"]);
+    }
+
+    #[test]
+    fn test_start_resolution() {
+        let graph = graph(
+            ":: StoryData\n{\"ifid\":\"A\",\"format\":\"SugarCube\",\"format-version\":\"2.37.3\",\"start\":\"Intro\"}\n\n:: Intro\n[[Start]]\n\n:: Start\nHi\n",
+        );
+        assert_eq!(graph.start(), Some("Intro"));
+        assert!(graph.issues().is_empty());
+
+        let graph = graph_with_missing_start();
+        assert_eq!(graph.start(), None);
+        assert!(matches!(
+            graph.issues()[0].kind,
+            IssueKind::MissingStart { .. }
+        ));
+    }
+
+    #[test]
+    fn test_start_falls_back_like_html_generation() {
+        let graph = graph(
+            ":: StoryTitle\nTest\n\n:: Styles [stylesheet]\nbody {}\n\n:: StoryInit\n<<set $a to 1>>\n",
+        );
+        assert_eq!(graph.start(), Some("StoryInit"));
+    }
+
+    fn graph_with_missing_start() -> StoryGraph {
+        graph(
+            ":: StoryData\n{\"ifid\":\"A\",\"format\":\"SugarCube\",\"format-version\":\"2.37.3\",\"start\":\"Nope\"}\n\n:: Start\nHi\n",
+        )
+    }
+}
@@ -0,0 +1,138 @@
+//! Link extraction from passage content
+//!
+//! Recognizes the Twine 2 link markup:
+//! `[[Target]]`, `[[Text|Target]]`, `[[Text->Target]]` and `[[Target<-Text]]`.
+//! SugarCube setter components (`[[Text|Target][$x to 1]]`) are ignored.
+
+use serde::Serialize;
+use std::ops::Range;
+
+/// A link found in passage content
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Link {
+    /// Name of the target passage
+    pub target: String,
+    /// Display text of the link
+    pub text: String,
+    /// Byte range of the whole `[[...]]` markup within the passage content
+    pub range: Range<usize>,
+    /// Byte range of the target name within the passage content
+    pub target_range: Range<usize>,
+}
+
+/// Extract all passage links from passage content
+///
+/// Links never span multiple lines. Links pointing to external URLs or
+/// to story variables (`[[Go|$next]]`) are skipped since they cannot be
+/// resolved statically.
+pub fn extract_links(content: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    let mut cursor = 0;
+
+    while let Some(found) = content[cursor..].find("[[") {
+        let start = cursor + found;
+        let inner_start = start + 2;
+
+        let Some(close) = content[inner_start..].find("]]") else {
+            break;
+        };
+        let inner_end = inner_start + close;
+        let inner = &content[inner_start..inner_end];
+
+        if inner.contains('\n') {
+            cursor = inner_start;
+            continue;
+        }
+
+        cursor = inner_end + 2;
+
+        if let Some(link) = parse_link(inner, inner_start, start..cursor) {
+            links.push(link);
+        }
+    }
+
+    links
+}
+
+fn parse_link(inner: &str, inner_start: usize, range: Range<usize>) -> Option<Link> {
+    let markup = match inner.find("][") {
+        Some(setter) => &inner[..setter],
+        None => inner,
+    };
+
+    // Rightmost `->` and leftmost `<-` act as the divider, like the Twine 2 editor
+    let (text, target, target_offset) = if let Some(i) = markup.rfind("->") {
+        (&markup[..i], &markup[i + 2..], i + 2)
+    } else if let Some(i) = markup.find("<-") {
+        (&markup[i + 2..], &markup[..i], 0)
+    } else if let Some(i) = markup.find('|') {
+        (&markup[..i], &markup[i + 1..], i + 1)
+    } else {
+        (markup, markup, 0)
+    };
+
+    let leading = target.len() - target.trim_start().len();
+    let target = target.trim();
+    if target.is_empty() || is_dynamic_target(target) {
+        return None;
+    }
+
+    let target_start = inner_start + target_offset + leading;
+
+    Some(Link {
+        target: target.to_string(),
+        text: text.trim().to_string(),
+        range,
+        target_range: target_start..target_start + target.len(),
+    })
+}
+
+fn is_dynamic_target(target: &str) -> bool {
+    if target.starts_with('$') || target.starts_with('_') {
+        return true;
+    }
+
+    target
+        .split_once("://")
+        .is_some_and(|(scheme, _)| !scheme.is_empty() && scheme.chars().all(char::is_alphanumeric))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extract_links;
+
+    fn targets(content: &str) -> Vec<String> {
+        extract_links(content)
+            .into_iter()
+            .map(|link| link.target)
+            .collect()
+    }
+
+    #[test]
+    fn test_extract_link_forms() {
+        let content = "[[Start]] [[Go|Next]] [[Onward->Hall]] [[Back<-Return]]";
+        assert_eq!(targets(content), vec!["Start", "Next", "Hall", "Back"]);
+    }
+
+    #[test]
+    fn test_extract_link_ignores_setters_and_dynamic_targets() {
+        let content = "[[Buy|Shop][$gold -= 1]] [[Go|$next]] [[Site|https://example.com]]";
+        assert_eq!(targets(content), vec!["Shop"]);
+    }
+
+    #[test]
+    fn test_extract_link_ranges() {
+        let content = "Intro\n[[Go -> Next Room ]]";
+        let links = extract_links(content);
+
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].text, "Go");
+        assert_eq!(&content[links[0].range.clone()], "[[Go -> Next Room ]]");
+        assert_eq!(&content[links[0].target_range.clone()], "Next Room");
+    }
+
+    #[test]
+    fn test_extract_link_does_not_span_lines() {
+        assert!(targets("[[Broken\nLink]]").is_empty());
+    }
+}
@@ -0,0 +1,499 @@
+//! Lint rules for parsed stories
+
+use super::SPECIAL_TAGS;
+use crate::core::conflict::{
+    ConflictPolicy, DuplicatePolicy, PassageMerger, StoryDataPolicy, passage_location,
+};
+use crate::core::output::default_start_passage;
+use crate::core::story::{Passage, StoryData};
+use crate::error::ParseDiagnostic;
+use indexmap::IndexMap;
+use serde::Serialize;
+use std::fmt;
+
+/// Tags understood by the story formats besides [`SPECIAL_TAGS`]
+const FORMAT_TAGS: &[&str] = &["nobr", "html"];
+
+/// Passages that are allowed to have an empty body
+const EMPTY_ALLOWED: &[&str] = &["StoryData", "StoryTitle"];
+
+/// Diagnostic severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A lint finding
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Rule identifier, e.g. `duplicate-passage`
+    pub code: &'static str,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<u32>,
+    /// 1-based column, counted in characters
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<u32>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Error,
+            code,
+            message: message.into(),
+            file: None,
+            line: None,
+            column: None,
+        }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            severity: Severity::Warning,
+            code,
+            message: message.into(),
+            file: None,
+            line: None,
+            column: None,
+        }
+    }
+
+    /// Attach the location of a passage
+    pub fn at(mut self, passage: &Passage) -> Self {
+        self.file = passage.source_file.clone();
+        self.line = passage.source_line;
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl From<ParseDiagnostic> for Diagnostic {
+    fn from(diagnostic: ParseDiagnostic) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: "parse-error",
+            message: diagnostic.message,
+            file: diagnostic.file,
+            line: Some(diagnostic.line),
+            column: Some(diagnostic.column),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}", file)?;
+            if let Some(line) = self.line {
+                write!(f, ":{}", line)?;
+                if let Some(column) = self.column {
+                    write!(f, ":{}", column)?;
+                }
+            }
+            write!(f, ": ")?;
+        }
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+/// Run all lint rules
+///
+/// `sources` holds the passages and parsed StoryData of each file in build
+/// order, so that duplicates and conflicts across files can be reported.
+/// `story_data` is the aggregated StoryData, if any. Duplicates and
+/// conflicts are errors where `policy` fails the build, warnings where it
+/// warns, and not reported where it resolves them quietly.
+pub fn lint<'a, I>(
+    sources: I,
+    story_data: Option<&StoryData>,
+    policy: ConflictPolicy,
+) -> Vec<Diagnostic>
+where
+    I: IntoIterator<Item = (&'a IndexMap<String, Passage>, Option<&'a StoryData>)>,
+{
+    let mut diagnostics = Vec::new();
+    let mut passages: IndexMap<&str, &Passage> = IndexMap::new();
+    let mut merger = PassageMerger::new(policy.lenient());
+
+    for (source, source_data) in sources {
+        merger.add_source(source, source_data);
+
+        for (name, passage) in source {
+            let previous = passages.insert(name.as_str(), passage);
+            if matches!(name.as_str(), "StoryData" | "StoryTitle") {
+                continue;
+            }
+            let Some(previous) = previous else {
+                continue;
+            };
+            let diagnostic = match policy.duplicates {
+                DuplicatePolicy::Error => Diagnostic::error,
+                DuplicatePolicy::Warn => Diagnostic::warning,
+                DuplicatePolicy::LastWins => continue,
+            };
+            diagnostics.push(
+                diagnostic(
+                    "duplicate-passage",
+                    format!(
+                        "Duplicate passage '{}' (first defined at {})",
+                        name,
+                        passage_location(previous)
+                    ),
+                )
+                .at(passage),
+            );
+        }
+    }
+
+    if let Ok(merged) = merger.finish() {
+        let diagnostic = match policy.story_data {
+            StoryDataPolicy::Error => Diagnostic::error,
+            StoryDataPolicy::First | StoryDataPolicy::Last => Diagnostic::warning,
+        };
+        diagnostics.extend(
+            merged
+                .conflicts
+                .iter()
+                .map(|conflict| diagnostic("conflicting-story-data", conflict.to_string())),
+        );
+    }
+
+    check_story_data(&passages, story_data, &mut diagnostics);
+    check_start(&passages, story_data, &mut diagnostics);
+
+    for passage in passages.values() {
+        check_empty(passage, &mut diagnostics);
+        check_tags(passage, &mut diagnostics);
+    }
+
+    diagnostics
+}
+
+fn check_story_data(
+    passages: &IndexMap<&str, &Passage>,
+    story_data: Option<&StoryData>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let Some(data) = story_data else {
+        diagnostics.push(Diagnostic::error(
+            "missing-story-data",
+            "No StoryData passage found",
+        ));
+        return;
+    };
+
+    let mut data = data.clone();
+    if data.name.is_none() {
+        data.name = passages
+            .get("StoryTitle")
+            .map(|passage| passage.content.clone());
+    }
+
+    let invalid = |message: String| {
+        let diagnostic = Diagnostic::error("invalid-story-data", message);
+        match passages.get("StoryData") {
+            Some(passage) => diagnostic.at(passage),
+            None => diagnostic,
+        }
+    };
+
+    if let Err(e) = data.validate() {
+        diagnostics.push(invalid(e));
+    }
+
+    if !data.ifid.is_empty() && !is_valid_ifid(&data.ifid) {
+        diagnostics.push(invalid(format!(
+            "IFID '{}' is not a valid UUID (expected XXXXXXXX-XXXX-XXXX-XXXX-XXXXXXXXXXXX)",
+            data.ifid
+        )));
+    }
+}
+
+fn check_start(
+    passages: &IndexMap<&str, &Passage>,
+    story_data: Option<&StoryData>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let located = |diagnostic: Diagnostic| match passages.get("StoryData") {
+        Some(passage) => diagnostic.at(passage),
+        None => diagnostic,
+    };
+
+    match story_data.and_then(|data| data.start.as_deref()) {
+        Some(start) => match passages.get(start) {
+            None => diagnostics.push(located(Diagnostic::error(
+                "unresolved-start",
+                format!("Start passage '{}' does not exist", start),
+            ))),
+            Some(passage) if !is_playable(passage) => diagnostics.push(located(Diagnostic::error(
+                "unresolved-start",
+                format!("Start passage '{}' is not a story passage", start),
+            ))),
+            Some(_) => {}
+        },
+        // Without a start, the story begins at `Start` or its first passage
+        None if default_start_passage(passages.values().copied()).is_none() => {
+            diagnostics.push(located(Diagnostic::error(
+                "unresolved-start",
+                "No start passage: set 'start' in StoryData or add a passage named 'Start'",
+            )))
+        }
+        None => {}
+    }
+}
+
+fn check_empty(passage: &Passage, diagnostics: &mut Vec<Diagnostic>) {
+    if passage.content.trim().is_empty() && !EMPTY_ALLOWED.contains(&passage.name.as_str()) {
+        diagnostics.push(
+            Diagnostic::warning(
+                "empty-passage",
+                format!("Passage '{}' has an empty body", passage.name),
+            )
+            .at(passage),
+        );
+    }
+}
+
+fn check_tags(passage: &Passage, diagnostics: &mut Vec<Diagnostic>) {
+    let Some(tags) = passage.tags.as_deref() else {
+        return;
+    };
+
+    for tag in tags.split_whitespace() {
+        if is_known_tag(tag) {
+            continue;
+        }
+
+        if let Some(suggestion) = near_known_tag(tag) {
+            diagnostics.push(
+                Diagnostic::warning(
+                    "unknown-special-tag",
+                    format!(
+                        "Passage '{}' has unknown tag '{}', did you mean '{}'?",
+                        passage.name, tag, suggestion
+                    ),
+                )
+                .at(passage),
+            );
+        }
+    }
+}
+
+fn is_known_tag(tag: &str) -> bool {
+    SPECIAL_TAGS.contains(&tag) || FORMAT_TAGS.contains(&tag)
+}
+
+/// Find a special tag the given tag is probably a typo of
+///
+/// Arbitrary user tags are fine, so only tags within a small edit
+/// distance of a known tag are reported.
+fn near_known_tag(tag: &str) -> Option<&'static str> {
+    let lower = tag.to_lowercase();
+
+    SPECIAL_TAGS
+        .iter()
+        .chain(FORMAT_TAGS)
+        .copied()
+        .map(|known| (known, edit_distance(&lower, &known.to_lowercase())))
+        .filter(|(known, distance)| *distance <= if known.len() > 5 { 2 } else { 1 })
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(known, _)| known)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(current)
+            };
+            previous = current;
+        }
+    }
+
+    row[b.len()]
+}
+
+fn is_valid_ifid(ifid: &str) -> bool {
+    let groups: Vec<&str> = ifid.split('-').collect();
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(group, len)| group.len() == len && group.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+fn is_playable(passage: &Passage) -> bool {
+    if EMPTY_ALLOWED.contains(&passage.name.as_str()) {
+        return false;
+    }
+
+    !passage.tags.as_deref().is_some_and(|tags| {
+        tags.split_whitespace()
+            .any(|tag| matches!(tag, "script" | "stylesheet"))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::file::parse_text_content;
+
+    const STORY_DATA: &str = ":: StoryTitle\nTest\n\n:: StoryData\n{\"ifid\":\"D674C58C-DEFA-4F70-B7A2-27742230C0FC\",\"format\":\"SugarCube\",\"format-version\":\"2.37.3\"}\n\n";
+
+    fn diagnostics(files: &[(&str, &str)], policy: ConflictPolicy) -> Vec<Diagnostic> {
+        let parsed: Vec<_> = files
+            .iter()
+            .map(|(name, content)| parse_text_content(name, content).unwrap())
+            .collect();
+        let story_data = parsed.iter().find_map(|p| p.story_data.clone());
+
+        lint(
+            parsed.iter().map(|p| (&p.passages, p.story_data.as_ref())),
+            story_data.as_ref(),
+            policy,
+        )
+    }
+
+    fn codes(files: &[(&str, &str)]) -> Vec<&'static str> {
+        diagnostics(files, ConflictPolicy::default())
+            .into_iter()
+            .map(|d| d.code)
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_story() {
+        let content = format!("{STORY_DATA}:: Start\nHello\n");
+        assert!(codes(&[("a.twee", &content)]).is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_across_files() {
+        let content = format!("{STORY_DATA}:: Start\nHello\n");
+        let codes = codes(&[("a.twee", &content), ("b.twee", ":: Start\nAgain\n")]);
+        assert_eq!(codes, vec!["duplicate-passage"]);
+    }
+
+    #[test]
+    fn test_duplicates_follow_the_policy() {
+        let content = format!("{STORY_DATA}:: Start\nHello\n");
+        let files = [
+            ("a.twee", content.as_str()),
+            ("b.twee", ":: Start\nAgain\n"),
+        ];
+        let severities = |duplicates| {
+            let policy = ConflictPolicy {
+                duplicates,
+                ..ConflictPolicy::default()
+            };
+            diagnostics(&files, policy)
+                .into_iter()
+                .map(|d| d.severity)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(severities(DuplicatePolicy::Error), vec![Severity::Error]);
+        assert_eq!(severities(DuplicatePolicy::Warn), vec![Severity::Warning]);
+        assert!(severities(DuplicatePolicy::LastWins).is_empty());
+    }
+
+    #[test]
+    fn test_conflicting_story_data_across_files() {
+        let content = format!("{STORY_DATA}:: Start\nHello\n");
+        let other = STORY_DATA.replace("2.37.3", "2.36.1");
+        let codes = codes(&[("a.twee", &content), ("b.twee", &other)]);
+        assert_eq!(codes, vec!["conflicting-story-data"]);
+    }
+
+    #[test]
+    fn test_story_data_rules() {
+        assert_eq!(
+            codes(&[("a.twee", ":: Start\nHello\n")]),
+            vec!["missing-story-data"]
+        );
+
+        let content = ":: StoryTitle\nTest\n\n:: StoryData\n{\"ifid\":\"not-an-ifid\",\"format\":\"SugarCube\",\"format-version\":\"2.37.3\",\"start\":\"Intro\"}\n\n:: Start\nHello\n";
+        assert_eq!(
+            codes(&[("a.twee", content)]),
+            vec!["invalid-story-data", "unresolved-start"]
+        );
+    }
+
+    #[test]
+    fn test_start_falls_back_like_the_build() {
+        let content = format!("{STORY_DATA}:: Intro\nHello\n");
+        assert!(codes(&[("a.twee", &content)]).is_empty());
+
+        let content = format!("{STORY_DATA}:: Styles [stylesheet]\nbody {{}}\n");
+        assert_eq!(codes(&[("a.twee", &content)]), vec!["unresolved-start"]);
+    }
+
+    #[test]
+    fn test_empty_body_and_tag_typos() {
+        let content = format!(
+            "{STORY_DATA}:: Start\nHello\n\n:: Blank\n\n:: Menu [widgets]\n<<widget \"x\">><</widget>>\n\n:: Notes [todo]\nx\n"
+        );
+        assert_eq!(
+            codes(&[("a.twee", &content)]),
+            vec!["empty-passage", "unknown-special-tag"]
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_display() {
+        let passage = Passage {
+            name: "Start".to_string(),
+            tags: None,
+            position: None,
+            size: None,
+            content: String::new(),
+            source_file: Some("story/a.twee".to_string()),
+            source_line: Some(3),
+            spans: None,
+        };
+        let diagnostic = Diagnostic::warning("empty-passage", "empty").at(&passage);
+        assert_eq!(
+            diagnostic.to_string(),
+            "story/a.twee:3: warning[empty-passage]: empty"
+        );
+
+        let parse = ParseDiagnostic {
+            file: Some("story/a.twee".to_string()),
+            line: 4,
+            column: 7,
+            range: 0..1,
+            utf16_range: 0..1,
+            message: "Unclosed tag block".to_string(),
+        };
+        assert_eq!(
+            Diagnostic::from(parse).to_string(),
+            "story/a.twee:4:7: error[parse-error]: Unclosed tag block"
+        );
+    }
+}
@@ -0,0 +1,61 @@
+//! Static analysis of parsed stories
+
+pub mod graph;
+pub mod links;
+pub mod lint;
+pub mod render;
+
+pub use graph::{GraphIssue, IssueKind, PassageNode, StoryGraph};
+pub use links::{Link, extract_links};
+pub use lint::{Diagnostic, Severity, lint};
+pub use render::{GraphEdge, GraphExport, GraphFormat, GraphNode};
+
+use crate::core::story::Passage;
+
+/// Passages with special meaning to Twine or the story formats
+pub const SPECIAL_PASSAGES: &[&str] = &[
+    "StoryData",
+    "StoryTitle",
+    "StoryInit",
+    "StoryCaption",
+    "StoryMenu",
+    "StoryBanner",
+    "StorySubtitle",
+    "StoryAuthor",
+    "StoryInterface",
+    "StoryShare",
+    "PassageReady",
+    "PassageDone",
+    "PassageHeader",
+    "PassageFooter",
+];
+
+/// Tags marking passages that are used by the story format rather than linked to
+pub const SPECIAL_TAGS: &[&str] = &[
+    "script",
+    "stylesheet",
+    "widget",
+    "init",
+    "header",
+    "footer",
+    "startup",
+    "debug-header",
+    "debug-footer",
+    "debug-startup",
+    "Twine.image",
+    "Twine.audio",
+    "Twine.video",
+    "Twine.vtt",
+];
+
+/// Whether a passage is special and never expected to have incoming links
+pub fn is_special_passage(passage: &Passage) -> bool {
+    if SPECIAL_PASSAGES.contains(&passage.name.as_str()) {
+        return true;
+    }
+
+    passage.tags.as_deref().is_some_and(|tags| {
+        tags.split_whitespace()
+            .any(|tag| SPECIAL_TAGS.contains(&tag))
+    })
+}
@@ -0,0 +1,381 @@
+//! Rendering the passage link graph as Graphviz DOT, Mermaid or JSON
+//!
+//! Only story passages are drawn, special passages (scripts, stylesheets,
+//! StoryData, ...) would clutter the branching structure. Links to missing
+//! passages are kept and drawn as dashed nodes.
+
+use super::graph::{PassageNode, StoryGraph};
+use indexmap::IndexMap;
+use serde::Serialize;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+/// Output format of a rendered graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GraphFormat {
+    /// Graphviz DOT
+    #[default]
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+    /// Nodes and edges as JSON
+    Json,
+}
+
+impl FromStr for GraphFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Self::Dot),
+            "mermaid" => Ok(Self::Mermaid),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "Unknown graph format '{s}' (expected dot, mermaid or json)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for GraphFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dot => write!(f, "dot"),
+            Self::Mermaid => write!(f, "mermaid"),
+            Self::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// Twine tag color names mapped to the colors of the Twine 2 editor
+const TWINE_COLORS: &[(&str, &str)] = &[
+    ("red", "#e74c3c"),
+    ("orange", "#e67e22"),
+    ("yellow", "#f1c40f"),
+    ("green", "#2ecc71"),
+    ("blue", "#3498db"),
+    ("purple", "#9b59b6"),
+];
+
+/// Resolve a Twine tag color name, other values are passed through
+fn resolve_color(color: &str) -> &str {
+    TWINE_COLORS
+        .iter()
+        .find(|(name, _)| *name == color)
+        .map_or(color, |(_, hex)| hex)
+}
+
+/// A passage as drawn in the graph
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphNode {
+    pub name: String,
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_file: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source_line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    pub start: bool,
+    /// Target of a broken link, the passage does not exist
+    pub missing: bool,
+}
+
+/// A link as drawn in the graph
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphEdge {
+    pub source: String,
+    pub target: String,
+    /// Link text, when it differs from the target name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// Nodes and edges of a graph, ready to render
+#[derive(Debug, Clone, Serialize)]
+pub struct GraphExport {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<String>,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl GraphExport {
+    pub fn new(graph: &StoryGraph) -> Self {
+        let mut nodes: IndexMap<String, GraphNode> = graph
+            .nodes()
+            .filter(|node| !node.special)
+            .map(|node| (node.name.clone(), Self::node(graph, node)))
+            .collect();
+
+        let mut edges = Vec::new();
+        for node in graph.nodes().filter(|node| !node.special) {
+            for link in &node.links {
+                // Links into special passages are not part of the branching structure
+                if graph
+                    .node(&link.target)
+                    .is_some_and(|target| target.special)
+                {
+                    continue;
+                }
+                if !nodes.contains_key(&link.target) {
+                    nodes.insert(
+                        link.target.clone(),
+                        GraphNode {
+                            name: link.target.clone(),
+                            tags: Vec::new(),
+                            source_file: None,
+                            source_line: None,
+                            color: None,
+                            start: false,
+                            missing: true,
+                        },
+                    );
+                }
+                edges.push(GraphEdge {
+                    source: node.name.clone(),
+                    target: link.target.clone(),
+                    text: (link.text != link.target).then(|| link.text.clone()),
+                });
+            }
+        }
+
+        Self {
+            start: graph.start().map(str::to_string),
+            nodes: nodes.into_values().collect(),
+            edges,
+        }
+    }
+
+    fn node(graph: &StoryGraph, node: &PassageNode) -> GraphNode {
+        GraphNode {
+            name: node.name.clone(),
+            tags: node.tags.clone(),
+            source_file: node.source_file.clone(),
+            source_line: node.source_line,
+            color: graph
+                .color(node)
+                .map(|color| resolve_color(color).to_string()),
+            start: graph.start() == Some(node.name.as_str()),
+            missing: false,
+        }
+    }
+
+    pub fn render(&self, format: GraphFormat) -> String {
+        match format {
+            GraphFormat::Dot => self.to_dot(),
+            GraphFormat::Mermaid => self.to_mermaid(),
+            GraphFormat::Json => self.to_json(),
+        }
+    }
+
+    /// Nodes grouped by source file, in order of first appearance
+    ///
+    /// Nodes without a source file (missing passages) come last under `None`.
+    fn groups(&self) -> IndexMap<Option<&str>, Vec<usize>> {
+        let mut groups: IndexMap<Option<&str>, Vec<usize>> = IndexMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            groups
+                .entry(node.source_file.as_deref())
+                .or_default()
+                .push(i);
+        }
+        if let Some(ungrouped) = groups.shift_remove(&None) {
+            groups.insert(None, ungrouped);
+        }
+        groups
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph story {\n");
+        out.push_str("  node [shape=box, style=\"rounded,filled\", fillcolor=\"#ffffff\"];\n");
+
+        for (group, (file, members)) in self.groups().into_iter().enumerate() {
+            let indent = if file.is_some() { "    " } else { "  " };
+            if let Some(file) = file {
+                let _ = writeln!(out, "  subgraph cluster_{} {{", group);
+                let _ = writeln!(out, "    label={};", dot_quote(file));
+            }
+            for &i in &members {
+                let node = &self.nodes[i];
+                let mut attrs = Vec::new();
+                if let Some(color) = &node.color {
+                    attrs.push(format!("fillcolor={}", dot_quote(color)));
+                }
+                if node.start {
+                    attrs.push("penwidth=2".to_string());
+                }
+                if node.missing {
+                    attrs.push("style=\"rounded,dashed\"".to_string());
+                    attrs.push("color=\"#e74c3c\"".to_string());
+                }
+                let _ = write!(out, "{}{}", indent, dot_quote(&node.name));
+                if !attrs.is_empty() {
+                    let _ = write!(out, " [{}]", attrs.join(", "));
+                }
+                out.push_str(";\n");
+            }
+            if file.is_some() {
+                out.push_str("  }\n");
+            }
+        }
+
+        for edge in &self.edges {
+            let _ = write!(
+                out,
+                "  {} -> {}",
+                dot_quote(&edge.source),
+                dot_quote(&edge.target)
+            );
+            if let Some(text) = &edge.text {
+                let _ = write!(out, " [label={}]", dot_quote(text));
+            }
+            out.push_str(";\n");
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    pub fn to_mermaid(&self) -> String {
+        let ids: IndexMap<&str, String> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.name.as_str(), format!("p{}", i)))
+            .collect();
+
+        let mut out = String::from("flowchart TD\n");
+        let mut styles = Vec::new();
+
+        for (group, (file, members)) in self.groups().into_iter().enumerate() {
+            let indent = if file.is_some() { "    " } else { "  " };
+            if let Some(file) = file {
+                let _ = writeln!(out, "  subgraph f{}[\"{}\"]", group, mermaid_escape(file));
+            }
+            for &i in &members {
+                let node = &self.nodes[i];
+                let id = &ids[node.name.as_str()];
+                let label = mermaid_escape(&node.name);
+                if node.start {
+                    let _ = writeln!(out, "{}{}([\"{}\"])", indent, id, label);
+                } else {
+                    let _ = writeln!(out, "{}{}[\"{}\"]", indent, id, label);
+                }
+
+                if let Some(color) = &node.color {
+                    styles.push(format!("  style {} fill:{}", id, color));
+                }
+                if node.missing {
+                    styles.push(format!(
+                        "  style {} stroke:#e74c3c,stroke-dasharray:5 5",
+                        id
+                    ));
+                }
+            }
+            if file.is_some() {
+                out.push_str("  end\n");
+            }
+        }
+
+        for edge in &self.edges {
+            let source = &ids[edge.source.as_str()];
+            let target = &ids[edge.target.as_str()];
+            match &edge.text {
+                Some(text) => {
+                    let _ = writeln!(
+                        out,
+                        "  {} -->|\"{}\"| {}",
+                        source,
+                        mermaid_escape(text),
+                        target
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "  {} --> {}", source, target);
+                }
+            }
+        }
+
+        for style in styles {
+            out.push_str(&style);
+            out.push('\n');
+        }
+
+        out
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+fn dot_quote(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{}\"", escaped)
+}
+
+fn mermaid_escape(value: &str) -> String {
+    value
+        .replace('"', "#quot;")
+        .replace('|', "#124;")
+        .replace('<', "#lt;")
+        .replace('>', "#gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::file::{aggregate_sources, parse_text_content};
+
+    fn export() -> GraphExport {
+        let sources = vec![
+            parse_text_content(
+                "intro.twee",
+                ":: StoryData\n{\"ifid\":\"A\",\"format\":\"SugarCube\",\"format-version\":\"2.37.3\",\"tag-colors\":{\"combat\":\"red\"}}\n\n:: StoryTitle\nGraph\n\n:: Start\n[[Fight|Arena]] [[Rest]]\n\n:: Script [script]\n[[Start]]\n",
+            )
+            .unwrap(),
+            parse_text_content("arena.twee", ":: Arena [combat]\n[[Start]] [[Gone]]\n")
+                .unwrap(),
+        ];
+        let (passages, story_data) = aggregate_sources(sources).unwrap();
+        GraphExport::new(&StoryGraph::new(&passages, Some(&story_data)))
+    }
+
+    #[test]
+    fn test_export_nodes_and_edges() {
+        let export = export();
+
+        let names: Vec<_> = export.nodes.iter().map(|n| n.name.as_str()).collect();
+        assert_eq!(names, vec!["Start", "Arena", "Rest", "Gone"]);
+        assert!(export.nodes[0].start);
+        assert_eq!(export.nodes[1].color.as_deref(), Some("#e74c3c"));
+        assert!(export.nodes[2].missing && export.nodes[3].missing);
+        assert_eq!(export.edges.len(), 4);
+        assert_eq!(export.edges[0].text.as_deref(), Some("Fight"));
+    }
+
+    #[test]
+    fn test_dot_groups_by_source_file() {
+        let dot = export().to_dot();
+
+        assert!(dot.contains(
+            "subgraph cluster_0 {\n    label=\"intro.twee\";\n    \"Start\" [penwidth=2];"
+        ));
+        assert!(dot.contains("\"Arena\" [fillcolor=\"#e74c3c\"];"));
+        assert!(dot.contains("\"Start\" -> \"Arena\" [label=\"Fight\"];"));
+        assert!(!dot.contains("Script"));
+    }
+
+    #[test]
+    fn test_mermaid_flowchart() {
+        let mermaid = export().to_mermaid();
+
+        assert!(
+            mermaid
+                .starts_with("flowchart TD\n  subgraph f0[\"intro.twee\"]\n    p0([\"Start\"])\n")
+        );
+        assert!(mermaid.contains("  p0 -->|\"Fight\"| p1\n"));
+        assert!(mermaid.contains("  style p1 fill:#e74c3c\n"));
+    }
+}
@@ -1,7 +1,12 @@
 // Stable API facade for external consumers - Pure logic, no I/O
 
+pub use crate::analysis::GraphFormat;
+use crate::analysis::{GraphExport, GraphIssue, StoryGraph};
+use crate::core::conflict::PassageMerger;
+pub use crate::core::conflict::{ConflictPolicy, DuplicatePolicy, StoryDataPolicy};
 use crate::core::file::{
-    aggregate_sources, inject_tweers_paths, parse_bytes_content, parse_text_content,
+    ParsedSource, aggregate_sources_with_policy, inject_tweers_paths, parse_bytes_content,
+    parse_text_content,
 };
 use crate::core::html::TwineHtmlParser;
 use crate::core::story::{Passage, StoryData, StoryFormat};
@@ -39,6 +44,7 @@ pub struct BuildConfig {
     format_info: StoryFormatInfo,
     is_debug: bool,
     start_passage: Option<String>,
+    conflict_policy: ConflictPolicy,
 }
 
 impl BuildConfig {
@@ -48,6 +54,7 @@ impl BuildConfig {
             format_info,
             is_debug: false,
             start_passage: None,
+            conflict_policy: ConflictPolicy::default(),
         }
     }
 
@@ -101,6 +108,18 @@ impl BuildConfig {
         self.start_passage = start_passage;
         self
     }
+
+    /// How to handle passages with the same name in different sources
+    pub fn duplicate_policy(mut self, policy: DuplicatePolicy) -> Self {
+        self.conflict_policy.duplicates = policy;
+        self
+    }
+
+    /// How to handle sources that disagree on StoryData or StoryTitle
+    pub fn story_data_policy(mut self, policy: StoryDataPolicy) -> Self {
+        self.conflict_policy.story_data = policy;
+        self
+    }
 }
 
 /// Parse output - contains parsed passages and story data
@@ -141,7 +160,15 @@ pub struct BuildFromParsedConfig {
 /// Helper function to parse sources into passages and story data
 fn parse_sources(
     sources: &[InputSource],
+    policy: ConflictPolicy,
 ) -> Result<(IndexMap<String, Passage>, StoryData), Box<dyn std::error::Error + Send + Sync>> {
+    aggregate_sources_with_policy(parse_inputs(sources)?, policy)
+}
+
+/// Helper function to parse each source on its own
+fn parse_inputs(
+    sources: &[InputSource],
+) -> Result<Vec<ParsedSource>, Box<dyn std::error::Error + Send + Sync>> {
     let mut parsed_sources = Vec::new();
 
     for source in sources {
@@ -161,7 +188,7 @@ fn parse_sources(
         }
     }
 
-    aggregate_sources(parsed_sources)
+    Ok(parsed_sources)
 }
 
 /// Pure build function - no I/O (synchronous)
@@ -173,7 +200,7 @@ pub fn build(config: BuildConfig) -> Result<BuildOutput, Box<dyn std::error::Err
     let story_format = StoryFormat::parse(&config.format_info.source)?;
 
     // Parse all sources
-    let (passages, mut story_data) = parse_sources(&config.sources)?;
+    let (passages, mut story_data) = parse_sources(&config.sources, config.conflict_policy)?;
     let source_paths = collect_source_paths(&config.sources);
 
     // Sort passages by source_file using depth-first natural order
@@ -209,7 +236,7 @@ pub fn build(config: BuildConfig) -> Result<BuildOutput, Box<dyn std::error::Err
 pub fn parse(
     sources: Vec<InputSource>,
 ) -> Result<ParseOutput, Box<dyn std::error::Error + Send + Sync>> {
-    let (passages, story_data) = parse_sources(&sources)?;
+    let (passages, story_data) = parse_sources(&sources, ConflictPolicy::default())?;
 
     // Extract format info from story_data and create empty source
     let format_info = StoryFormatInfo {
@@ -228,6 +255,8 @@ pub fn parse(
 
 /// Parse passages only - does not require StoryData
 /// Useful for IDE integration where individual files need to be parsed
+///
+/// Passages parsed from Twee sources carry their [`Passage::spans`].
 pub fn passages(
     sources: Vec<InputSource>,
 ) -> Result<IndexMap<String, Passage>, Box<dyn std::error::Error + Send + Sync>> {
@@ -253,6 +282,32 @@ pub fn passages(
     Ok(all_passages)
 }
 
+/// Check passage links for broken targets, orphans and unreachable passages
+pub fn check_links(
+    passages: &IndexMap<String, Passage>,
+    story_data: Option<&StoryData>,
+) -> Vec<GraphIssue> {
+    StoryGraph::new(passages, story_data).issues()
+}
+
+/// Render the passage link graph of the sources as DOT, Mermaid or JSON
+///
+/// StoryData is optional here, without it the start passage falls back to
+/// `Start` and passages have no tag colors.
+pub fn story_graph(
+    sources: Vec<InputSource>,
+    format: GraphFormat,
+) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let mut merger = PassageMerger::new(ConflictPolicy::default());
+    for parsed in parse_inputs(&sources)? {
+        merger.add_source(&parsed.passages, parsed.story_data.as_ref());
+    }
+    let merged = merger.finish()?;
+
+    let graph = StoryGraph::new(&merged.passages, merged.story_data.as_ref());
+    Ok(GraphExport::new(&graph).render(format))
+}
+
 /// Build HTML from already parsed data
 pub fn build_from_parsed(
     parsed: ParseOutput,
@@ -310,6 +365,11 @@ pub fn html_to_twee(html: &str) -> Result<String, Box<dyn std::error::Error + Se
     ))
 }
 
+/// Rewrite Twee source into its canonical layout
+pub fn format_twee(content: &str) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    Ok(crate::core::formatter::format_twee(content)?)
+}
+
 fn collect_source_paths(sources: &[InputSource]) -> Vec<PathBuf> {
     sources
         .iter()
@@ -9,8 +9,20 @@ pub static EXECUTABLE_PATH: OnceLock<PathBuf> = OnceLock::new();
 /// Storage path for StoryFormat
 pub const STORY_FORMAT_DIR: &str = "story-format";
 
-/// Storage path for config.toml
-pub const CONFIG_FILE: &str = "config.toml";
+/// Project-local StoryFormat directory, shared with the VSCode extension
+pub const PROJECT_FORMAT_DIR: &str = ".storyformats";
+
+/// Environment variable listing extra StoryFormat directories
+pub const FORMAT_PATH_ENV: &str = "TWEERS_FORMAT_PATH";
+
+/// Project configuration file, looked up from the working directory upwards
+pub const CONFIG_FILE: &str = "tweers.toml";
+
+/// Project-local parse cache directory
+pub const CACHE_DIR: &str = ".tweers/cache";
+
+/// Output directory of the serve command
+pub const SERVE_DIR: &str = ".tweers/serve";
 
 /// Log file path
 pub const LOG_FILE: &str = "tweers.log";
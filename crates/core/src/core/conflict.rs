@@ -0,0 +1,387 @@
+//! Conflict handling when merging passages from multiple sources
+
+use crate::core::story::{Passage, StoryData};
+use crate::util::sort::compare_paths;
+use indexmap::IndexMap;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use tracing::{debug, warn};
+
+/// What to do when two sources define a passage with the same name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DuplicatePolicy {
+    /// Fail the build
+    Error,
+    /// Log a warning and keep the passage that comes last in build order
+    #[default]
+    Warn,
+    /// Keep the passage that comes last in build order without a warning
+    LastWins,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "warn" => Ok(Self::Warn),
+            "last-wins" => Ok(Self::LastWins),
+            _ => Err(format!(
+                "Unknown duplicate policy '{s}' (expected error, warn or last-wins)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for DuplicatePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warn => write!(f, "warn"),
+            Self::LastWins => write!(f, "last-wins"),
+        }
+    }
+}
+
+/// A passage name defined in more than one place
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicatePassage {
+    pub name: String,
+    /// Location of the passage that was defined first
+    pub first: String,
+    /// Location of the passage that would replace it
+    pub second: String,
+}
+
+impl fmt::Display for DuplicatePassage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Duplicate passage '{}' defined at {} and {}",
+            self.name, self.first, self.second
+        )
+    }
+}
+
+/// Format the `source_file:source_line` location of a passage
+pub fn passage_location(passage: &Passage) -> String {
+    match (&passage.source_file, passage.source_line) {
+        (Some(file), Some(line)) => format!("{file}:{line}"),
+        (Some(file), None) => file.clone(),
+        (None, Some(line)) => format!("line {line}"),
+        (None, None) => "unknown location".to_string(),
+    }
+}
+
+/// Insert a passage into the merged map, applying the duplicate policy
+///
+/// With [`DuplicatePolicy::Error`] the existing passage is kept and the
+/// duplicate is returned, so callers can collect every conflict before failing.
+pub fn insert_passage(
+    passages: &mut IndexMap<String, Passage>,
+    name: String,
+    passage: Passage,
+    policy: DuplicatePolicy,
+) -> Result<(), DuplicatePassage> {
+    if let Some(existing) = passages.get(&name) {
+        let duplicate = DuplicatePassage {
+            name: name.clone(),
+            first: passage_location(existing),
+            second: passage_location(&passage),
+        };
+
+        match policy {
+            DuplicatePolicy::Error => return Err(duplicate),
+            DuplicatePolicy::Warn => warn!("{}", duplicate),
+            DuplicatePolicy::LastWins => debug!("{}", duplicate),
+        }
+    }
+
+    passages.insert(name, passage);
+    Ok(())
+}
+
+/// What to do when sources disagree on `StoryData` or `StoryTitle`
+///
+/// Definitions are ordered by source path (see [`compare_paths`]), so `First`
+/// and `Last` pick the same definition regardless of collection order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StoryDataPolicy {
+    /// Fail the build
+    Error,
+    /// Log a warning and keep the definition that comes first in build order
+    #[default]
+    First,
+    /// Log a warning and keep the definition that comes last in build order
+    Last,
+}
+
+impl FromStr for StoryDataPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(Self::Error),
+            "first" => Ok(Self::First),
+            "last" => Ok(Self::Last),
+            _ => Err(format!(
+                "Unknown StoryData policy '{s}' (expected error, first or last)"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for StoryDataPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::First => write!(f, "first"),
+            Self::Last => write!(f, "last"),
+        }
+    }
+}
+
+/// Policies applied when merging sources
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ConflictPolicy {
+    pub duplicates: DuplicatePolicy,
+    pub story_data: StoryDataPolicy,
+}
+
+impl ConflictPolicy {
+    /// The policy resolving every conflict the same way without failing or
+    /// warning about duplicates, for merges whose conflicts are reported elsewhere
+    pub fn lenient(self) -> Self {
+        Self {
+            duplicates: DuplicatePolicy::LastWins,
+            story_data: match self.story_data {
+                StoryDataPolicy::Error => StoryDataPolicy::First,
+                policy => policy,
+            },
+        }
+    }
+}
+
+/// Sources that disagree on `StoryData` or `StoryTitle`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoryConflict {
+    /// `StoryData` or `StoryTitle`
+    pub passage: &'static str,
+    /// Every definition as `location (value)`, in build order
+    pub definitions: Vec<String>,
+}
+
+impl fmt::Display for StoryConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Conflicting {} definitions: {}",
+            self.passage,
+            self.definitions.join(", ")
+        )
+    }
+}
+
+/// Conflicts that make merging fail under the configured policy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictError {
+    pub duplicates: Vec<DuplicatePassage>,
+    pub story: Vec<StoryConflict>,
+}
+
+impl fmt::Display for ConflictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self
+            .duplicates
+            .iter()
+            .map(ToString::to_string)
+            .chain(self.story.iter().map(ToString::to_string))
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl std::error::Error for ConflictError {}
+
+/// Result of merging sources
+#[derive(Debug, Clone)]
+pub struct MergedSources {
+    pub passages: IndexMap<String, Passage>,
+    pub story_data: Option<StoryData>,
+    /// Story conflicts that were resolved by the policy, to be reported as warnings
+    pub conflicts: Vec<StoryConflict>,
+}
+
+/// Merges passages of several sources, applying a [`ConflictPolicy`]
+///
+/// `StoryData` and `StoryTitle` are exempt from the duplicate policy: identical
+/// definitions in several files are fine, differing ones are story conflicts.
+pub struct PassageMerger {
+    policy: ConflictPolicy,
+    passages: IndexMap<String, Passage>,
+    duplicates: Vec<DuplicatePassage>,
+    story_data: Vec<(Option<Passage>, StoryData)>,
+    titles: Vec<Passage>,
+}
+
+impl PassageMerger {
+    pub fn new(policy: ConflictPolicy) -> Self {
+        Self {
+            policy,
+            passages: IndexMap::new(),
+            duplicates: Vec::new(),
+            story_data: Vec::new(),
+            titles: Vec::new(),
+        }
+    }
+
+    /// Add the passages and StoryData parsed from one source
+    pub fn add_source(
+        &mut self,
+        passages: &IndexMap<String, Passage>,
+        story_data: Option<&StoryData>,
+    ) {
+        for (name, passage) in passages {
+            match name.as_str() {
+                "StoryData" => {}
+                "StoryTitle" => self.titles.push(passage.clone()),
+                _ => {
+                    if let Err(duplicate) = insert_passage(
+                        &mut self.passages,
+                        name.clone(),
+                        passage.clone(),
+                        self.policy.duplicates,
+                    ) {
+                        self.duplicates.push(duplicate);
+                    }
+                    continue;
+                }
+            }
+
+            // Keep the position of the first definition, the winner is filled in later
+            self.passages
+                .entry(name.clone())
+                .or_insert_with(|| passage.clone());
+        }
+
+        if let Some(data) = story_data {
+            self.story_data
+                .push((passages.get("StoryData").cloned(), data.clone()));
+        }
+    }
+
+    pub fn finish(mut self) -> Result<MergedSources, ConflictError> {
+        sort_by_source(&mut self.story_data, |(passage, _)| passage.as_ref());
+        sort_by_source(&mut self.titles, |passage| Some(passage));
+
+        let mut conflicts = Vec::new();
+
+        let data_differs = |a: &StoryData, b: &StoryData| {
+            a.ifid != b.ifid || a.format != b.format || a.format_version != b.format_version
+        };
+        if has_conflict(&self.story_data, |a, b| data_differs(&a.1, &b.1)) {
+            conflicts.push(StoryConflict {
+                passage: "StoryData",
+                definitions: self
+                    .story_data
+                    .iter()
+                    .map(|(passage, data)| {
+                        format!(
+                            "{} (ifid {}, {} {})",
+                            passage
+                                .as_ref()
+                                .map(passage_location)
+                                .unwrap_or_else(|| "unknown location".to_string()),
+                            data.ifid,
+                            data.format,
+                            data.format_version
+                        )
+                    })
+                    .collect(),
+            });
+        }
+
+        if has_conflict(&self.titles, |a, b| a.content.trim() != b.content.trim()) {
+            conflicts.push(StoryConflict {
+                passage: "StoryTitle",
+                definitions: self
+                    .titles
+                    .iter()
+                    .map(|passage| {
+                        format!(
+                            "{} ({:?})",
+                            passage_location(passage),
+                            passage.content.trim()
+                        )
+                    })
+                    .collect(),
+            });
+        }
+
+        if !self.duplicates.is_empty()
+            || (self.policy.story_data == StoryDataPolicy::Error && !conflicts.is_empty())
+        {
+            let story = if self.policy.story_data == StoryDataPolicy::Error {
+                conflicts
+            } else {
+                Vec::new()
+            };
+            return Err(ConflictError {
+                duplicates: self.duplicates,
+                story,
+            });
+        }
+
+        let pick_last = self.policy.story_data == StoryDataPolicy::Last;
+        let title = pick(self.titles, pick_last);
+        let chosen = pick(self.story_data, pick_last);
+
+        if let Some(title) = &title {
+            self.passages
+                .insert("StoryTitle".to_string(), title.clone());
+        }
+
+        let story_data = chosen.map(|(passage, mut data)| {
+            if let Some(passage) = passage {
+                self.passages.insert("StoryData".to_string(), passage);
+            }
+            if let Some(title) = &title {
+                data.name = Some(title.content.trim().to_string());
+            }
+            data
+        });
+
+        Ok(MergedSources {
+            passages: self.passages,
+            story_data,
+            conflicts,
+        })
+    }
+}
+
+fn sort_by_source<T>(items: &mut [T], passage: impl Fn(&T) -> Option<&Passage>) {
+    let source = |item: &T| {
+        passage(item)
+            .and_then(|p| p.source_file.clone())
+            .unwrap_or_default()
+    };
+    items.sort_by(|a, b| compare_paths(source(a), source(b)));
+}
+
+fn has_conflict<T>(items: &[T], differs: impl Fn(&T, &T) -> bool) -> bool {
+    items
+        .split_first()
+        .is_some_and(|(first, rest)| rest.iter().any(|item| differs(first, item)))
+}
+
+fn pick<T>(items: Vec<T>, last: bool) -> Option<T> {
+    if last {
+        items.into_iter().last()
+    } else {
+        items.into_iter().next()
+    }
+}
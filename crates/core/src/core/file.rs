@@ -3,6 +3,7 @@
 //! This module provides unified file type detection and passage creation
 //! to ensure consistent behavior between CLI pipeline and API.
 
+use crate::core::conflict::{ConflictPolicy, PassageMerger};
 use crate::core::story::{Passage, StoryData};
 use crate::excel::parser::ExcelParser;
 use indexmap::IndexMap;
@@ -112,6 +113,7 @@ pub fn create_script_passage(name: String, content: String) -> Passage {
         content,
         source_file: None,
         source_line: None,
+        spans: None,
     }
 }
 
@@ -125,6 +127,7 @@ pub fn create_stylesheet_passage(name: String, content: String) -> Passage {
         content,
         source_file: None,
         source_line: None,
+        spans: None,
     }
 }
 
@@ -138,6 +141,7 @@ pub fn create_init_script_passage(name: String, content: String) -> Passage {
         content,
         source_file: None,
         source_line: None,
+        spans: None,
     }
 }
 
@@ -151,6 +155,7 @@ pub fn create_html_passage(name: String, content: String) -> Passage {
         content,
         source_file: None,
         source_line: None,
+        spans: None,
     }
 }
 
@@ -182,7 +187,8 @@ pub fn parse_text_content(
         }
         FileType::Twee | FileType::Unknown => {
             // Parse as Twee file
-            let (mut passages, story_data) = TweeParser::parse(content)?;
+            let (mut passages, story_data) =
+                TweeParser::parse(content).map_err(|e| e.with_file(name))?;
             set_passages_source_file(&mut passages, name);
             Ok(ParsedSource {
                 passages,
@@ -191,7 +197,8 @@ pub fn parse_text_content(
         }
         FileType::Excel | FileType::Media => {
             // Text content shouldn't be Excel or Media - treat as Twee
-            let (mut passages, story_data) = TweeParser::parse(content)?;
+            let (mut passages, story_data) =
+                TweeParser::parse(content).map_err(|e| e.with_file(name))?;
             set_passages_source_file(&mut passages, name);
             Ok(ParsedSource {
                 passages,
@@ -241,24 +248,34 @@ pub fn parse_bytes_content(
 ///
 /// This handles:
 /// - Merging all passages from multiple sources
-/// - Taking the first StoryData found
+/// - Resolving StoryData and StoryTitle defined in several sources
 /// - Cross-file StoryTitle merge (if StoryData.name is None, get from StoryTitle passage)
+///
+/// Conflicts are handled with the default [`ConflictPolicy`].
 pub fn aggregate_sources(
     sources: Vec<ParsedSource>,
 ) -> Result<(IndexMap<String, Passage>, StoryData), Box<dyn std::error::Error + Send + Sync>> {
-    let mut all_passages = IndexMap::new();
-    let mut story_data: Option<StoryData> = None;
+    aggregate_sources_with_policy(sources, ConflictPolicy::default())
+}
 
-    for source in sources {
-        for (name, passage) in source.passages {
-            all_passages.insert(name, passage);
-        }
-        if story_data.is_none() && source.story_data.is_some() {
-            story_data = source.story_data;
-        }
+/// Aggregate multiple parsed sources, handling conflicts with the given policy
+pub fn aggregate_sources_with_policy(
+    sources: Vec<ParsedSource>,
+    policy: ConflictPolicy,
+) -> Result<(IndexMap<String, Passage>, StoryData), Box<dyn std::error::Error + Send + Sync>> {
+    let mut merger = PassageMerger::new(policy);
+
+    for source in &sources {
+        merger.add_source(&source.passages, source.story_data.as_ref());
     }
 
-    let mut final_story_data = story_data.ok_or("StoryData is required")?;
+    let merged = merger.finish()?;
+    for conflict in &merged.conflicts {
+        warn!("{}", conflict);
+    }
+
+    let all_passages = merged.passages;
+    let mut final_story_data = merged.story_data.ok_or("StoryData is required")?;
 
     // Cross-file StoryTitle merge
     if final_story_data.name.is_none()
@@ -363,6 +380,7 @@ pub fn inject_tweers_paths(
             content,
             source_file: None,
             source_line: None,
+            spans: None,
         },
     );
 }
@@ -419,6 +437,7 @@ fn normalize_path_string(path: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::core::conflict::{DuplicatePolicy, StoryDataPolicy};
 
     #[test]
     fn test_detect_file_type() {
@@ -463,6 +482,81 @@ mod tests {
         );
     }
 
+    fn duplicate_sources() -> Vec<ParsedSource> {
+        let main = ":: StoryData\n{\"ifid\":\"D674C58C-DEFA-4F70-B7A2-27742230C0FC\",\"format\":\"SugarCube\",\"format-version\":\"2.37.3\"}\n\n:: Ending\nFirst\n";
+        vec![
+            parse_text_content("chapter1.twee", main).unwrap(),
+            parse_text_content("chapter2.twee", "\n:: Ending\nSecond\n").unwrap(),
+        ]
+    }
+
+    #[test]
+    fn test_aggregate_sources_duplicate_policy() {
+        let policy = |duplicates| ConflictPolicy {
+            duplicates,
+            ..Default::default()
+        };
+
+        let (passages, _) =
+            aggregate_sources_with_policy(duplicate_sources(), policy(DuplicatePolicy::LastWins))
+                .expect("last-wins should succeed");
+        assert_eq!(passages["Ending"].content, "Second");
+
+        let (passages, _) = aggregate_sources(duplicate_sources()).expect("warn should succeed");
+        assert_eq!(passages["Ending"].content, "Second");
+
+        let err =
+            aggregate_sources_with_policy(duplicate_sources(), policy(DuplicatePolicy::Error))
+                .expect_err("error policy should fail");
+        assert_eq!(
+            err.to_string(),
+            "Duplicate passage 'Ending' defined at chapter1.twee:4 and chapter2.twee:2"
+        );
+    }
+
+    #[test]
+    fn test_aggregate_sources_story_data_conflicts() {
+        let sources = || {
+            vec![
+                parse_text_content(
+                    "b.twee",
+                    ":: StoryTitle\nB\n\n:: StoryData\n{\"ifid\":\"B\",\"format\":\"SugarCube\",\"format-version\":\"2.37.3\"}\n\n:: Start\nHi\n",
+                )
+                .unwrap(),
+                parse_text_content(
+                    "a.twee",
+                    ":: StoryTitle\nA\n\n:: StoryData\n{\"ifid\":\"A\",\"format\":\"SugarCube\",\"format-version\":\"2.37.3\"}\n",
+                )
+                .unwrap(),
+            ]
+        };
+        let policy = |story_data| ConflictPolicy {
+            story_data,
+            ..Default::default()
+        };
+
+        // Definitions are picked in path order, not in the order sources were given
+        let (passages, data) =
+            aggregate_sources_with_policy(sources(), policy(StoryDataPolicy::First))
+                .expect("first should succeed");
+        assert_eq!(data.ifid, "A");
+        assert_eq!(data.name.as_deref(), Some("A"));
+        assert_eq!(passages["StoryTitle"].content, "A");
+
+        let (_, data) = aggregate_sources_with_policy(sources(), policy(StoryDataPolicy::Last))
+            .expect("last should succeed");
+        assert_eq!(data.ifid, "B");
+        assert_eq!(data.name.as_deref(), Some("B"));
+
+        let err = aggregate_sources_with_policy(sources(), policy(StoryDataPolicy::Error))
+            .expect_err("error policy should fail");
+        assert_eq!(
+            err.to_string(),
+            "Conflicting StoryData definitions: a.twee:4 (ifid A, SugarCube 2.37.3), b.twee:4 (ifid B, SugarCube 2.37.3)\n\
+             Conflicting StoryTitle definitions: a.twee:1 (\"A\"), b.twee:1 (\"B\")"
+        );
+    }
+
     #[test]
     fn test_build_tweers_paths_content_includes_twee_passages() {
         let mut passages = IndexMap::new();
@@ -476,6 +570,7 @@ mod tests {
                 content: "Hello".to_string(),
                 source_file: Some("story/main.twee".to_string()),
                 source_line: Some(10),
+                spans: None,
             },
         );
         passages.insert(
@@ -488,6 +583,7 @@ mod tests {
                 content: "Test".to_string(),
                 source_file: Some("story/main.twee".to_string()),
                 source_line: Some(1),
+                spans: None,
             },
         );
         passages.insert(
@@ -500,6 +596,7 @@ mod tests {
                 content: "body {}".to_string(),
                 source_file: Some("assets/theme.css".to_string()),
                 source_line: None,
+                spans: None,
             },
         );
 
@@ -0,0 +1,250 @@
+//! Canonical layout for Twee sources
+//!
+//! Every passage is parsed on its own so duplicates and passage order survive
+//! formatting. Headers are rendered like [`TwineHtmlParser::to_twee`] renders
+//! them, passage bodies are kept as written apart from trailing whitespace.
+//!
+//! [`TwineHtmlParser::to_twee`]: crate::core::html::TwineHtmlParser::to_twee
+
+use crate::core::html::{STORY_DATA_KEYS, TwineHtmlParser};
+use crate::core::parser::{PassageMetadata, TweeParser};
+use crate::core::story::Passage;
+use crate::error::ParseDiagnostics;
+
+/// Rewrite Twee source into its canonical layout
+///
+/// - one space after `::`, between the name, tags and metadata
+/// - tags sorted and deduplicated
+/// - metadata with `position` before `size`
+/// - `StoryData` keys in Twine order, unknown keys sorted after them
+/// - no trailing whitespace, one blank line between passages
+///
+/// Text before the first passage header is kept. Line endings follow the
+/// input. Sources with parse errors are not formatted.
+pub fn format_twee(content: &str) -> Result<String, ParseDiagnostics> {
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    let mut sections = Vec::new();
+    let mut diagnostics = Vec::new();
+
+    let chunks = split_passages(content);
+    let (preamble_end, _) = chunks.first().copied().unwrap_or((content.len(), 0));
+    let preamble = trim_lines(&content[..preamble_end]);
+    if !preamble.is_empty() {
+        sections.push(preamble);
+    }
+
+    for (offset, line) in chunks {
+        let end = content[offset..]
+            .match_indices('\n')
+            .map(|(i, _)| offset + i + 1)
+            .find(|&i| content[i..].starts_with("::"))
+            .unwrap_or(content.len());
+        let chunk = &content[offset..end];
+
+        let (passages, _, chunk_diagnostics) = TweeParser::parse_with_diagnostics(chunk);
+        if !chunk_diagnostics.is_empty() {
+            diagnostics.extend(chunk_diagnostics.into_iter().map(|mut diagnostic| {
+                diagnostic.line += line - 1;
+                diagnostic.range = diagnostic.range.start + offset..diagnostic.range.end + offset;
+                diagnostic
+            }));
+            continue;
+        }
+
+        if let Some(passage) = passages.values().next() {
+            sections.push(format_passage(chunk, passage));
+        }
+    }
+
+    if !diagnostics.is_empty() {
+        return Err(ParseDiagnostics::new(diagnostics));
+    }
+
+    if sections.is_empty() {
+        return Ok(String::new());
+    }
+
+    let formatted = sections.join("\n\n") + "\n";
+    if newline == "\n" {
+        Ok(formatted)
+    } else {
+        Ok(formatted.replace('\n', newline))
+    }
+}
+
+/// Byte offset and 1-based line of every passage header
+fn split_passages(content: &str) -> Vec<(usize, u32)> {
+    let mut headers = Vec::new();
+    let mut offset = 0;
+    for (line, text) in content.split_inclusive('\n').enumerate() {
+        if text.starts_with("::") {
+            headers.push((offset, line as u32 + 1));
+        }
+        offset += text.len();
+    }
+    headers
+}
+
+fn format_passage(chunk: &str, passage: &Passage) -> String {
+    let tags = passage.tags.as_deref().map(|tags| {
+        let mut tags: Vec<&str> = tags.split_whitespace().collect();
+        tags.sort_unstable();
+        tags.dedup();
+        tags.join(" ")
+    });
+
+    let body = passage
+        .spans
+        .as_ref()
+        .map(|spans| &chunk[spans.body.range()])
+        .unwrap_or_default();
+    let body = if passage.name == "StoryData" {
+        format_story_data(body).unwrap_or_else(|| trim_lines(body))
+    } else {
+        trim_lines(body)
+    };
+
+    // The body keeps its escapes, only the header is rendered from parsed values
+    let header = match passage.spans.as_ref().and_then(|spans| {
+        let metadata = &chunk[spans.metadata.as_ref()?.range()];
+        Some((&chunk[spans.header.range()], metadata))
+    }) {
+        // Metadata that did not parse has no values to render, rendering the
+        // header would drop it
+        Some((header, metadata)) if serde_json::from_str::<PassageMetadata>(metadata).is_err() => {
+            header.trim_end().to_string()
+        }
+        _ => {
+            let mut header =
+                TwineHtmlParser::render_passage(&passage.name, tags.as_deref(), None, None, "");
+            header.push_str(&render_metadata(passage));
+            header
+        }
+    };
+    if body.is_empty() {
+        header
+    } else {
+        format!("{header}\n{body}")
+    }
+}
+
+/// Render the metadata block with the keys that are set, `position` first
+fn render_metadata(passage: &Passage) -> String {
+    let fields: Vec<String> = [("position", &passage.position), ("size", &passage.size)]
+        .into_iter()
+        .filter_map(|(key, value)| {
+            Some(format!(
+                "\"{key}\":{}",
+                serde_json::to_string(value.as_ref()?).unwrap()
+            ))
+        })
+        .collect();
+    if fields.is_empty() {
+        String::new()
+    } else {
+        format!(" {{{}}}", fields.join(","))
+    }
+}
+
+/// Render the StoryData object with one key per line in Twine order
+fn format_story_data(body: &str) -> Option<String> {
+    let serde_json::Value::Object(object) = serde_json::from_str(body).ok()? else {
+        return None;
+    };
+
+    let mut keys: Vec<&String> = object.keys().collect();
+    keys.sort_by_key(|key| {
+        STORY_DATA_KEYS
+            .iter()
+            .position(|known| known == key)
+            .unwrap_or(STORY_DATA_KEYS.len())
+    });
+
+    let fields: Vec<(&str, String)> = keys
+        .into_iter()
+        .map(|key| (key.as_str(), serde_json::to_string(&object[key]).unwrap()))
+        .collect();
+    Some(TwineHtmlParser::render_json_fields(&fields))
+}
+
+/// Strip trailing whitespace from every line and blank lines around the text
+fn trim_lines(text: &str) -> String {
+    text.lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim_matches('\n')
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_twee;
+
+    #[test]
+    fn test_format_headers_and_spacing() {
+        let source = "::Start  [ b a b ]{\"size\":\"200,100\",\"position\":\"10,20\"}   \nHello [[Next]]   \n\n\n\n::  Next\\[1\\]\nBye\n";
+
+        assert_eq!(
+            format_twee(source).unwrap(),
+            ":: Start [a b] {\"position\":\"10,20\",\"size\":\"200,100\"}\nHello [[Next]]\n\n:: Next\\[1]\nBye\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_metadata_that_does_not_parse() {
+        let source =
+            "::Start  [b a] {\"position\":10}  \nHi\n\n::Next {\"size\":\"100,100\"}\nBye\n";
+
+        assert_eq!(
+            format_twee(source).unwrap(),
+            "::Start  [b a] {\"position\":10}\nHi\n\n:: Next {\"size\":\"100,100\"}\nBye\n"
+        );
+    }
+
+    #[test]
+    fn test_format_story_data_key_order() {
+        let source = ":: StoryData\n{\"zoom\":1,\"start\":\"Start\",\"custom\":true,\"format\":\"SugarCube\",\"ifid\":\"ABC\",\"format-version\":\"2.37.3\",\"tag-colors\":{\"a\":\"red\"}}\n:: Start\nHi\n";
+
+        assert_eq!(
+            format_twee(source).unwrap(),
+            ":: StoryData\n{\n    \"ifid\": \"ABC\",\n    \"format\": \"SugarCube\",\n    \"format-version\": \"2.37.3\",\n    \"start\": \"Start\",\n    \"tag-colors\": {\"a\":\"red\"},\n    \"zoom\": 1,\n    \"custom\": true\n}\n\n:: Start\nHi\n"
+        );
+    }
+
+    #[test]
+    fn test_format_keeps_duplicates_escapes_and_preamble() {
+        let source =
+            "Notes about the story  \n\n:: A\none\n\\:: not a header\n:: A\ntwo\n:: Empty\n";
+
+        assert_eq!(
+            format_twee(source).unwrap(),
+            "Notes about the story\n\n:: A\none\n\\:: not a header\n\n:: A\ntwo\n\n:: Empty\n"
+        );
+    }
+
+    #[test]
+    fn test_format_is_idempotent_and_keeps_crlf() {
+        let source = ":: Start [x]\r\nLine  \r\n\r\n:: End\r\nDone\r\n";
+        let formatted = format_twee(source).unwrap();
+
+        assert_eq!(formatted, ":: Start [x]\r\nLine\r\n\r\n:: End\r\nDone\r\n");
+        assert_eq!(format_twee(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn test_format_reports_located_errors() {
+        let source = ":: Start\nHi\n\n:: Next [tag\nThere\n";
+        let err = format_twee(source).unwrap_err();
+
+        assert_eq!(err.diagnostics.len(), 1);
+        let diagnostic = &err.diagnostics[0];
+        assert_eq!(diagnostic.line, 4);
+        assert_eq!(&source[diagnostic.range.clone()], "[tag");
+    }
+}
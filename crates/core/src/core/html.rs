@@ -8,6 +8,17 @@ const STORY_DATA_PASSAGE_NAME: &str = "StoryData";
 const STORY_TITLE_PASSAGE_NAME: &str = "StoryTitle";
 const USER_STYLESHEET_PASSAGE_NAME: &str = "StoryStylesheet";
 const USER_SCRIPT_PASSAGE_NAME: &str = "StoryScript";
+
+/// Key order of StoryData JSON in Twee output
+pub(crate) const STORY_DATA_KEYS: &[&str] = &[
+    "ifid",
+    "format",
+    "format-version",
+    "start",
+    "tag-colors",
+    "zoom",
+];
+
 pub struct HtmlParseOutput {
     pub passages: IndexMap<String, Passage>,
     pub story_data: StoryData,
@@ -98,6 +109,7 @@ impl TwineHtmlParser {
                 content,
                 source_file: None,
                 source_line: None,
+                spans: None,
             });
         }
 
@@ -135,6 +147,7 @@ impl TwineHtmlParser {
                 content: Self::story_data_to_twee_json(&story_data),
                 source_file: None,
                 source_line: None,
+                spans: None,
             },
         );
         passages.insert(
@@ -147,6 +160,7 @@ impl TwineHtmlParser {
                 content: story_name,
                 source_file: None,
                 source_line: None,
+                spans: None,
             },
         );
 
@@ -260,6 +274,7 @@ impl TwineHtmlParser {
                 content: element.inner.trim().to_string(),
                 source_file: None,
                 source_line: None,
+                spans: None,
             }));
         }
 
@@ -383,7 +398,7 @@ impl TwineHtmlParser {
         )
     }
 
-    fn render_passage(
+    pub(crate) fn render_passage(
         name: &str,
         tags: Option<&str>,
         position: Option<&str>,
@@ -460,11 +475,17 @@ impl TwineHtmlParser {
             fields.push(("zoom", serde_json::to_string(&zoom).unwrap()));
         }
 
+        Self::render_json_fields(&fields)
+    }
+
+    /// Render already serialized JSON values as an object with one key per line
+    pub(crate) fn render_json_fields(fields: &[(&str, String)]) -> String {
         let mut lines = Vec::with_capacity(fields.len() + 2);
         lines.push("{".to_string());
         for (index, (key, value)) in fields.iter().enumerate() {
             let suffix = if index + 1 == fields.len() { "" } else { "," };
-            lines.push(format!("    \"{key}\": {value}{suffix}"));
+            let key = serde_json::to_string(key).unwrap();
+            lines.push(format!("    {key}: {value}{suffix}"));
         }
         lines.push("}".to_string());
         lines.join("\n")
@@ -494,6 +515,7 @@ mod tests {
                     content: ":: not a header".to_string(),
                     source_file: None,
                     source_line: None,
+                    spans: None,
                 },
             )]),
             &crate::core::story::StoryData {
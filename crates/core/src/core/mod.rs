@@ -1,7 +1,10 @@
+pub mod conflict;
 pub mod file;
+pub mod formatter;
 pub mod html;
 pub mod parser;
 mod skip;
+pub mod span;
 /**
 Twine Spec: https://github.com/iftechfoundation/twine-specs
 
@@ -1,6 +1,9 @@
 use crate::core::story::{Passage, StoryData, StoryFormat};
 use crate::util::html::HtmlEscape;
 use indexmap::IndexMap;
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::Range;
 use tracing::debug;
 
 struct StoryInfo<'a> {
@@ -12,6 +15,174 @@ struct StoryInfo<'a> {
     zoom: f32,
 }
 
+/// Rendering results kept between builds of the same story
+///
+/// Holds the `<tw-passagedata>` element of every passage, checked against a
+/// hash of the passage, and the story format source split at its
+/// placeholders. Rebuilds only escape the passages that changed and splice
+/// the story data into the template instead of searching the format source.
+#[derive(Debug, Clone, Default)]
+pub struct HtmlCache {
+    template: Option<FormatTemplate>,
+    fragments: HashMap<String, Fragment>,
+}
+
+/// `<tw-passagedata>` element of a passage, without the `pid` attribute,
+/// which depends on the position of the passage
+#[derive(Debug, Clone)]
+struct Fragment {
+    hash: u64,
+    html: String,
+}
+
+impl Fragment {
+    fn render(passage: &Passage, hash: u64) -> Self {
+        let tags = passage.tags.as_deref().unwrap_or("");
+        let position = passage.position.as_deref().unwrap_or("");
+        let size = passage.size.as_deref().unwrap_or("");
+        let html = format!(
+            "name={:?} tags={:?} position={:?} size={:?}>{}</tw-passagedata>",
+            passage.name,
+            tags,
+            position,
+            size,
+            HtmlEscape::escape_content(&passage.content)
+        );
+        Self { hash, html }
+    }
+
+    /// Hash of everything the element is rendered from
+    fn hash(passage: &Passage) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        passage.name.hash(&mut hasher);
+        passage.tags.hash(&mut hasher);
+        passage.position.hash(&mut hasher);
+        passage.size.hash(&mut hasher);
+        passage.content.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+/// Fragments of the previous build, moved over as passages are rendered so
+/// that removed passages drop out of the cache
+struct FragmentReuse<'a> {
+    previous: HashMap<String, Fragment>,
+    current: &'a mut HashMap<String, Fragment>,
+    rendered: usize,
+}
+
+impl FragmentReuse<'_> {
+    fn fragment(&mut self, passage: &Passage) -> &str {
+        let hash = Fragment::hash(passage);
+        let fragment = match self.previous.remove(&passage.name) {
+            Some(fragment) if fragment.hash == hash => fragment,
+            _ => {
+                self.rendered += 1;
+                Fragment::render(passage, hash)
+            }
+        };
+
+        &self
+            .current
+            .entry(passage.name.clone())
+            .insert_entry(fragment)
+            .into_mut()
+            .html
+    }
+}
+
+/// Story format source split at the `{{STORY_NAME}}` and `{{STORY_DATA}}`
+/// placeholders
+#[derive(Debug, Clone)]
+struct FormatTemplate {
+    source: String,
+    parts: Vec<TemplatePart>,
+}
+
+#[derive(Debug, Clone)]
+enum TemplatePart {
+    Text(Range<usize>),
+    StoryName,
+    StoryData,
+}
+
+impl FormatTemplate {
+    const STORY_NAME: &str = "{{STORY_NAME}}";
+    const STORY_DATA: &str = "{{STORY_DATA}}";
+
+    fn new(source: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut start = 0;
+        loop {
+            let next = [
+                (Self::STORY_NAME, TemplatePart::StoryName),
+                (Self::STORY_DATA, TemplatePart::StoryData),
+            ]
+            .into_iter()
+            .filter_map(|(placeholder, part)| {
+                source[start..]
+                    .find(placeholder)
+                    .map(|index| (start + index, placeholder.len(), part))
+            })
+            .min_by_key(|(index, _, _)| *index);
+
+            let Some((index, len, part)) = next else {
+                parts.push(TemplatePart::Text(start..source.len()));
+                break;
+            };
+            parts.push(TemplatePart::Text(start..index));
+            parts.push(part);
+            start = index + len;
+        }
+
+        Self {
+            source: source.to_string(),
+            parts,
+        }
+    }
+
+    fn render(&self, name: &str, story_data: &str) -> String {
+        let mut html = String::with_capacity(self.source.len() + story_data.len());
+        for part in &self.parts {
+            match part {
+                TemplatePart::Text(range) => html.push_str(&self.source[range.clone()]),
+                TemplatePart::StoryName => html.push_str(name),
+                TemplatePart::StoryData => html.push_str(story_data),
+            }
+        }
+        html
+    }
+}
+
+/// Whether a passage is written to the story data as `<tw-passagedata>`
+fn is_story_passage(passage: &Passage) -> bool {
+    if passage.name == "StoryTitle" || passage.name == "StoryData" {
+        return false;
+    }
+
+    !passage.tags.as_deref().is_some_and(|tags| {
+        tags.split_whitespace()
+            .any(|tag| matches!(tag, "script" | "stylesheet" | "html"))
+    })
+}
+
+/// Start passage of a story whose StoryData names none
+///
+/// A passage named `Start`, otherwise the first passage that is written to
+/// the story data.
+pub fn default_start_passage<'a, I>(passages: I) -> Option<&'a str>
+where
+    I: IntoIterator<Item = &'a Passage>,
+    I::IntoIter: Clone,
+{
+    let mut passages = passages.into_iter();
+    passages
+        .clone()
+        .find(|passage| passage.name == "Start")
+        .or_else(|| passages.find(|passage| is_story_passage(passage)))
+        .map(|passage| passage.name.as_str())
+}
+
 pub struct HtmlOutputHandler;
 
 impl HtmlOutputHandler {
@@ -20,6 +191,28 @@ impl HtmlOutputHandler {
         story_data: &Option<StoryData>,
         story_format: &StoryFormat,
         is_debug: bool,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Self::render(passages, story_data, story_format, is_debug, None)
+    }
+
+    /// Generate HTML, reusing what is still valid in `cache` from the last
+    /// build and updating it for the next one
+    pub fn generate_html_cached(
+        passages: &IndexMap<String, Passage>,
+        story_data: &Option<StoryData>,
+        story_format: &StoryFormat,
+        is_debug: bool,
+        cache: &mut HtmlCache,
+    ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
+        Self::render(passages, story_data, story_format, is_debug, Some(cache))
+    }
+
+    fn render(
+        passages: &IndexMap<String, Passage>,
+        story_data: &Option<StoryData>,
+        story_format: &StoryFormat,
+        is_debug: bool,
+        cache: Option<&mut HtmlCache>,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         let data = story_data.as_ref().ok_or("StoryData is required")?;
 
@@ -38,13 +231,7 @@ impl HtmlOutputHandler {
         let format_version = &data.format_version;
 
         let start_passage = data.start.as_deref()
-            .or_else(|| {
-                if passages.contains_key("Start") {
-                    Some("Start")
-                } else {
-                    passages.keys().next().map(|k| k.as_str())
-                }
-            })
+            .or_else(|| default_start_passage(passages.values()))
             .ok_or("No start passage found (either specify 'start' in StoryData or provide at least one passage)")?;
 
         let zoom = data.zoom.unwrap_or(1.0);
@@ -57,12 +244,42 @@ impl HtmlOutputHandler {
             start_passage,
             zoom,
         };
-        let story_data_xml = Self::get_twine2_data_chunk(passages, &story_info, data, is_debug)?;
 
-        let mut html = story_format
-            .source
-            .replace("{{STORY_NAME}}", name)
-            .replace("{{STORY_DATA}}", &story_data_xml);
+        let mut html = match cache {
+            Some(cache) => {
+                let mut reuse = FragmentReuse {
+                    previous: std::mem::take(&mut cache.fragments),
+                    current: &mut cache.fragments,
+                    rendered: 0,
+                };
+                let story_data_xml = Self::get_twine2_data_chunk(
+                    passages,
+                    &story_info,
+                    data,
+                    is_debug,
+                    Some(&mut reuse),
+                )?;
+                debug!(
+                    "Rendered {} of {} passages, reused the rest",
+                    reuse.rendered,
+                    reuse.current.len()
+                );
+
+                let template = match &mut cache.template {
+                    Some(template) if template.source == story_format.source => template,
+                    template => template.insert(FormatTemplate::new(&story_format.source)),
+                };
+                template.render(name, &story_data_xml)
+            }
+            None => {
+                let story_data_xml =
+                    Self::get_twine2_data_chunk(passages, &story_info, data, is_debug, None)?;
+                story_format
+                    .source
+                    .replace("{{STORY_NAME}}", name)
+                    .replace("{{STORY_DATA}}", &story_data_xml)
+            }
+        };
 
         // Insert HTML passages into <body>
         html = Self::insert_html_passages(html, passages)?;
@@ -100,13 +317,7 @@ impl HtmlOutputHandler {
                 let start_passage = data
                     .start
                     .as_deref()
-                    .or_else(|| {
-                        if passages.contains_key("Start") {
-                            Some("Start")
-                        } else {
-                            passages.keys().next().map(|k| k.as_str())
-                        }
-                    })
+                    .or_else(|| default_start_passage(passages.values()))
                     .ok_or("No start passage found")?;
 
                 let zoom = data.zoom.unwrap_or(1.0);
@@ -119,8 +330,13 @@ impl HtmlOutputHandler {
                     start_passage,
                     zoom,
                 };
-                let story_data_xml =
-                    Self::get_twine2_data_chunk(passages, &story_info, data, context.is_debug)?;
+                let story_data_xml = Self::get_twine2_data_chunk(
+                    passages,
+                    &story_info,
+                    data,
+                    context.is_debug,
+                    None,
+                )?;
 
                 let mut html = story_format
                     .source
@@ -164,13 +380,7 @@ impl HtmlOutputHandler {
             let start_passage = story_data
                 .start
                 .as_deref()
-                .or_else(|| {
-                    if passages.contains_key("Start") {
-                        Some("Start")
-                    } else {
-                        passages.keys().next().map(|k| k.as_str())
-                    }
-                })
+                .or_else(|| default_start_passage(passages.values()))
                 .ok_or("No start passage found")?;
 
             let zoom = story_data.zoom.unwrap_or(1.0);
@@ -183,8 +393,13 @@ impl HtmlOutputHandler {
                 start_passage,
                 zoom,
             };
-            let story_data_xml =
-                Self::get_twine2_data_chunk(passages, &story_info, story_data, context.is_debug)?;
+            let story_data_xml = Self::get_twine2_data_chunk(
+                passages,
+                &story_info,
+                story_data,
+                context.is_debug,
+                None,
+            )?;
 
             let mut html = story_format
                 .source
@@ -206,6 +421,7 @@ impl HtmlOutputHandler {
         story_info: &StoryInfo,
         story_data: &StoryData,
         is_debug: bool,
+        mut fragments: Option<&mut FragmentReuse>,
     ) -> Result<String, Box<dyn std::error::Error + Send + Sync>> {
         let mut data = Vec::new();
         let mut start_id = String::new();
@@ -290,33 +506,16 @@ impl HtmlOutputHandler {
                 passage_start_id = Some(passage.name.clone());
             }
 
-            if passage.name == "StoryTitle" || passage.name == "StoryData" {
+            if !is_story_passage(passage) {
                 continue;
             }
 
-            if let Some(ref tags) = passage.tags {
-                let tags_list: Vec<&str> = tags.split_whitespace().collect();
-                if tags_list.contains(&"script")
-                    || tags_list.contains(&"stylesheet")
-                    || tags_list.contains(&"html")
-                {
-                    continue;
-                }
+            data.extend_from_slice(format!("<tw-passagedata pid=\"{pid}\" ").as_bytes());
+            match fragments.as_deref_mut() {
+                Some(fragments) => data.extend_from_slice(fragments.fragment(passage).as_bytes()),
+                None => data.extend_from_slice(Fragment::render(passage, 0).html.as_bytes()),
             }
 
-            let escaped_content = HtmlEscape::escape_content(&passage.content);
-
-            let tags = passage.tags.as_deref().unwrap_or("");
-            let position = passage.position.as_deref().unwrap_or("");
-            let size = passage.size.as_deref().unwrap_or("");
-
-            data.extend_from_slice(
-                format!(
-                    "<tw-passagedata pid=\"{}\" name={:?} tags={:?} position={:?} size={:?}>{}</tw-passagedata>",
-                    pid, passage.name, tags, position, size, escaped_content
-                ).as_bytes()
-            );
-
             if story_info.start_passage == passage.name {
                 start_id = pid.to_string();
             }
@@ -1,92 +1,200 @@
+use crate::core::span::{LineIndex, PassageSpans};
 use crate::core::story::{Passage, StoryData};
+use crate::error::{ParseDiagnostic, ParseDiagnostics};
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
+use std::ops::Range;
 use tracing::debug;
 
+/// Passage metadata block, both keys are optional in Twee 3
 #[derive(Debug, Serialize, Deserialize)]
-struct PassageMetadata {
-    position: String,
-    size: String,
+pub(crate) struct PassageMetadata {
+    #[serde(default)]
+    pub(crate) position: Option<String>,
+    #[serde(default)]
+    pub(crate) size: Option<String>,
 }
 
-type PassageHeader = (String, Option<String>, Option<String>, Option<String>, u32); // name, tags, position, size, line_num
-type HeaderParseResult = (String, Option<String>, Option<String>, Option<String>); // name, tags, position, size
-type ParseResult = Result<(IndexMap<String, Passage>, Option<StoryData>), String>;
+type ParseResult = Result<(IndexMap<String, Passage>, Option<StoryData>), ParseDiagnostics>;
+type RecoveredParse = (
+    IndexMap<String, Passage>,
+    Option<StoryData>,
+    Vec<ParseDiagnostic>,
+);
+
+/// A parsed passage header, ranges are relative to the header text
+#[derive(Debug)]
+struct ParsedHeader {
+    name: String,
+    tags: Option<String>,
+    position: Option<String>,
+    size: Option<String>,
+    name_range: Range<usize>,
+    tags_range: Option<Range<usize>>,
+    metadata_range: Option<Range<usize>>,
+}
+
+/// A passage whose body is still being collected
+struct PendingPassage {
+    header: ParsedHeader,
+    /// 1-based line of the header
+    line: u32,
+    /// Byte range of the header line
+    header_range: Range<usize>,
+    /// Byte offset where the header text starts, after `::` and whitespace
+    header_offset: usize,
+    /// Byte offset of the line after the header
+    body_start: usize,
+}
+
+/// Error inside a passage header, with a byte range relative to the header text
+#[derive(Debug)]
+struct HeaderError {
+    message: String,
+    range: Range<usize>,
+}
+
+impl HeaderError {
+    fn new(message: impl Into<String>, range: Range<usize>) -> Self {
+        Self {
+            message: message.into(),
+            range,
+        }
+    }
+}
 
 /// Struct to reduce function parameters
 struct PassageContext<'a> {
     story_title: &'a mut Option<String>,
     passages: &'a mut IndexMap<String, Passage>,
     story_data: &'a mut Option<StoryData>,
+    diagnostics: &'a mut Vec<ParseDiagnostic>,
+    source: &'a str,
+    index: &'a LineIndex,
 }
 
 pub struct TweeParser;
 
 impl TweeParser {
     /// Parse twee3 file content
+    ///
+    /// Fails with every error found in the content.
     pub fn parse(content: &str) -> ParseResult {
+        let (passages, story_data, diagnostics) = Self::parse_with_diagnostics(content);
+
+        if diagnostics.is_empty() {
+            Ok((passages, story_data))
+        } else {
+            Err(ParseDiagnostics::new(diagnostics))
+        }
+    }
+
+    /// Parse twee3 file content, recovering from errors
+    ///
+    /// Passages with an invalid header are skipped, passages with an unclosed
+    /// tag or metadata block are kept with what could be parsed.
+    pub fn parse_with_diagnostics(content: &str) -> RecoveredParse {
         debug!(
             "Starting to parse content with {} lines",
             content.lines().count()
         );
+        let index = LineIndex::new(content);
+        let mut diagnostics = Vec::new();
         let mut passages = IndexMap::new();
         let mut story_data = None;
         let mut story_title: Option<String> = None;
 
-        let mut current_passage: Option<PassageHeader> = None;
+        let mut current_passage: Option<PendingPassage> = None;
+        // Body lines of a passage whose header could not be parsed are dropped
+        let mut skipping = false;
 
         let mut current_content: Vec<&str> = Vec::new();
+        // End of the last body line of the current passage
+        let mut body_end = 0;
+
+        let mut line_start = 0;
+        for (line_num, raw_line) in content.split_inclusive('\n').enumerate() {
+            let offset = line_start;
+            line_start += raw_line.len();
+            let line = raw_line
+                .strip_suffix('\n')
+                .map(|l| l.strip_suffix('\r').unwrap_or(l))
+                .unwrap_or(raw_line);
 
-        for (line_num, line) in content.lines().enumerate() {
             debug!("Processing line {}: {:?}", line_num + 1, line);
             if line.starts_with("::") {
-                if let Some((name, tags, position, size, start_line)) = current_passage.take() {
+                if let Some(pending) = current_passage.take() {
                     let mut context = PassageContext {
                         story_title: &mut story_title,
                         passages: &mut passages,
                         story_data: &mut story_data,
+                        diagnostics: &mut diagnostics,
+                        source: content,
+                        index: &index,
                     };
-                    Self::save_passage(
-                        name,
-                        tags,
-                        position,
-                        size,
-                        start_line,
-                        &current_content,
-                        &mut context,
-                    )?;
+                    Self::save_passage(pending, &current_content, body_end, &mut context);
                     current_content.clear();
                 }
-                let header = line.trim_start_matches("::").trim();
+
+                let after_marker = line.trim_start_matches("::");
+                let header = after_marker.trim();
+                let header_offset = offset + line.len() - after_marker.trim_start().len();
                 debug!("Parsing header: {:?}", header);
-                let (name, tags, position, size) = Self::parse_header(header)?;
-                debug!(
-                    "Parsed metadata: {:?}",
-                    (name.clone(), tags.clone(), position.clone(), size.clone())
-                );
-                current_passage = Some((name, tags, position, size, (line_num + 1) as u32));
-            } else if line.starts_with("\\::") {
-                current_content.push(line.trim_start_matches("\\").trim());
+
+                skipping = false;
+                match Self::parse_header(header) {
+                    Ok((parsed, error)) => {
+                        debug!("Parsed metadata: {:?}", parsed);
+                        if let Some(error) = error {
+                            diagnostics.push(Self::diagnostic(
+                                content,
+                                &index,
+                                error.range.start + header_offset..error.range.end + header_offset,
+                                error.message,
+                            ));
+                        }
+                        current_passage = Some(PendingPassage {
+                            header: parsed,
+                            line: (line_num + 1) as u32,
+                            header_range: offset..offset + line.len(),
+                            header_offset,
+                            body_start: line_start,
+                        });
+                        body_end = line_start;
+                    }
+                    Err(error) => {
+                        diagnostics.push(Self::diagnostic(
+                            content,
+                            &index,
+                            offset..offset + line.len(),
+                            error.message,
+                        ));
+                        current_content.clear();
+                        skipping = true;
+                    }
+                }
+            } else if skipping {
+                continue;
             } else {
-                current_content.push(line);
+                if line.starts_with("\\::") {
+                    current_content.push(line.trim_start_matches("\\").trim());
+                } else {
+                    current_content.push(line);
+                }
+                body_end = offset + line.len();
             }
         }
 
-        if let Some((name, tags, position, size, start_line)) = current_passage {
+        if let Some(pending) = current_passage {
             let mut context = PassageContext {
                 story_title: &mut story_title,
                 passages: &mut passages,
                 story_data: &mut story_data,
+                diagnostics: &mut diagnostics,
+                source: content,
+                index: &index,
             };
-            Self::save_passage(
-                name,
-                tags,
-                position,
-                size,
-                start_line,
-                &current_content,
-                &mut context,
-            )?;
+            Self::save_passage(pending, &current_content, body_end, &mut context);
         }
 
         // Merge story_title into story_data if present
@@ -96,21 +204,28 @@ impl TweeParser {
             data.name = Some(title);
         }
 
-        Ok((passages, story_data))
+        (passages, story_data, diagnostics)
     }
 
     fn save_passage(
-        name: String,
-        tags: Option<String>,
-        position: Option<String>,
-        size: Option<String>,
-        start_line: u32,
-        content_lines: &Vec<&str>,
+        pending: PendingPassage,
+        content_lines: &[&str],
+        body_end: usize,
         context: &mut PassageContext,
-    ) -> Result<(), String> {
+    ) {
         use tracing::debug;
 
-        let content = content_lines.join("\n").trim().to_string();
+        let PendingPassage {
+            header,
+            line: start_line,
+            header_range,
+            header_offset,
+            body_start,
+        } = pending;
+        let name = header.name;
+
+        let joined = content_lines.join("\n");
+        let content = joined.trim().to_string();
         debug!(
             "Saving passage '{}' with content length: {}",
             name,
@@ -124,8 +239,12 @@ impl TweeParser {
                     *context.story_data = Some(data);
                 }
                 Err(e) => {
-                    return Err(std::format!(
-                        "Failed to parse StoryData JSON: {e}. \nContent: '{content}'"
+                    let range = Self::json_error_range(&joined, start_line + 1, &e, context);
+                    context.diagnostics.push(Self::diagnostic(
+                        context.source,
+                        context.index,
+                        range,
+                        format!("Failed to parse StoryData JSON: {e}"),
                     ));
                 }
             }
@@ -133,46 +252,120 @@ impl TweeParser {
             *context.story_title = Some(content.clone());
         }
 
+        // A body that was never extended ends where it starts
+        let body_end = body_end.max(body_start).min(context.source.len());
+        let body_start = body_start.min(body_end);
+        let raw_body = &context.source[body_start..body_end];
+        let body_range = if raw_body.trim().is_empty() {
+            body_start..body_start
+        } else {
+            body_start + raw_body.len() - raw_body.trim_start().len()
+                ..body_start + raw_body.trim_end().len()
+        };
+
+        let source = context.source;
+        let index = context.index;
+        let relative = |range: Range<usize>| {
+            index.span(
+                source,
+                range.start + header_offset..range.end + header_offset,
+            )
+        };
+        let spans = PassageSpans {
+            header: index.span(source, header_range),
+            name: relative(header.name_range),
+            tags: header.tags_range.map(relative),
+            metadata: header.metadata_range.map(relative),
+            body: index.span(source, body_range),
+        };
+
         let passage = Passage {
             name: name.clone(),
-            tags,
-            position,
-            size,
+            tags: header.tags,
+            position: header.position,
+            size: header.size,
             content,
             source_file: None,
             source_line: Some(start_line),
+            spans: Some(spans),
         };
         context.passages.insert(name, passage);
-
-        Ok(())
     }
 
-    fn parse_header(header: &str) -> Result<HeaderParseResult, String> {
-        let mut chars = header.chars().peekable();
+    /// Locate a JSON error of a passage body in the source text
+    ///
+    /// `body` is the untrimmed body starting at line `body_line`.
+    fn json_error_range(
+        body: &str,
+        body_line: u32,
+        error: &serde_json::Error,
+        context: &PassageContext,
+    ) -> Range<usize> {
+        let leading = &body[..body.len() - body.trim_start().len()];
+        let skipped_lines = leading.matches('\n').count() as u32;
+        let first_column = leading.len() - leading.rfind('\n').map_or(0, |i| i + 1);
+
+        let error_line = error.line().max(1) as u32;
+        let mut column = error.column().saturating_sub(1);
+        if error_line == 1 {
+            column += first_column;
+        }
 
-        while let Some(&ch) = chars.peek() {
-            if ch.is_whitespace() {
-                chars.next();
-            } else {
-                break;
-            }
+        let line = body_line + skipped_lines + error_line - 1;
+        let line_start = context.index.line_start(line);
+        let line_end = context.source[line_start..]
+            .find('\n')
+            .map_or(context.source.len(), |i| line_start + i);
+
+        let mut start = (line_start + column).min(line_end);
+        while !context.source.is_char_boundary(start) {
+            start -= 1;
+        }
+        let end = start
+            + context.source[start..line_end]
+                .chars()
+                .next()
+                .map_or(0, char::len_utf8);
+
+        start..end
+    }
+
+    fn diagnostic(
+        source: &str,
+        index: &LineIndex,
+        range: Range<usize>,
+        message: impl Into<String>,
+    ) -> ParseDiagnostic {
+        let span = index.span(source, range.clone());
+        ParseDiagnostic {
+            file: None,
+            line: span.start.line,
+            column: span.start.column,
+            range,
+            utf16_range: span.start.utf16_offset..span.end.utf16_offset,
+            message: message.into(),
         }
+    }
 
+    /// Parse a passage header
+    ///
+    /// Returns an error when no passage can be created. An unclosed tag or
+    /// metadata block still yields the header parsed so far along with the error.
+    fn parse_header(header: &str) -> Result<(ParsedHeader, Option<HeaderError>), HeaderError> {
         let mut name = String::new();
         let mut escaped = false;
+        let mut rest = header.len();
 
-        while let Some(&ch) = chars.peek() {
+        for (i, ch) in header.char_indices() {
             if escaped {
-                chars.next();
                 name.push(ch);
                 escaped = false;
             } else if ch == '\\' {
-                chars.next();
                 escaped = true;
             } else if ch == '[' || ch == '{' {
+                rest = i;
                 break;
             } else {
-                chars.next();
                 name.push(ch);
             }
         }
@@ -180,87 +373,98 @@ impl TweeParser {
         let name = name.trim().to_string();
 
         if name.is_empty() {
-            return Err("Empty passage name".to_string());
+            return Err(HeaderError::new("Empty passage name", 0..header.len()));
         }
 
-        let remainder: String = chars.collect();
-        let remainder = remainder.trim();
-
-        let mut tags = None;
-        let mut position = None;
-        let mut size = None;
-
-        let mut i = 0;
-        let remainder_chars: Vec<char> = remainder.chars().collect();
+        let mut parsed = ParsedHeader {
+            name,
+            tags: None,
+            position: None,
+            size: None,
+            name_range: 0..header[..rest].trim_end().len(),
+            tags_range: None,
+            metadata_range: None,
+        };
 
-        while i < remainder_chars.len() {
-            match remainder_chars[i] {
-                '[' => {
-                    let (tag_content, end_pos) = Self::parse_bracket_block(&remainder_chars, i)?;
-                    if !tag_content.trim().is_empty() {
-                        tags = Some(tag_content.trim().to_string());
+        let mut i = rest;
+        while let Some(ch) = header[i..].chars().next() {
+            match ch {
+                '[' => match Self::parse_bracket_block(header, i) {
+                    Ok((tag_content, end_pos)) => {
+                        if !tag_content.trim().is_empty() {
+                            parsed.tags = Some(tag_content.trim().to_string());
+                        }
+                        parsed.tags_range = Some(i..end_pos + 1);
+                        i = end_pos + 1;
                     }
-                    i = end_pos + 1;
-                }
-                '{' => {
-                    let (json_content, end_pos) = Self::parse_brace_block(&remainder_chars, i)?;
-                    if let Ok(metadata) = serde_json::from_str::<PassageMetadata>(&json_content) {
-                        position = Some(metadata.position);
-                        size = Some(metadata.size);
+                    Err(error) => return Ok((parsed, Some(error))),
+                },
+                '{' => match Self::parse_brace_block(header, i) {
+                    Ok((json_content, end_pos)) => {
+                        if let Ok(metadata) = serde_json::from_str::<PassageMetadata>(&json_content)
+                        {
+                            parsed.position = metadata.position;
+                            parsed.size = metadata.size;
+                        }
+                        parsed.metadata_range = Some(i..end_pos + 1);
+                        i = end_pos + 1;
                     }
-                    i = end_pos + 1;
-                }
+                    Err(error) => return Ok((parsed, Some(error))),
+                },
                 _ => {
-                    i += 1;
+                    i += ch.len_utf8();
                 }
             }
         }
 
-        Ok((name, tags, position, size))
+        Ok((parsed, None))
     }
 
-    fn parse_bracket_block(chars: &[char], start: usize) -> Result<(String, usize), String> {
-        if start >= chars.len() || chars[start] != '[' {
-            return Err("Expected '[' at start position".to_string());
+    fn parse_bracket_block(header: &str, start: usize) -> Result<(String, usize), HeaderError> {
+        if !header[start..].starts_with('[') {
+            return Err(HeaderError::new(
+                "Expected '[' at start position",
+                start..start,
+            ));
         }
 
         let mut content = String::new();
-        let mut i = start + 1;
         let mut escaped = false;
 
-        while i < chars.len() {
-            let ch = chars[i];
-
+        for (i, ch) in header[start + 1..].char_indices() {
             if escaped {
                 content.push(ch);
                 escaped = false;
             } else if ch == '\\' {
                 escaped = true;
             } else if ch == ']' {
-                return Ok((content, i));
+                return Ok((content, start + 1 + i));
             } else {
                 content.push(ch);
             }
-
-            i += 1;
         }
 
-        Err("Unclosed '[' bracket".to_string())
+        Err(HeaderError::new(
+            "Unclosed '[' bracket",
+            start..header.len(),
+        ))
     }
-    fn parse_brace_block(chars: &[char], start: usize) -> Result<(String, usize), String> {
-        if start >= chars.len() || chars[start] != '{' {
-            return Err("Expected '{' at start position".to_string());
+
+    fn parse_brace_block(header: &str, start: usize) -> Result<(String, usize), HeaderError> {
+        if !header[start..].starts_with('{') {
+            return Err(HeaderError::new(
+                "Expected '{' at start position",
+                start..start,
+            ));
         }
 
         let mut content = String::new();
-        let mut i = start;
         let mut brace_count = 0;
         let mut in_string = false;
         let mut string_delimiter = None;
         let mut escaped = false;
 
-        while i < chars.len() {
-            let ch = chars[i];
+        for (i, ch) in header[start..].char_indices() {
             content.push(ch);
 
             if escaped {
@@ -283,16 +487,127 @@ impl TweeParser {
                     '}' => {
                         brace_count -= 1;
                         if brace_count == 0 {
-                            return Ok((content, i));
+                            return Ok((content, start + i));
                         }
                     }
                     _ => {}
                 }
             }
-
-            i += 1;
         }
 
-        Err("Unclosed '{' brace".to_string())
+        Err(HeaderError::new("Unclosed '{' brace", start..header.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TweeParser;
+
+    #[test]
+    fn test_parse_collects_every_error() {
+        let content =
+            ":: Start [tag\nHello\n\n::  \nLost body\n\n:: Next {\"position\":\"1,1\"\nWorld\n";
+        let err = TweeParser::parse(content).expect_err("parse should fail");
+
+        let located: Vec<_> = err
+            .diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.line,
+                    d.column,
+                    &content[d.range.clone()],
+                    d.message.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            located,
+            vec![
+                (1, 10, "[tag", "Unclosed '[' bracket"),
+                (4, 1, "::  ", "Empty passage name"),
+                (7, 9, "{\"position\":\"1,1\"", "Unclosed '{' brace"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_carry_utf16_offsets() {
+        let content = ":: 😀 Start [tag\nHello\n";
+        let err = TweeParser::parse(content).expect_err("parse should fail");
+
+        let diagnostic = &err.diagnostics[0];
+        assert_eq!(diagnostic.range, 14..18);
+        // The emoji is 4 bytes but 2 UTF-16 code units
+        assert_eq!(diagnostic.utf16_range, 12..16);
+    }
+
+    #[test]
+    fn test_parse_recovers_passages() {
+        let content = ":: Start [tag\nHello\n\n::\nLost body\n\n:: Next\nWorld\n";
+        let (passages, _, diagnostics) = TweeParser::parse_with_diagnostics(content);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(passages.keys().collect::<Vec<_>>(), vec!["Start", "Next"]);
+        assert_eq!(passages["Start"].content, "Hello");
+        assert_eq!(passages["Next"].content, "World");
+    }
+
+    #[test]
+    fn test_story_data_json_error_location() {
+        let content = ":: StoryData\n{\n  \"ifid\": \"A\",\n  \"format\" \"SugarCube\"\n}\n";
+        let err = TweeParser::parse(content).expect_err("parse should fail");
+
+        assert_eq!(err.diagnostics.len(), 1);
+        let diagnostic = &err.diagnostics[0];
+        assert_eq!((diagnostic.line, diagnostic.column), (4, 12));
+        assert!(
+            diagnostic
+                .message
+                .starts_with("Failed to parse StoryData JSON")
+        );
+    }
+
+    #[test]
+    fn test_passage_spans() {
+        let content = "::  Start [a b] {\"position\":\"1,2\",\"size\":\"100,100\"}\r\n\r\n  Hello\r\nWorld\r\n\r\n:: Empty\n";
+        let (passages, _) = TweeParser::parse(content).expect("parse should succeed");
+
+        let spans = passages["Start"].spans.expect("spans should be set");
+        let text = |span: crate::core::span::Span| &content[span.range()];
+        assert_eq!(
+            text(spans.header),
+            "::  Start [a b] {\"position\":\"1,2\",\"size\":\"100,100\"}"
+        );
+        assert_eq!(text(spans.name), "Start");
+        assert_eq!((spans.name.start.line, spans.name.start.column), (1, 5));
+        assert_eq!(text(spans.tags.unwrap()), "[a b]");
+        assert_eq!(
+            text(spans.metadata.unwrap()),
+            "{\"position\":\"1,2\",\"size\":\"100,100\"}"
+        );
+        assert_eq!(text(spans.body), "Hello\r\nWorld");
+        assert_eq!((spans.body.start.line, spans.body.start.column), (3, 3));
+        assert_eq!((spans.body.end.line, spans.body.end.column), (4, 6));
+
+        let empty = passages["Empty"].spans.expect("spans should be set");
+        assert!(empty.tags.is_none());
+        assert_eq!(empty.body.range(), content.len()..content.len());
+    }
+
+    #[test]
+    fn test_spans_carry_utf16_positions() {
+        let content = ":: Café 😀\nLe 😀 [[Fin]]\n";
+        let (passages, _) = TweeParser::parse(content).expect("parse should succeed");
+        let spans = passages["Café 😀"].spans.expect("spans should be set");
+        let utf16: Vec<u16> = content.encode_utf16().collect();
+        let utf16_text = |span: crate::core::span::Span| {
+            String::from_utf16(&utf16[span.start.utf16_offset..span.end.utf16_offset]).unwrap()
+        };
+
+        assert_eq!(utf16_text(spans.name), "Café 😀");
+        assert_eq!(utf16_text(spans.body), "Le 😀 [[Fin]]");
+        assert_eq!(spans.body.end.utf16_column, 14);
+        assert_eq!(spans.body.end.column, 13);
     }
 }
@@ -0,0 +1,169 @@
+//! Mapping between byte offsets and line/column positions
+
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// A location in a source text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    /// 1-based line
+    pub line: u32,
+    /// 1-based column, counted in characters
+    pub column: u32,
+    /// Byte offset from the start of the text
+    pub offset: usize,
+    /// 1-based column, counted in UTF-16 code units
+    pub utf16_column: u32,
+    /// UTF-16 offset from the start of the text, a JavaScript string index
+    pub utf16_offset: usize,
+}
+
+/// A range of a source text, the end is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    /// Byte range of the span
+    pub fn range(&self) -> Range<usize> {
+        self.start.offset..self.end.offset
+    }
+}
+
+/// Where the parts of a passage are in its source file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PassageSpans {
+    /// The whole header line, from `::` to the end of the line
+    pub header: Span,
+    /// The passage name as written, including escapes
+    pub name: Span,
+    /// The tag block, including the brackets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Span>,
+    /// The metadata block, including the braces
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<Span>,
+    /// The passage content without surrounding whitespace
+    ///
+    /// Empty passages get an empty span on the line after the header.
+    pub body: Span,
+}
+
+/// Line start offsets of a text, for converting byte offsets to positions
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+    /// UTF-16 offsets of the line starts
+    utf16_line_starts: Vec<usize>,
+    len: usize,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        let mut utf16_line_starts = vec![0];
+        let mut utf16_offset = 0;
+        for (i, c) in text.char_indices() {
+            utf16_offset += c.len_utf16();
+            if c == '\n' {
+                line_starts.push(i + 1);
+                utf16_line_starts.push(utf16_offset);
+            }
+        }
+
+        Self {
+            line_starts,
+            utf16_line_starts,
+            len: text.len(),
+        }
+    }
+
+    /// Byte offset where the given 1-based line starts
+    pub fn line_start(&self, line: u32) -> usize {
+        let index = (line.max(1) - 1) as usize;
+        self.line_starts.get(index).copied().unwrap_or(self.len)
+    }
+
+    /// 1-based line and column of a byte offset
+    ///
+    /// Columns count characters, so the text is needed to measure the line prefix.
+    pub fn line_col(&self, text: &str, offset: usize) -> (u32, u32) {
+        let offset = offset.min(self.len);
+        let line = self.line(offset);
+        let start = self.line_starts[line - 1];
+        let column = text
+            .get(start..offset)
+            .map_or(offset - start, |prefix| prefix.chars().count());
+
+        (line as u32, column as u32 + 1)
+    }
+
+    /// Position of a byte offset
+    pub fn position(&self, text: &str, offset: usize) -> Position {
+        let offset = offset.min(self.len);
+        let (line, column) = self.line_col(text, offset);
+        let start = self.line_starts[line as usize - 1];
+        let utf16_column = text
+            .get(start..offset)
+            .map_or(offset - start, |prefix| prefix.encode_utf16().count());
+
+        Position {
+            line,
+            column,
+            offset,
+            utf16_column: utf16_column as u32 + 1,
+            utf16_offset: self.utf16_line_starts[line as usize - 1] + utf16_column,
+        }
+    }
+
+    /// 1-based line containing a byte offset
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts
+            .partition_point(|&start| start <= offset)
+            .max(1)
+    }
+
+    /// Span of a byte range
+    pub fn span(&self, text: &str, range: Range<usize>) -> Span {
+        Span {
+            start: self.position(text, range.start),
+            end: self.position(text, range.end),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineIndex;
+
+    #[test]
+    fn test_line_col() {
+        let text = "ab\ncé\n\nx";
+        let index = LineIndex::new(text);
+
+        assert_eq!(index.line_col(text, 0), (1, 1));
+        assert_eq!(index.line_col(text, 3), (2, 1));
+        assert_eq!(index.line_col(text, 6), (2, 3));
+        assert_eq!(index.line_col(text, 7), (3, 1));
+        assert_eq!(index.line_col(text, 8), (4, 1));
+        assert_eq!(index.line_start(4), 8);
+    }
+
+    #[test]
+    fn test_utf16_position() {
+        let text = "😀é\nx😀y";
+        let index = LineIndex::new(text);
+
+        let position = index.position(text, text.find('y').unwrap());
+        assert_eq!((position.line, position.column), (2, 3));
+        assert_eq!(position.utf16_column, 4);
+        // 😀 is 2 UTF-16 code units, é and the newline 1 each
+        assert_eq!(position.utf16_offset, 7);
+        assert_eq!(
+            position.utf16_offset,
+            text[..position.offset].encode_utf16().count()
+        );
+    }
+}
@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 use super::skip::parse_js_object;
+use super::span::PassageSpans;
 
 /// StoryData Passage
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +44,9 @@ pub struct Passage {
     /// Line number in source file (1-based)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_line: Option<u32>,
+    /// Location of the passage parts in the source file (for IDE integration)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spans: Option<PassageSpans>,
 }
 
 /// StoryFormat
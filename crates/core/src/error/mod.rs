@@ -1,8 +1,10 @@
 /// Centralized error handling for TweeRS
 pub mod excel;
+pub mod parse;
 pub mod pipeline;
 pub mod tweers;
 
 pub use excel::{ExcelParseError, ExcelResult};
+pub use parse::{ParseDiagnostic, ParseDiagnostics};
 pub use pipeline::{PipelineError, PipelineResult, ProcessingError};
 pub use tweers::{Result, TweersError};
@@ -0,0 +1,88 @@
+/// Located parse error types
+use serde::Serialize;
+use std::fmt;
+use std::ops::Range;
+
+/// A parse error pointing at a span of the source text
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ParseDiagnostic {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    /// 1-based line
+    pub line: u32,
+    /// 1-based column, counted in characters
+    pub column: u32,
+    /// Byte range within the source text
+    pub range: Range<usize>,
+    /// The same range in UTF-16 code units, as JavaScript indexes strings
+    pub utf16_range: Range<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ParseDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+        }
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+/// Parse errors collected from a source
+///
+/// Errors without a known location only carry a message.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ParseDiagnostics {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    pub diagnostics: Vec<ParseDiagnostic>,
+}
+
+impl ParseDiagnostics {
+    pub fn new(diagnostics: Vec<ParseDiagnostic>) -> Self {
+        Self {
+            message: None,
+            diagnostics,
+        }
+    }
+
+    /// Create an error without location
+    pub fn message(msg: impl Into<String>) -> Self {
+        Self {
+            message: Some(msg.into()),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Set the file of every diagnostic that does not have one yet
+    pub fn with_file(mut self, file: &str) -> Self {
+        for diagnostic in &mut self.diagnostics {
+            diagnostic.file.get_or_insert_with(|| file.to_string());
+        }
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.message.is_none() && self.diagnostics.is_empty()
+    }
+}
+
+impl fmt::Display for ParseDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self
+            .message
+            .iter()
+            .cloned()
+            .chain(self.diagnostics.iter().map(ToString::to_string))
+            .collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+impl std::error::Error for ParseDiagnostics {}
+
+impl From<ParseDiagnostic> for ParseDiagnostics {
+    fn from(diagnostic: ParseDiagnostic) -> Self {
+        Self::new(vec![diagnostic])
+    }
+}
@@ -19,6 +19,8 @@ pub enum ProcessingError {
     MissingInput { node: String, required: String },
     #[error("Missing required output '{required}' for node '{node}'")]
     MissingOutput { node: String, required: String },
+    #[error("Node '{node}' wrote '{key}' without declaring it as an output")]
+    UndeclaredOutput { node: String, key: String },
     #[error("Node processing error: {0}")]
     NodeError(#[from] Box<dyn std::error::Error + Send + Sync>),
     #[error("Pipeline configuration error: {message}")]
@@ -9,7 +9,7 @@ pub enum TweersError {
 
     // Parsing errors
     #[error("Parse error: {0}")]
-    Parse(String),
+    Parse(crate::error::ParseDiagnostics),
 
     #[error("Excel parsing error: {0}")]
     Excel(#[from] crate::error::ExcelParseError),
@@ -25,6 +25,10 @@ pub enum TweersError {
     #[error("Invalid configuration: {0}")]
     InvalidConfig(String),
 
+    // Conflicting definitions across sources
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
     // Format errors
     #[error("Format error: {0}")]
     Format(String),
@@ -48,7 +52,17 @@ pub type Result<T> = std::result::Result<T, TweersError>;
 impl TweersError {
     /// Create a parse error
     pub fn parse(msg: impl Into<String>) -> Self {
-        Self::Parse(msg.into())
+        Self::Parse(crate::error::ParseDiagnostics::message(msg))
+    }
+
+    /// Create a parse error for a source that failed to parse
+    ///
+    /// Located diagnostics are kept as they are, other errors get the path prepended.
+    pub fn parse_failure(path: &str, error: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        match error.downcast::<crate::error::ParseDiagnostics>() {
+            Ok(diagnostics) => Self::Parse(*diagnostics),
+            Err(error) => Self::parse(format!("Failed to parse {}: {}", path, error)),
+        }
     }
 
     /// Create a missing input error
@@ -61,6 +75,11 @@ impl TweersError {
         Self::InvalidConfig(msg.into())
     }
 
+    /// Create a conflict error
+    pub fn conflict(msg: impl Into<String>) -> Self {
+        Self::Conflict(msg.into())
+    }
+
     /// Create a format error
     pub fn format(msg: impl Into<String>) -> Self {
         Self::Format(msg.into())
@@ -97,7 +116,13 @@ impl From<&str> for TweersError {
 // Conversion from serde_json::Error
 impl From<serde_json::Error> for TweersError {
     fn from(err: serde_json::Error) -> Self {
-        Self::Parse(err.to_string())
+        Self::parse(err.to_string())
+    }
+}
+
+impl From<crate::error::ParseDiagnostics> for TweersError {
+    fn from(diagnostics: crate::error::ParseDiagnostics) -> Self {
+        Self::Parse(diagnostics)
     }
 }
 
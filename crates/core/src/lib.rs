@@ -1,3 +1,4 @@
+pub mod analysis;
 pub mod api;
 pub mod commands;
 pub mod config;
@@ -8,6 +9,9 @@ pub mod excel;
 pub mod pipeline;
 pub mod util;
 
+/// Version of tweers-core, which produces the parse results others cache
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 // WASM bindings (only compiled when wasm feature is enabled)
 #[cfg(feature = "wasm")]
 pub mod wasm;
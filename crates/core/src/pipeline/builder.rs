@@ -1,15 +1,23 @@
 // Pipeline builder for fluent pipeline construction
-use super::core::Pipeline;
+use super::core::{ExecutionMode, Pipeline};
+use super::observer::PipelineObserver;
 use super::registry::NodeRegistry;
 use crate::error::{Result, TweersError};
 use std::sync::Arc;
 
 /// Builder for constructing pipelines with registered nodes
+///
+/// Cloning is cheap, so a builder can be kept around to create a fresh
+/// pipeline for every run.
+#[derive(Clone)]
 pub struct PipelineBuilder {
     name: String,
     registry: Arc<NodeRegistry>,
     node_names: Vec<String>,
     external_inputs: Vec<String>,
+    external_outputs: Vec<String>,
+    mode: ExecutionMode,
+    observers: Vec<Arc<dyn PipelineObserver>>,
 }
 
 impl PipelineBuilder {
@@ -20,6 +28,9 @@ impl PipelineBuilder {
             registry,
             node_names: Vec::new(),
             external_inputs: Vec::new(),
+            external_outputs: Vec::new(),
+            mode: ExecutionMode::default(),
+            observers: Vec::new(),
         }
     }
 
@@ -29,6 +40,28 @@ impl PipelineBuilder {
         self
     }
 
+    /// Set the keys the caller reads from the result
+    pub fn with_external_outputs(mut self, outputs: Vec<String>) -> Self {
+        self.external_outputs = outputs;
+        self
+    }
+
+    /// Set how the pipeline runs its nodes
+    pub fn with_execution_mode(mut self, mode: ExecutionMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn execution_mode(&self) -> ExecutionMode {
+        self.mode
+    }
+
+    /// Add an observer to the built pipelines
+    pub fn with_observer(mut self, observer: Arc<dyn PipelineObserver>) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
     /// Add a node by name
     pub fn add_node(mut self, name: impl Into<String>) -> Result<Self> {
         let node_name = name.into();
@@ -61,6 +94,11 @@ impl PipelineBuilder {
         Ok(self)
     }
 
+    /// Names of the nodes added so far, in execution order
+    pub fn node_names(&self) -> &[String] {
+        &self.node_names
+    }
+
     /// Build the pipeline
     pub fn build(self) -> Result<Pipeline> {
         let mut nodes = Vec::new();
@@ -73,7 +111,13 @@ impl PipelineBuilder {
         }
 
         let mut pipeline = Pipeline::new(self.name);
-        pipeline = pipeline.with_external_inputs(self.external_inputs);
+        pipeline = pipeline
+            .with_external_inputs(self.external_inputs)
+            .with_external_outputs(self.external_outputs)
+            .with_execution_mode(self.mode);
+        for observer in self.observers {
+            pipeline = pipeline.with_observer(observer);
+        }
 
         for node in nodes {
             pipeline = pipeline.add_node(node)?;
@@ -1,11 +1,15 @@
 // Pipeline core - pure logic framework
 
 use super::keys::TypedKey;
-use crate::error::Result;
+use super::observer::PipelineObserver;
+use crate::error::{ProcessingError, Result};
 use async_trait::async_trait;
 use std::any::Any;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::Arc;
+use std::time::Instant;
+use tracing::{Instrument, debug};
 
 /// Pipeline data map for passing data between nodes
 #[derive(Clone)]
@@ -32,6 +36,16 @@ impl PipeMap {
             .and_then(|v| v.downcast_ref::<T>())
     }
 
+    /// Check if a value is stored under the key name
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.data.contains_key(key)
+    }
+
+    /// Names of all stored values
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.data.keys().map(String::as_str)
+    }
+
     /// Legacy string-based insert (deprecated, use insert_typed)
     #[deprecated(note = "Use insert_typed for type safety")]
     pub fn insert<T: Any + Send + Sync>(&mut self, key: impl Into<String>, value: T) {
@@ -52,6 +66,8 @@ impl Default for PipeMap {
 }
 
 /// Pipeline node trait
+///
+/// Keys in `input()` ending with `?` are optional, the node runs without them.
 #[async_trait]
 pub trait PipeNode: Send + Sync {
     fn name(&self) -> String;
@@ -61,11 +77,57 @@ pub trait PipeNode: Send + Sync {
     async fn process(&self, data: PipeMap) -> Result<PipeMap>;
 }
 
+/// Split an `input()`/`output()` entry into its key and whether it is required
+fn parse_key(key: &str) -> (&str, bool) {
+    match key.strip_suffix('?') {
+        Some(key) => (key, false),
+        None => (key, true),
+    }
+}
+
+/// Output of a node that no later node reads before it is overwritten
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnusedOutput {
+    pub node: String,
+    pub key: String,
+}
+
+impl fmt::Display for UnusedOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "output '{}' of node '{}' is never used",
+            self.key, self.node
+        )
+    }
+}
+
+/// How a pipeline runs its nodes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// One node after another, in the order they were added
+    #[default]
+    Sequential,
+    /// Nodes start as soon as the nodes they depend on are done
+    ///
+    /// Dependencies follow from the declared inputs and outputs, so the
+    /// result matches a sequential run. Nodes sharing a key still run one
+    /// after another: most build nodes, JS script nodes included, read and
+    /// write `context`, so the build itself stays sequential and only nodes
+    /// like asset compression overlap with it. A node writing a key it does
+    /// not declare as an output fails the run. Needs the `concurrent`
+    /// feature, without it the nodes run sequentially.
+    Concurrent,
+}
+
 /// Pipeline - orchestrates execution of nodes
 pub struct Pipeline {
     name: String,
-    nodes: Vec<Box<dyn PipeNode + Send + Sync>>,
+    nodes: Vec<Arc<dyn PipeNode + Send + Sync>>,
     external_inputs: Vec<String>,
+    external_outputs: Vec<String>,
+    mode: ExecutionMode,
+    observers: Vec<Arc<dyn PipelineObserver>>,
 }
 
 impl Pipeline {
@@ -78,6 +140,9 @@ impl Pipeline {
             name: name.into(),
             nodes: Vec::new(),
             external_inputs: Vec::new(),
+            external_outputs: Vec::new(),
+            mode: ExecutionMode::default(),
+            observers: Vec::new(),
         }
     }
 
@@ -86,15 +151,300 @@ impl Pipeline {
         self
     }
 
+    /// Keys the caller reads from the result, these never count as unused
+    pub fn with_external_outputs(mut self, outputs: Vec<String>) -> Self {
+        self.external_outputs = outputs;
+        self
+    }
+
+    pub fn with_execution_mode(mut self, mode: ExecutionMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Add an observer, the same observer is only called once
+    pub fn with_observer(mut self, observer: Arc<dyn PipelineObserver>) -> Self {
+        if !self
+            .observers
+            .iter()
+            .any(|added| Arc::ptr_eq(added, &observer))
+        {
+            self.observers.push(observer);
+        }
+        self
+    }
+
     pub fn add_node(mut self, node: Box<dyn PipeNode + Send + Sync>) -> Result<Self> {
-        self.nodes.push(node);
+        self.nodes.push(Arc::from(node));
         Ok(self)
     }
 
-    pub async fn execute(&self, mut data: PipeMap) -> Result<PipeMap> {
+    /// Append the nodes and observers of another pipeline
+    ///
+    /// The external keys of `other` are not taken over.
+    pub fn append(mut self, other: Pipeline) -> Self {
+        self.nodes.extend(other.nodes);
+        for observer in other.observers {
+            self = self.with_observer(observer);
+        }
+        self
+    }
+
+    /// Indices of the earlier nodes each node has to wait for
+    ///
+    /// A node waits for an earlier one when it reads a key the earlier node
+    /// writes, or writes a key the earlier node reads or writes.
+    pub fn dependencies(&self) -> Vec<Vec<usize>> {
+        let keys = |entries: Vec<String>| -> HashSet<String> {
+            entries
+                .iter()
+                .map(|entry| parse_key(entry).0.to_string())
+                .collect()
+        };
+        let inputs: Vec<_> = self.nodes.iter().map(|node| keys(node.input())).collect();
+        let outputs: Vec<_> = self.nodes.iter().map(|node| keys(node.output())).collect();
+
+        (0..self.nodes.len())
+            .map(|later| {
+                (0..later)
+                    .filter(|&earlier| {
+                        !outputs[earlier].is_disjoint(&inputs[later])
+                            || !outputs[earlier].is_disjoint(&outputs[later])
+                            || !inputs[earlier].is_disjoint(&outputs[later])
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Check the declared inputs and outputs of the nodes
+    ///
+    /// Every required input has to be an external input or the output of an
+    /// earlier node. Returns the outputs nothing reads.
+    pub fn validate(&self) -> Result<Vec<UnusedOutput>> {
+        self.validate_with(self.external_inputs.iter().map(String::as_str))
+    }
+
+    fn validate_with<'a>(
+        &self,
+        available: impl IntoIterator<Item = &'a str>,
+    ) -> Result<Vec<UnusedOutput>> {
+        let mut available: HashSet<String> = available.into_iter().map(str::to_string).collect();
+
+        for node in &self.nodes {
+            for input in node.input() {
+                let (key, required) = parse_key(&input);
+                if required && !available.contains(key) {
+                    return Err(ProcessingError::MissingInput {
+                        node: node.name(),
+                        required: key.to_string(),
+                    }
+                    .into());
+                }
+            }
+            for output in node.output() {
+                available.insert(parse_key(&output).0.to_string());
+            }
+        }
+
+        let mut unused = Vec::new();
+        for (index, node) in self.nodes.iter().enumerate() {
+            for output in node.output() {
+                let key = parse_key(&output).0;
+                if !self.is_read_after(index, key) {
+                    unused.push(UnusedOutput {
+                        node: node.name(),
+                        key: key.to_string(),
+                    });
+                }
+            }
+        }
+
+        Ok(unused)
+    }
+
+    /// Whether the value a node writes to `key` is read by a later node or the caller
+    fn is_read_after(&self, index: usize, key: &str) -> bool {
+        for node in &self.nodes[index + 1..] {
+            if node.input().iter().any(|input| parse_key(input).0 == key) {
+                return true;
+            }
+            if node
+                .output()
+                .iter()
+                .any(|output| parse_key(output).0 == key)
+            {
+                return false;
+            }
+        }
+        self.external_outputs.iter().any(|output| output == key)
+    }
+
+    /// Run the nodes, after validating them against the given data
+    pub async fn execute(&self, data: PipeMap) -> Result<PipeMap> {
+        let unused = self.validate_with(
+            self.external_inputs
+                .iter()
+                .map(String::as_str)
+                .chain(data.keys()),
+        )?;
+        for output in unused {
+            debug!("Pipeline '{}': {}", self.name, output);
+        }
+
+        if self.observers.is_empty() {
+            return self.run(data).await;
+        }
+
+        for observer in &self.observers {
+            observer.pipeline_started(&self.name);
+        }
+        let start = Instant::now();
+        let result = self.run(data).await;
+        let elapsed = start.elapsed();
+        for observer in &self.observers {
+            observer.pipeline_finished(&self.name, elapsed, result.as_ref());
+        }
+        result
+    }
+
+    async fn run(&self, mut data: PipeMap) -> Result<PipeMap> {
+        #[cfg(feature = "concurrent")]
+        if self.mode == ExecutionMode::Concurrent {
+            return self.execute_concurrent(data).await;
+        }
+
         for node in &self.nodes {
-            data = node.process(data).await?;
+            data = run_node(&self.name, node.as_ref(), &self.observers, data).await?;
         }
         Ok(data)
     }
+
+    /// Run every node on the tokio runtime once its dependencies are done
+    ///
+    /// Each node gets the data as it is when the node starts, only its
+    /// declared outputs are merged back. Writes to other keys would be lost,
+    /// so they are reported as errors.
+    #[cfg(feature = "concurrent")]
+    async fn execute_concurrent(&self, mut data: PipeMap) -> Result<PipeMap> {
+        use crate::error::TweersError;
+        use tokio::task::JoinSet;
+
+        let dependencies = self.dependencies();
+        let mut started = vec![false; self.nodes.len()];
+        let mut done = vec![false; self.nodes.len()];
+        let mut running = JoinSet::new();
+
+        loop {
+            for (index, node) in self.nodes.iter().enumerate() {
+                if started[index] || !dependencies[index].iter().all(|&dep| done[dep]) {
+                    continue;
+                }
+                started[index] = true;
+                debug!("Pipeline '{}': starting {}", self.name, node.name());
+
+                let node = node.clone();
+                let input = data.clone();
+                let name = self.name.clone();
+                let observers = self.observers.clone();
+                running.spawn(async move {
+                    let before = input.clone();
+                    let result = run_node(&name, node.as_ref(), &observers, input)
+                        .await
+                        .and_then(|output| check_undeclared_writes(node.as_ref(), &before, output));
+                    (index, result)
+                });
+            }
+
+            let Some(joined) = running.join_next().await else {
+                break;
+            };
+            let (index, result) = joined
+                .map_err(|e| TweersError::other(format!("Pipeline node task failed: {}", e)))?;
+            let output = result?;
+
+            for key in self.nodes[index].output() {
+                let key = parse_key(&key).0;
+                match output.data.get(key) {
+                    Some(value) => data.data.insert(key.to_string(), value.clone()),
+                    None => data.data.remove(key),
+                };
+            }
+            done[index] = true;
+        }
+
+        Ok(data)
+    }
+}
+
+/// Fail when a node changed a key it does not declare as an output
+#[cfg(feature = "concurrent")]
+fn check_undeclared_writes(
+    node: &(dyn PipeNode + Send + Sync),
+    before: &PipeMap,
+    output: PipeMap,
+) -> Result<PipeMap> {
+    let declared: HashSet<String> = node
+        .output()
+        .iter()
+        .map(|entry| parse_key(entry).0.to_string())
+        .collect();
+
+    let mut undeclared: Vec<&str> = output
+        .data
+        .iter()
+        .filter(|(key, value)| {
+            !declared.contains(key.as_str())
+                && before
+                    .data
+                    .get(key.as_str())
+                    .is_none_or(|old| !Arc::ptr_eq(old, value))
+        })
+        .map(|(key, _)| key.as_str())
+        .collect();
+    undeclared.sort_unstable();
+
+    match undeclared.first() {
+        Some(key) => Err(ProcessingError::UndeclaredOutput {
+            node: node.name(),
+            key: key.to_string(),
+        }
+        .into()),
+        None => Ok(output),
+    }
+}
+
+/// Process data with a node in its span, calling the observers around it
+async fn run_node(
+    pipeline: &str,
+    node: &(dyn PipeNode + Send + Sync),
+    observers: &[Arc<dyn PipelineObserver>],
+    data: PipeMap,
+) -> Result<PipeMap> {
+    let name = node.name();
+    let span = tracing::debug_span!(
+        "pipeline_node",
+        pipeline,
+        node = name.as_str(),
+        elapsed_ms = tracing::field::Empty
+    );
+
+    async {
+        if observers.is_empty() {
+            return node.process(data).await;
+        }
+
+        for observer in observers {
+            observer.node_started(pipeline, &name);
+        }
+        let start = Instant::now();
+        let result = node.process(data).await;
+        let elapsed = start.elapsed();
+        for observer in observers {
+            observer.node_finished(pipeline, &name, elapsed, result.as_ref());
+        }
+        result
+    }
+    .instrument(span)
+    .await
 }
@@ -1,8 +1,10 @@
+use crate::analysis::Diagnostic;
 use crate::core::story::{Passage, StoryData};
 use indexmap::IndexMap;
 /// Type-safe keys for PipeMap
 use std::marker::PhantomData;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 /// A type-safe key for PipeMap that enforces compile-time type checking
 pub struct TypedKey<T> {
@@ -73,7 +75,10 @@ pub const FAST_COMPRESSION: TypedKey<bool> = TypedKey::new("fast_compression");
 pub const CONTEXT: TypedKey<crate::commands::BuildContext> = TypedKey::new("context");
 
 /// All passages map
-pub const ALL_PASSAGES: TypedKey<IndexMap<String, Passage>> = TypedKey::new("all_passages");
+///
+/// The map is shared with the build context between rebuilds, nodes that
+/// change passages insert a new map instead of changing it in place.
+pub const ALL_PASSAGES: TypedKey<Arc<IndexMap<String, Passage>>> = TypedKey::new("all_passages");
 
 /// Story data
 pub const STORY_DATA: TypedKey<Option<StoryData>> = TypedKey::new("story_data");
@@ -85,3 +90,6 @@ pub const PARSED_DATA: TypedKey<Vec<ParsedDataEntry>> = TypedKey::new("parsed_da
 
 /// Asset file map: (local path, archive path) for pack
 pub const ASSET_FILE_MAP: TypedKey<Vec<(PathBuf, String)>> = TypedKey::new("asset_file_map");
+
+/// Lint diagnostics
+pub const DIAGNOSTICS: TypedKey<Vec<Diagnostic>> = TypedKey::new("diagnostics");
@@ -4,10 +4,12 @@
 pub mod builder;
 pub mod core;
 pub mod keys;
+pub mod observer;
 pub mod registry;
 
 // Re-export core types
 pub use builder::*;
 pub use core::*;
 pub use keys::*;
+pub use observer::*;
 pub use registry::*;
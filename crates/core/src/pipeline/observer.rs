@@ -0,0 +1,191 @@
+// Pipeline observers - hooks around node execution
+
+use super::core::PipeMap;
+use super::keys::{ALL_PASSAGES, FILES, HTML_CONTENT, MODIFIED_FILES};
+use crate::error::TweersError;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::{Span, debug};
+
+/// Outcome of a node or pipeline run as seen by observers
+pub type RunResult<'a> = std::result::Result<&'a PipeMap, &'a TweersError>;
+
+/// Hooks called by `Pipeline::execute` around the pipeline and every node
+///
+/// Nodes may run concurrently, so hooks of different nodes can interleave.
+pub trait PipelineObserver: Send + Sync {
+    fn pipeline_started(&self, _pipeline: &str) {}
+
+    fn node_started(&self, _pipeline: &str, _node: &str) {}
+
+    fn node_finished(&self, _pipeline: &str, _node: &str, _elapsed: Duration, _result: RunResult) {}
+
+    fn pipeline_finished(&self, _pipeline: &str, _elapsed: Duration, _result: RunResult) {}
+}
+
+/// Observer that logs every node run with its elapsed time
+///
+/// Nodes run inside a `pipeline_node` span, so the events of a node, this
+/// observer's included, are attributed to it. The span records the elapsed
+/// time when the node finishes.
+#[derive(Default)]
+pub struct TracingObserver;
+
+impl TracingObserver {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl PipelineObserver for TracingObserver {
+    fn node_finished(&self, _pipeline: &str, node: &str, elapsed: Duration, result: RunResult) {
+        let elapsed_ms = millis(elapsed);
+        Span::current().record("elapsed_ms", elapsed_ms);
+        match result {
+            Ok(_) => debug!("{} finished in {:.1} ms", node, elapsed_ms),
+            Err(e) => debug!("{} failed after {:.1} ms: {}", node, elapsed_ms, e),
+        }
+    }
+}
+
+/// Wall time of one node run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeTiming {
+    pub node: String,
+    pub millis: f64,
+}
+
+/// Timings and sizes of one pipeline run
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TimingReport {
+    pub pipeline: String,
+    pub total_millis: f64,
+    /// Nodes in the order they finished
+    pub nodes: Vec<NodeTiming>,
+    pub files_collected: Option<usize>,
+    pub files_changed: Option<usize>,
+    pub passages: Option<usize>,
+    pub html_bytes: Option<usize>,
+}
+
+impl TimingReport {
+    /// Take the counts from the data a node produced
+    fn record_counts(&mut self, data: &PipeMap) {
+        if let Some(files) = data.get_typed(FILES) {
+            self.files_collected = Some(files.len());
+        }
+        if let Some(files) = data.get_typed(MODIFIED_FILES) {
+            self.files_changed = Some(files.len());
+        }
+        if let Some(passages) = data.get_typed(ALL_PASSAGES) {
+            self.passages = Some(passages.len());
+        }
+        if let Some(html) = data.get_typed(HTML_CONTENT) {
+            self.html_bytes = Some(html.len());
+        }
+    }
+}
+
+impl fmt::Display for TimingReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}: {:.1} ms", self.pipeline, self.total_millis)?;
+
+        let width = self
+            .nodes
+            .iter()
+            .map(|timing| timing.node.len())
+            .max()
+            .unwrap_or(0);
+        for timing in &self.nodes {
+            writeln!(
+                f,
+                "  {:<width$}  {:>10.1} ms",
+                timing.node,
+                timing.millis,
+                width = width
+            )?;
+        }
+
+        let counts = [
+            ("files collected", self.files_collected),
+            ("files changed", self.files_changed),
+            ("passages", self.passages),
+            ("HTML bytes", self.html_bytes),
+        ];
+        let counts: Vec<String> = counts
+            .iter()
+            .filter_map(|(label, count)| count.map(|count| format!("{}: {}", label, count)))
+            .collect();
+        if !counts.is_empty() {
+            writeln!(f, "  {}", counts.join(", "))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Callback receiving the report of every finished pipeline run
+pub type TimingReporter = Box<dyn Fn(&TimingReport) + Send + Sync>;
+
+/// Observer collecting a `TimingReport` per pipeline run
+#[derive(Default)]
+pub struct TimingsObserver {
+    report: Mutex<TimingReport>,
+    reporter: Option<TimingReporter>,
+}
+
+impl TimingsObserver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Call `reporter` with the report whenever a pipeline run finishes
+    pub fn with_reporter(reporter: impl Fn(&TimingReport) + Send + Sync + 'static) -> Self {
+        Self {
+            report: Mutex::default(),
+            reporter: Some(Box::new(reporter)),
+        }
+    }
+
+    /// Report of the last (or still running) pipeline run
+    pub fn report(&self) -> TimingReport {
+        self.report.lock().unwrap().clone()
+    }
+}
+
+impl PipelineObserver for TimingsObserver {
+    fn pipeline_started(&self, pipeline: &str) {
+        *self.report.lock().unwrap() = TimingReport {
+            pipeline: pipeline.to_string(),
+            ..TimingReport::default()
+        };
+    }
+
+    fn node_finished(&self, _pipeline: &str, node: &str, elapsed: Duration, result: RunResult) {
+        let mut report = self.report.lock().unwrap();
+        report.nodes.push(NodeTiming {
+            node: node.to_string(),
+            millis: millis(elapsed),
+        });
+        if let Ok(data) = result {
+            report.record_counts(data);
+        }
+    }
+
+    fn pipeline_finished(&self, _pipeline: &str, elapsed: Duration, _result: RunResult) {
+        let report = {
+            let mut report = self.report.lock().unwrap();
+            report.total_millis = millis(elapsed);
+            report.clone()
+        };
+        if let Some(reporter) = &self.reporter {
+            reporter(&report);
+        }
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
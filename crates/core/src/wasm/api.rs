@@ -1,8 +1,31 @@
 // WASM API bindings - JavaScript-callable functions
 
-use super::types::{JsBuildConfig, JsBuildOutput, JsHtmlParseOutput, JsParseOutput};
+use super::types::{JsBuildConfig, JsBuildOutput, JsHtmlParseOutput, JsParseError, JsParseOutput};
 use wasm_bindgen::prelude::*;
 
+/// Convert an API error into a JsValue
+///
+/// Located parse errors become a `JsParseError` object, anything else a string.
+fn to_js_error(context: &str, error: Box<dyn std::error::Error + Send + Sync>) -> JsValue {
+    let message = format!("{}: {}", context, error);
+
+    match error.downcast::<crate::error::ParseDiagnostics>() {
+        Ok(diagnostics) => {
+            let js_error = JsParseError {
+                message,
+                diagnostics: diagnostics
+                    .diagnostics
+                    .into_iter()
+                    .map(Into::into)
+                    .collect(),
+            };
+            serde_wasm_bindgen::to_value(&js_error)
+                .unwrap_or_else(|e| JsValue::from_str(&format!("Failed to serialize error: {}", e)))
+        }
+        Err(_) => JsValue::from_str(&message),
+    }
+}
+
 /// Build a Twee story from the given configuration
 ///
 /// # Arguments
@@ -12,7 +35,8 @@ use wasm_bindgen::prelude::*;
 /// * `JsBuildOutput` - Contains the generated HTML
 ///
 /// # Errors
-/// Returns a JsValue error if the build fails
+/// Returns a JsValue error if the build fails, a `JsParseError` object when a
+/// source fails to parse
 #[wasm_bindgen]
 pub fn build(config_js: JsValue) -> Result<JsBuildOutput, JsValue> {
     // Convert JS value to Rust type
@@ -23,8 +47,7 @@ pub fn build(config_js: JsValue) -> Result<JsBuildOutput, JsValue> {
     let config: crate::api::BuildConfig = js_config.into();
 
     // Call the core build function
-    let output = crate::api::build(config)
-        .map_err(|e| JsValue::from_str(&format!("Build failed: {}", e)))?;
+    let output = crate::api::build(config).map_err(|e| to_js_error("Build failed", e))?;
 
     // Convert output to JS-friendly type
     Ok(JsBuildOutput::new(output.html))
@@ -39,7 +62,8 @@ pub fn build(config_js: JsValue) -> Result<JsBuildOutput, JsValue> {
 /// * `JsValue` - Contains passages, story data, and format info (with empty source)
 ///
 /// # Errors
-/// Returns a JsValue error if parsing fails
+/// Returns a `JsParseError` object when a source fails to parse, a JsValue
+/// error for other failures
 #[wasm_bindgen]
 pub fn parse(sources_js: JsValue) -> Result<JsValue, JsValue> {
     use super::types::JsInputSource;
@@ -52,8 +76,7 @@ pub fn parse(sources_js: JsValue) -> Result<JsValue, JsValue> {
     let sources: Vec<crate::api::InputSource> = js_sources.into_iter().map(|s| s.into()).collect();
 
     // Call the core parse function
-    let output = crate::api::parse(sources)
-        .map_err(|e| JsValue::from_str(&format!("Parse failed: {}", e)))?;
+    let output = crate::api::parse(sources).map_err(|e| to_js_error("Parse failed", e))?;
 
     // Convert output to JS-friendly type
     let js_output: JsParseOutput = output.into();
@@ -125,8 +148,7 @@ pub fn passages(sources_js: JsValue) -> Result<JsValue, JsValue> {
 
     let sources: Vec<crate::api::InputSource> = js_sources.into_iter().map(|s| s.into()).collect();
 
-    let output = crate::api::passages(sources)
-        .map_err(|e| JsValue::from_str(&format!("Parse failed: {}", e)))?;
+    let output = crate::api::passages(sources).map_err(|e| to_js_error("Parse failed", e))?;
 
     let js_passages: std::collections::HashMap<String, JsPassage> =
         output.into_iter().map(|(k, v)| (k, v.into())).collect();
@@ -27,6 +27,46 @@ export interface JsBuildConfig {
   start_passage?: string;
 }
 
+/**
+ * Location in a source file
+ */
+export interface JsPosition {
+  /** 1-based line */
+  line: number;
+  /** 1-based column, counted in characters */
+  column: number;
+  /** Byte offset from the start of the file, use `utf16_offset` to index JavaScript strings */
+  offset: number;
+  /** 1-based column, counted in UTF-16 code units */
+  utf16_column: number;
+  /** UTF-16 offset from the start of the file, a JavaScript string index */
+  utf16_offset: number;
+}
+
+/**
+ * Range of a source file, the end is exclusive
+ */
+export interface JsSpan {
+  start: JsPosition;
+  end: JsPosition;
+}
+
+/**
+ * Where the parts of a passage are in its source file
+ */
+export interface JsPassageSpans {
+  /** The whole header line, from `::` to the end of the line */
+  header: JsSpan;
+  /** The passage name as written, including escapes */
+  name: JsSpan;
+  /** The tag block, including the brackets */
+  tags?: JsSpan;
+  /** The metadata block, including the braces */
+  metadata?: JsSpan;
+  /** The passage content without surrounding whitespace */
+  body: JsSpan;
+}
+
 /**
  * Passage data
  */
@@ -38,6 +78,8 @@ export interface JsPassage {
   content: string;
   source_file?: string;
   source_line?: number;
+  /** Only set for passages parsed from Twee sources */
+  spans?: JsPassageSpans;
 }
 
 /**
@@ -72,6 +114,30 @@ export interface JsHtmlParseOutput {
   is_debug: boolean;
 }
 
+/**
+ * Parse error located in a source
+ */
+export interface JsParseDiagnostic {
+  file?: string;
+  /** 1-based line */
+  line: number;
+  /** 1-based column, counted in characters */
+  column: number;
+  /** UTF-16 offset where the error span starts, a JavaScript string index */
+  start: number;
+  /** UTF-16 offset where the error span ends, a JavaScript string index */
+  end: number;
+  message: string;
+}
+
+/**
+ * Error thrown when sources fail to parse
+ */
+export interface JsParseError {
+  message: string;
+  diagnostics: JsParseDiagnostic[];
+}
+
 /**
  * Build output containing generated HTML
  */
@@ -86,7 +152,7 @@ export class JsBuildOutput {
  *
  * @param config_js - Build configuration
  * @returns Build output containing HTML
- * @throws Error if build fails
+ * @throws JsParseError if a source fails to parse, Error if build fails
  */
 export function build(config_js: JsBuildConfig): JsBuildOutput;
 
@@ -95,7 +161,7 @@ export function build(config_js: JsBuildConfig): JsBuildOutput;
  *
  * @param sources_js - Array of input sources
  * @returns Parsed passages, story data, and format info (with empty source)
- * @throws Error if parsing fails
+ * @throws JsParseError if a source fails to parse, Error for other failures
  */
 export function parse(sources_js: JsInputSource[]): JsParseOutput;
 
@@ -111,6 +177,8 @@ export function build_from_parsed(parsed_js: JsParseOutput): JsBuildOutput;
 /**
  * Parse passages only - does not require StoryData
  * Useful for IDE integration where individual files need to be parsed
+ *
+ * @throws JsParseError if a source fails to parse, Error for other failures
  */
 export function passages(sources_js: JsInputSource[]): Map<string, JsPassage>;
 
@@ -48,6 +48,8 @@ pub struct JsPassage {
     pub source_file: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source_line: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spans: Option<crate::core::span::PassageSpans>,
 }
 
 /// JavaScript-friendly StoryData
@@ -81,6 +83,26 @@ pub struct JsHtmlParseOutput {
     pub is_debug: bool,
 }
 
+/// JavaScript-friendly located parse error
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsParseDiagnostic {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub line: u32,
+    pub column: u32,
+    /// UTF-16 offsets of the error span, usable as JavaScript string indices
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+/// JavaScript-friendly error thrown when sources fail to parse
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsParseError {
+    pub message: String,
+    pub diagnostics: Vec<JsParseDiagnostic>,
+}
+
 /// JavaScript-friendly build output
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[wasm_bindgen]
@@ -166,6 +188,7 @@ impl From<crate::core::story::Passage> for JsPassage {
             content: passage.content,
             source_file: passage.source_file,
             source_line: passage.source_line,
+            spans: passage.spans,
         }
     }
 }
@@ -180,6 +203,7 @@ impl From<JsPassage> for crate::core::story::Passage {
             content: js_passage.content,
             source_file: js_passage.source_file,
             source_line: js_passage.source_line,
+            spans: js_passage.spans,
         }
     }
 }
@@ -212,6 +236,19 @@ impl From<JsStoryData> for crate::core::story::StoryData {
     }
 }
 
+impl From<crate::error::ParseDiagnostic> for JsParseDiagnostic {
+    fn from(diagnostic: crate::error::ParseDiagnostic) -> Self {
+        JsParseDiagnostic {
+            file: diagnostic.file,
+            line: diagnostic.line,
+            column: diagnostic.column,
+            start: diagnostic.utf16_range.start,
+            end: diagnostic.utf16_range.end,
+            message: diagnostic.message,
+        }
+    }
+}
+
 impl From<crate::api::ParseOutput> for JsParseOutput {
     fn from(parse_output: crate::api::ParseOutput) -> Self {
         let passages: std::collections::HashMap<String, JsPassage> = parse_output
@@ -179,6 +179,51 @@ Custom start passage
     assert_eq!(output.story_data.start, Some("CustomStart".to_string()));
 }
 
+#[test]
+fn test_start_falls_back_to_first_story_passage() {
+    use tweers_core::api::{BuildConfig, InputSource, StoryFormatInfo, build};
+
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let test_dir = manifest_dir.parent().unwrap().parent().unwrap();
+    let format_file = test_dir.join("test/story-format/sugarcube-2.37.3/format.js");
+    let format_source = fs::read_to_string(&format_file).expect("Failed to read format file");
+
+    let content = r#":: StoryData
+{
+    "ifid": "12345678-1234-1234-1234-123456789012",
+    "format": "SugarCube",
+    "format-version": "2.37.3"
+}
+
+:: StoryTitle
+Test
+
+:: Styles [stylesheet]
+body {}
+
+:: Intro
+First story passage
+"#;
+
+    let sources = vec![InputSource::Text {
+        name: "test.twee".to_string(),
+        content: content.to_string(),
+    }];
+    let format_info = StoryFormatInfo {
+        name: "SugarCube".to_string(),
+        version: "2.37.3".to_string(),
+        source: format_source,
+    };
+
+    let output = build(BuildConfig::new(format_info).sources(sources)).expect("Build failed");
+    assert!(output.html.contains(r#"startnode="1""#));
+    assert!(
+        output
+            .html
+            .contains(r#"<tw-passagedata pid="1" name="Intro""#)
+    );
+}
+
 #[test]
 fn test_parse_api() {
     use tweers_core::api::{InputSource, parse};
@@ -551,3 +596,84 @@ Talk to me
             .contains("color: blue;")
     );
 }
+
+#[test]
+fn test_cached_generation_matches_full_generation() {
+    use tweers_core::core::file::parse_text_content;
+    use tweers_core::core::output::{HtmlCache, HtmlOutputHandler};
+    use tweers_core::core::story::StoryFormat;
+
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let test_dir = manifest_dir.parent().unwrap().parent().unwrap();
+    let format_source =
+        fs::read_to_string(test_dir.join("test/story-format/sugarcube-2.37.3/format.js")).unwrap();
+    let sugarcube = StoryFormat::parse(&format_source).unwrap();
+    let mut other_format = sugarcube.clone();
+    other_format.source = "<html><title>{{STORY_NAME}}</title><body>{{STORY_DATA}}</body><!-- {{STORY_NAME}} --></html>".to_string();
+
+    let parsed = parse_text_content(
+        "test.twee",
+        r#":: StoryData
+{
+    "ifid": "12345678-1234-1234-1234-123456789012",
+    "format": "SugarCube",
+    "format-version": "2.37.3"
+}
+
+:: StoryTitle
+Cache Test
+
+:: Start
+Hello <b>"world"</b>
+
+:: Second [tag1]
+Another passage
+
+:: Body [html]
+<div id="extra"></div>
+"#,
+    )
+    .unwrap();
+    let mut passages = parsed.passages;
+    let mut story_data = parsed.story_data;
+    story_data.as_mut().unwrap().name = Some("Cache Test".to_string());
+
+    let mut cache = HtmlCache::default();
+    let mut check = |passages: &_, story_format: &StoryFormat| {
+        let cached = HtmlOutputHandler::generate_html_cached(
+            passages,
+            &story_data,
+            story_format,
+            false,
+            &mut cache,
+        )
+        .unwrap();
+        let full =
+            HtmlOutputHandler::generate_html(passages, &story_data, story_format, false).unwrap();
+        assert_eq!(cached, full);
+        cached
+    };
+
+    check(&passages, &sugarcube);
+    assert!(check(&passages, &sugarcube).contains("Hello &lt;b&gt;&quot;world&quot;&lt;/b&gt;"));
+
+    // Edited, added and removed passages, pids shift with them
+    passages.get_mut("Start").unwrap().content = "Edited".to_string();
+    passages.get_mut("Second").unwrap().tags = Some("tag2".to_string());
+    let mut third = passages["Second"].clone();
+    third.name = "Third".to_string();
+    passages.insert("Third".to_string(), third);
+    let html = check(&passages, &sugarcube);
+    assert!(html.contains(r#"<tw-passagedata pid="3" name="Third""#));
+
+    passages.shift_remove("Second");
+    let html = check(&passages, &sugarcube);
+    assert!(html.contains(r#"<tw-passagedata pid="2" name="Third""#));
+    assert!(!html.contains(r#"name="Second""#));
+
+    // A different format source replaces the template
+    let html = check(&passages, &other_format);
+    assert!(html.starts_with("<html><title>Cache Test</title><body>\n<div id=\"extra\">"));
+    assert!(html.ends_with("<!-- Cache Test --></html>"));
+    check(&passages, &sugarcube);
+}
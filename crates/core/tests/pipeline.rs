@@ -0,0 +1,122 @@
+// Unit tests for Pipeline validation and node dependencies
+use async_trait::async_trait;
+use tweers_core::error::Result;
+use tweers_core::pipeline::{PipeMap, PipeNode, Pipeline, UnusedOutput};
+
+// Node declaring fixed inputs and outputs
+struct KeyNode {
+    name: &'static str,
+    input: Vec<&'static str>,
+    output: Vec<&'static str>,
+}
+
+impl KeyNode {
+    fn boxed(name: &'static str, input: &[&'static str], output: &[&'static str]) -> Box<Self> {
+        Box::new(Self {
+            name,
+            input: input.to_vec(),
+            output: output.to_vec(),
+        })
+    }
+}
+
+#[async_trait]
+impl PipeNode for KeyNode {
+    fn name(&self) -> String {
+        self.name.to_string()
+    }
+
+    fn input(&self) -> Vec<String> {
+        self.input.iter().map(|key| key.to_string()).collect()
+    }
+
+    fn output(&self) -> Vec<String> {
+        self.output.iter().map(|key| key.to_string()).collect()
+    }
+
+    async fn process(&self, data: PipeMap) -> Result<PipeMap> {
+        Ok(data)
+    }
+}
+
+#[test]
+fn test_validate_accepts_ordered_nodes() {
+    let pipeline = Pipeline::new("test")
+        .with_external_inputs(vec!["sources".to_string()])
+        .with_external_outputs(vec!["html".to_string()])
+        .add_node(KeyNode::boxed(
+            "Collect",
+            &["sources", "base64?"],
+            &["files"],
+        ))
+        .unwrap()
+        .add_node(KeyNode::boxed("Render", &["files"], &["html"]))
+        .unwrap();
+
+    assert_eq!(pipeline.validate().unwrap(), vec![]);
+}
+
+#[test]
+fn test_validate_reports_missing_input() {
+    let pipeline = Pipeline::new("test")
+        .with_external_inputs(vec!["sources".to_string()])
+        .add_node(KeyNode::boxed("Render", &["passages"], &[]))
+        .unwrap()
+        .add_node(KeyNode::boxed("Aggregate", &["sources"], &["passages"]))
+        .unwrap();
+
+    let err = pipeline.validate().unwrap_err().to_string();
+    assert!(err.contains("'passages'"), "{err}");
+    assert!(err.contains("'Render'"), "{err}");
+}
+
+#[test]
+fn test_validate_reports_unused_outputs() {
+    let pipeline = Pipeline::new("test")
+        .add_node(KeyNode::boxed("First", &[], &["html", "report"]))
+        .unwrap()
+        .add_node(KeyNode::boxed("Second", &[], &["html"]))
+        .unwrap();
+
+    assert_eq!(
+        pipeline.validate().unwrap(),
+        vec![
+            UnusedOutput {
+                node: "First".to_string(),
+                key: "html".to_string(),
+            },
+            UnusedOutput {
+                node: "First".to_string(),
+                key: "report".to_string(),
+            },
+            UnusedOutput {
+                node: "Second".to_string(),
+                key: "html".to_string(),
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_dependencies_follow_declared_keys() {
+    let pipeline = Pipeline::new("test")
+        .add_node(KeyNode::boxed(
+            "Compress",
+            &["assets_dirs"],
+            &["asset_file_map"],
+        ))
+        .unwrap()
+        .add_node(KeyNode::boxed("Collect", &["sources"], &["files"]))
+        .unwrap()
+        .add_node(KeyNode::boxed("Render", &["files"], &["html"]))
+        .unwrap()
+        .add_node(KeyNode::boxed("Archive", &["html", "asset_file_map?"], &[]))
+        .unwrap()
+        .add_node(KeyNode::boxed("Rewrite", &[], &["files"]))
+        .unwrap();
+
+    assert_eq!(
+        pipeline.dependencies(),
+        vec![vec![], vec![], vec![1], vec![0, 2], vec![1, 2]]
+    );
+}
@@ -1,5 +1,5 @@
 use crate::error::{JSResult, ScriptError};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::debug;
 
 #[derive(Debug, Clone)]
@@ -25,6 +25,8 @@ impl Default for ScriptConfig {
 #[derive(Debug, Clone)]
 pub struct ScriptManager {
     pub scripts_dir: PathBuf,
+    /// Further directories searched after `scripts_dir`
+    pub extra_dirs: Vec<PathBuf>,
     pub data_scripts: Vec<PathBuf>,
     pub html_scripts: Vec<PathBuf>,
 }
@@ -33,6 +35,7 @@ impl ScriptManager {
     pub fn new(config: ScriptConfig) -> JSResult<Self> {
         let mut manager = Self {
             scripts_dir: config.scripts_dir,
+            extra_dirs: Vec::new(),
             data_scripts: Vec::new(),
             html_scripts: Vec::new(),
         };
@@ -48,35 +51,16 @@ impl ScriptManager {
         self.data_scripts.clear();
         self.html_scripts.clear();
 
-        if !self.scripts_dir.exists() {
-            debug!("Scripts directory does not exist: {:?}", self.scripts_dir);
-            return Ok(());
-        }
-
-        let data_dir = self.scripts_dir.join("data");
-        if data_dir.exists() && data_dir.is_dir() {
-            for entry in std::fs::read_dir(&data_dir)? {
-                let entry = entry?;
-                let path = entry.path();
-
-                if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("js") {
-                    debug!("Found data script: {:?}", path);
-                    self.data_scripts.push(path);
-                }
+        for scripts_dir in self.scripts_dirs() {
+            if !scripts_dir.exists() {
+                debug!("Scripts directory does not exist: {:?}", scripts_dir);
+                continue;
             }
-        }
-
-        let html_dir = self.scripts_dir.join("html");
-        if html_dir.exists() && html_dir.is_dir() {
-            for entry in std::fs::read_dir(&html_dir)? {
-                let entry = entry?;
-                let path = entry.path();
 
-                if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("js") {
-                    debug!("Found HTML script: {:?}", path);
-                    self.html_scripts.push(path);
-                }
-            }
+            self.data_scripts
+                .extend(find_scripts(&scripts_dir.join("data"), "data")?);
+            self.html_scripts
+                .extend(find_scripts(&scripts_dir.join("html"), "HTML")?);
         }
 
         self.data_scripts.sort();
@@ -91,6 +75,20 @@ impl ScriptManager {
         Ok(())
     }
 
+    /// Also use the scripts of `dir`
+    pub fn add_scripts_dir(&mut self, dir: PathBuf) -> JSResult<()> {
+        self.extra_dirs.push(dir);
+        self.discover_scripts()
+    }
+
+    /// Every directory scripts are discovered in
+    pub fn scripts_dirs(&self) -> Vec<PathBuf> {
+        std::iter::once(&self.scripts_dir)
+            .chain(&self.extra_dirs)
+            .cloned()
+            .collect()
+    }
+
     pub fn has_data_scripts(&self) -> bool {
         !self.data_scripts.is_empty()
     }
@@ -144,6 +142,25 @@ impl ScriptManager {
     }
 }
 
+/// `.js` files directly in `dir`
+fn find_scripts(dir: &Path, kind: &str) -> JSResult<Vec<PathBuf>> {
+    let mut scripts = Vec::new();
+    if !dir.is_dir() {
+        return Ok(scripts);
+    }
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+
+        if path.is_file() && path.extension().and_then(|s| s.to_str()) == Some("js") {
+            debug!("Found {} script: {:?}", kind, path);
+            scripts.push(path);
+        }
+    }
+    Ok(scripts)
+}
+
 impl Default for ScriptManager {
     fn default() -> Self {
         Self::new(ScriptConfig::default()).expect("Failed to create default ScriptManager")
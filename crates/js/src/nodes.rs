@@ -2,7 +2,9 @@ use crate::engine::ScriptEngine;
 use crate::error::ScriptError;
 use crate::manager::ScriptManager;
 use async_trait::async_trait;
+use indexmap::IndexMap;
 use serde_json::json;
+use std::sync::Arc;
 use tracing::{debug, error, info, warn};
 use tweers_core::error::{Result, TweersError};
 use tweers_core::pipeline::{PipeMap, PipeNode};
@@ -56,7 +58,7 @@ impl PipeNode for DataProcessorNode {
             return Ok(data);
         }
 
-        let mut current_passages = passages.clone();
+        let mut current_passages = IndexMap::clone(passages);
         let current_story_data = story_data.clone();
 
         for script_path in self.script_manager.get_data_scripts() {
@@ -97,7 +99,10 @@ impl PipeNode for DataProcessorNode {
             "{} data scripts executed",
             self.script_manager.get_data_scripts().len()
         );
-        data.insert_typed(tweers_core::pipeline::ALL_PASSAGES, current_passages);
+        data.insert_typed(
+            tweers_core::pipeline::ALL_PASSAGES,
+            Arc::new(current_passages),
+        );
         data.insert_typed(tweers_core::pipeline::STORY_DATA, current_story_data);
         Ok(data)
     }
@@ -155,7 +160,7 @@ impl PipeNode for HtmlProcessorNode {
             match tokio::fs::read_to_string(script_path).await {
                 Ok(script_content) => {
                     let mut engine = ScriptEngine::new()?;
-                    let passages_json = serde_json::to_string(&passages)?;
+                    let passages_json = serde_json::to_string(&**passages)?;
                     let format_info = json!({
                         "name": context.format_name,
                         "version": context.format_version
@@ -0,0 +1,60 @@
+//! Conversion between byte offsets and LSP positions
+//!
+//! LSP columns count UTF-16 code units, while spans and diagnostics from the
+//! parser count bytes or characters.
+
+use lsp_types::{Position, Range};
+use tweers_core::core::span::LineIndex;
+
+/// LSP position of a byte offset
+pub fn position(text: &str, index: &LineIndex, offset: usize) -> Position {
+    let (line, _) = index.line_col(text, offset);
+    let start = index.line_start(line);
+    let character = text
+        .get(start..offset.min(text.len()))
+        .map_or(0, |prefix| prefix.encode_utf16().count());
+
+    Position::new(line - 1, character as u32)
+}
+
+/// LSP range of a byte range
+pub fn range(text: &str, index: &LineIndex, range: std::ops::Range<usize>) -> Range {
+    Range::new(
+        position(text, index, range.start),
+        position(text, index, range.end),
+    )
+}
+
+/// Byte offset of an LSP position, clamped to the end of its line
+pub fn offset(text: &str, index: &LineIndex, position: Position) -> usize {
+    let start = index.line_start(position.line + 1);
+    let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+
+    let mut units = 0;
+    for (i, ch) in text[start..end].char_indices() {
+        if units >= position.character as usize {
+            return start + i;
+        }
+        units += ch.len_utf16();
+    }
+
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{offset, position};
+    use lsp_types::Position;
+    use tweers_core::core::span::LineIndex;
+
+    #[test]
+    fn test_utf16_round_trip() {
+        let text = ":: Start\n😀 [[Next]]\n";
+        let index = LineIndex::new(text);
+        let link = text.find("[[").unwrap();
+
+        assert_eq!(position(text, &index, link), Position::new(1, 3));
+        assert_eq!(offset(text, &index, Position::new(1, 3)), link);
+        assert_eq!(offset(text, &index, Position::new(0, 99)), 8);
+    }
+}
@@ -0,0 +1,31 @@
+//! Language server for Twee sources
+//!
+//! Runs over stdio and keeps every source of the workspace parsed, so editors
+//! get diagnostics, completion, navigation and rename from the same parser
+//! as the build.
+
+pub mod convert;
+pub mod server;
+pub mod workspace;
+
+pub use server::Server;
+pub use workspace::Workspace;
+
+use lsp_server::Connection;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+
+/// Run the language server over stdin/stdout until the client exits
+pub fn run() -> Result<(), Error> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+/// Run the language server on an existing connection
+pub fn serve(connection: Connection) -> Result<(), Error> {
+    let mut server = Server::new(connection)?;
+    server.initialize()?;
+    server.main_loop()
+}
@@ -0,0 +1,603 @@
+//! Request and notification handling
+
+use crate::Error;
+use crate::convert;
+use crate::workspace::{Document, Workspace};
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidChangeWatchedFiles, DidCloseTextDocument, DidOpenTextDocument,
+    Notification as LspNotification, PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, GotoDefinition, HoverRequest, References, RegisterCapability, Rename,
+    Request as LspRequest,
+};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    CompletionTextEdit, Diagnostic, DiagnosticSeverity, DidChangeWatchedFilesRegistrationOptions,
+    FileSystemWatcher, GlobPattern, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+    HoverContents, HoverParams, HoverProviderCapability, InitializeParams, InitializeResult,
+    Location, MarkupContent, MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams,
+    ReferenceParams, Registration, RegistrationParams, RenameParams, ServerCapabilities,
+    ServerInfo, TextDocumentPositionParams, TextDocumentSyncCapability, TextDocumentSyncKind,
+    TextEdit, Url, WorkspaceEdit,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tokio::runtime::Runtime;
+use tracing::{debug, info};
+use tweers_core::analysis::is_special_passage;
+use tweers_core::core::story::Passage;
+
+/// Lines of passage content shown in hover previews
+const PREVIEW_LINES: usize = 12;
+
+/// Sources the workspace loads, watched for changes made outside the editor
+const WATCHED_SOURCES: &str = "**/*.{twee,tw,js,css,xlsx,xlsm,xlsb,xls}";
+
+/// Id of the file watcher registration, also used for its request
+const WATCHER_ID: &str = "tweers/watched-sources";
+
+type HandlerResult<T> = std::result::Result<T, String>;
+
+pub struct Server {
+    connection: Connection,
+    runtime: Runtime,
+    workspace: Workspace,
+}
+
+impl Server {
+    pub fn new(connection: Connection) -> Result<Self, Error> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        Ok(Self {
+            connection,
+            runtime,
+            workspace: Workspace::new(),
+        })
+    }
+
+    pub fn capabilities() -> ServerCapabilities {
+        ServerCapabilities {
+            text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+            completion_provider: Some(CompletionOptions {
+                trigger_characters: Some(vec!["[".into(), "|".into(), ">".into()]),
+                ..Default::default()
+            }),
+            definition_provider: Some(OneOf::Left(true)),
+            references_provider: Some(OneOf::Left(true)),
+            hover_provider: Some(HoverProviderCapability::Simple(true)),
+            rename_provider: Some(OneOf::Left(true)),
+            ..Default::default()
+        }
+    }
+
+    /// Answer the initialize request and load the workspace sources
+    pub fn initialize(&mut self) -> Result<(), Error> {
+        let (id, params) = self.connection.initialize_start()?;
+        let params: InitializeParams = serde_json::from_value(params)?;
+
+        let result = InitializeResult {
+            capabilities: Self::capabilities(),
+            server_info: Some(ServerInfo {
+                name: "tweers".to_string(),
+                version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            }),
+        };
+        self.connection
+            .initialize_finish(id, serde_json::to_value(result)?)?;
+
+        let roots = workspace_roots(&params);
+        info!("Loading workspace from {:?}", roots);
+        self.runtime.block_on(self.workspace.load(&roots));
+        if can_watch_files(&params) {
+            self.register_file_watcher()?;
+        }
+        let paths = self.workspace.documents().map(|(path, _)| path.clone());
+        self.publish_diagnostics(paths.collect())
+    }
+
+    /// Ask the client to report sources created, changed or removed on disk
+    fn register_file_watcher(&self) -> Result<(), Error> {
+        let options = DidChangeWatchedFilesRegistrationOptions {
+            watchers: vec![FileSystemWatcher {
+                glob_pattern: GlobPattern::String(WATCHED_SOURCES.to_string()),
+                kind: None,
+            }],
+        };
+        let params = RegistrationParams {
+            registrations: vec![Registration {
+                id: WATCHER_ID.to_string(),
+                method: DidChangeWatchedFiles::METHOD.to_string(),
+                register_options: Some(serde_json::to_value(options)?),
+            }],
+        };
+
+        self.connection.sender.send(Message::Request(Request::new(
+            RequestId::from(WATCHER_ID.to_string()),
+            RegisterCapability::METHOD.to_string(),
+            params,
+        )))?;
+        Ok(())
+    }
+
+    /// Handle messages until the client shuts the server down
+    pub fn main_loop(&mut self) -> Result<(), Error> {
+        while let Ok(message) = self.connection.receiver.recv() {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+                    let response = self.handle_request(request);
+                    self.connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        debug!("Request {}", request.method);
+        match request.method.as_str() {
+            Completion::METHOD => self.dispatch::<Completion>(request, Self::completion),
+            GotoDefinition::METHOD => self.dispatch::<GotoDefinition>(request, Self::definition),
+            References::METHOD => self.dispatch::<References>(request, Self::references),
+            HoverRequest::METHOD => self.dispatch::<HoverRequest>(request, Self::hover),
+            Rename::METHOD => self.dispatch::<Rename>(request, Self::rename),
+            _ => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unhandled method {}", request.method),
+            ),
+        }
+    }
+
+    fn dispatch<R: LspRequest>(
+        &self,
+        request: Request,
+        handler: fn(&Self, R::Params) -> HandlerResult<R::Result>,
+    ) -> Response {
+        let params = match serde_json::from_value::<R::Params>(request.params) {
+            Ok(params) => params,
+            Err(e) => {
+                return Response::new_err(
+                    request.id,
+                    ErrorCode::InvalidParams as i32,
+                    e.to_string(),
+                );
+            }
+        };
+
+        match handler(self, params) {
+            Ok(result) => Response::new_ok(request.id, result),
+            Err(message) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, message),
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<(), Error> {
+        debug!("Notification {}", notification.method);
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                if let Ok(path) = params.text_document.uri.to_file_path() {
+                    let changed = self.workspace.open(path.clone(), params.text_document.text);
+                    self.publish_diagnostics(self.affected(path, &changed))?;
+                }
+            }
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                // Full sync, the last change holds the whole text
+                if let (Ok(path), Some(change)) = (
+                    params.text_document.uri.to_file_path(),
+                    params.content_changes.into_iter().last(),
+                ) {
+                    let changed = self.workspace.open(path.clone(), change.text);
+                    self.publish_diagnostics(self.affected(path, &changed))?;
+                }
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params)?;
+                if let Ok(path) = params.text_document.uri.to_file_path() {
+                    let changed = self.runtime.block_on(self.workspace.close(&path));
+                    self.publish_diagnostics(self.affected(path, &changed))?;
+                }
+            }
+            DidChangeWatchedFiles::METHOD => {
+                let params: lsp_types::DidChangeWatchedFilesParams =
+                    serde_json::from_value(notification.params)?;
+                let mut paths = BTreeSet::new();
+                for event in params.changes {
+                    let Ok(path) = event.uri.to_file_path() else {
+                        continue;
+                    };
+                    // The editor owns the text of open documents
+                    if self.workspace.document(&path).is_some_and(|doc| doc.open) {
+                        continue;
+                    }
+                    // Rereads the file, or drops it once it is gone
+                    let changed = self.runtime.block_on(self.workspace.close(&path));
+                    paths.extend(self.affected(path, &changed));
+                }
+                self.publish_diagnostics(paths)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// The edited document and every document linking to a passage name
+    /// that started or stopped being defined by the edit
+    fn affected(&self, path: PathBuf, changed: &BTreeSet<String>) -> BTreeSet<PathBuf> {
+        let mut paths = BTreeSet::from([path]);
+        if !changed.is_empty() {
+            paths.extend(
+                self.workspace
+                    .documents()
+                    .filter(|(_, document)| {
+                        document
+                            .links()
+                            .iter()
+                            .any(|link| changed.contains(&link.target))
+                    })
+                    .map(|(path, _)| path.clone()),
+            );
+        }
+        paths
+    }
+
+    /// Publish parse errors and broken links of the given sources
+    ///
+    /// A source that left the workspace gets an empty list, clearing what
+    /// the client still shows for it.
+    fn publish_diagnostics(&self, paths: BTreeSet<PathBuf>) -> Result<(), Error> {
+        for path in paths {
+            let Ok(uri) = Url::from_file_path(&path) else {
+                continue;
+            };
+            let diagnostics = match self.workspace.document(&path) {
+                Some(document) => match self.diagnostics(document) {
+                    Some(diagnostics) => diagnostics,
+                    None => continue,
+                },
+                None => Vec::new(),
+            };
+
+            let params = PublishDiagnosticsParams::new(uri, diagnostics, None);
+            self.connection
+                .sender
+                .send(Message::Notification(Notification::new(
+                    PublishDiagnostics::METHOD.to_string(),
+                    params,
+                )))?;
+        }
+
+        Ok(())
+    }
+
+    /// Diagnostics of a Twee source, `None` for sources without text
+    fn diagnostics(&self, document: &Document) -> Option<Vec<Diagnostic>> {
+        let (Some(text), Some(index)) = (&document.text, &document.line_index) else {
+            return None;
+        };
+
+        let mut diagnostics: Vec<Diagnostic> = document
+            .diagnostics
+            .iter()
+            .map(|diagnostic| Diagnostic {
+                range: convert::range(text, index, diagnostic.range.clone()),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("tweers".to_string()),
+                message: diagnostic.message.clone(),
+                ..Default::default()
+            })
+            .collect();
+
+        for link in document.links() {
+            if !self.workspace.contains(&link.target) {
+                diagnostics.push(Diagnostic {
+                    range: convert::range(text, index, link.target_range),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    code: Some(NumberOrString::String("broken-link".to_string())),
+                    source: Some("tweers".to_string()),
+                    message: format!("Broken link to '{}'", link.target),
+                    ..Default::default()
+                });
+            }
+        }
+
+        Some(diagnostics)
+    }
+
+    /// Document, text and byte offset of a cursor position
+    fn locate(&self, params: &TextDocumentPositionParams) -> Option<(&Document, usize)> {
+        let path = params.text_document.uri.to_file_path().ok()?;
+        let document = self.workspace.document(&path)?;
+        let offset = convert::offset(
+            document.text.as_deref()?,
+            document.line_index.as_ref()?,
+            params.position,
+        );
+
+        Some((document, offset))
+    }
+
+    fn location(&self, path: &Path, range: Range<usize>) -> Option<Location> {
+        let uri = Url::from_file_path(path).ok()?;
+        let document = self.workspace.document(path)?;
+        let range = match (&document.text, &document.line_index) {
+            (Some(text), Some(index)) => convert::range(text, index, range),
+            _ => lsp_types::Range::new(Position::new(0, 0), Position::new(0, 0)),
+        };
+
+        Some(Location::new(uri, range))
+    }
+
+    /// Location of a passage header name, or the start of its file
+    fn definition_location(&self, path: &Path, passage: &Passage) -> Option<Location> {
+        let range = passage.spans.map_or(0..0, |spans| spans.name.range());
+        self.location(path, range)
+    }
+
+    fn completion(&self, params: CompletionParams) -> HandlerResult<Option<CompletionResponse>> {
+        let Some((document, offset)) = self.locate(&params.text_document_position) else {
+            return Ok(None);
+        };
+        let (Some(text), Some(index)) = (&document.text, &document.line_index) else {
+            return Ok(None);
+        };
+
+        let line_start = index.line_start(index.line_col(text, offset).0);
+        let prefix = &text[line_start..offset];
+        let Some(open) = prefix.rfind("[[") else {
+            return Ok(None);
+        };
+        let inside = &prefix[open + 2..];
+        if inside.contains("]]") {
+            return Ok(None);
+        }
+
+        // The target follows the last divider, `[[Target<-Text]]` starts with it
+        let divider = [
+            inside.rfind("->").map(|i| i + 2),
+            inside.rfind('|').map(|i| i + 1),
+        ]
+        .into_iter()
+        .flatten()
+        .max()
+        .unwrap_or(0);
+        let target = &inside[divider..];
+        let target_start = offset - target.trim_start().len();
+        let range = convert::range(text, index, target_start..offset);
+
+        let mut names = BTreeSet::new();
+        let items = self
+            .workspace
+            .passages()
+            .filter(|(_, passage)| !is_special_passage(passage))
+            .filter(|(_, passage)| names.insert(passage.name.clone()))
+            .map(|(path, passage)| CompletionItem {
+                label: passage.name.clone(),
+                kind: Some(CompletionItemKind::REFERENCE),
+                detail: Some(path.display().to_string()),
+                text_edit: Some(CompletionTextEdit::Edit(TextEdit::new(
+                    range,
+                    passage.name.clone(),
+                ))),
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(Some(CompletionResponse::Array(items)))
+    }
+
+    fn definition(
+        &self,
+        params: GotoDefinitionParams,
+    ) -> HandlerResult<Option<GotoDefinitionResponse>> {
+        let Some((document, offset)) = self.locate(&params.text_document_position_params) else {
+            return Ok(None);
+        };
+        let Some(found) = document.name_at(offset) else {
+            return Ok(None);
+        };
+
+        let mut locations: Vec<Location> = self
+            .workspace
+            .definitions(&found.name)
+            .filter_map(|(path, passage)| self.definition_location(path, passage))
+            .collect();
+
+        Ok(match locations.len() {
+            0 => None,
+            1 => locations.pop().map(GotoDefinitionResponse::Scalar),
+            _ => Some(GotoDefinitionResponse::Array(locations)),
+        })
+    }
+
+    fn references(&self, params: ReferenceParams) -> HandlerResult<Option<Vec<Location>>> {
+        let Some((document, offset)) = self.locate(&params.text_document_position) else {
+            return Ok(None);
+        };
+        let Some(found) = document.name_at(offset) else {
+            return Ok(None);
+        };
+
+        let mut locations = Vec::new();
+        if params.context.include_declaration {
+            locations.extend(
+                self.workspace
+                    .definitions(&found.name)
+                    .filter_map(|(path, passage)| self.definition_location(path, passage)),
+            );
+        }
+        locations.extend(
+            self.workspace
+                .references(&found.name)
+                .into_iter()
+                .filter_map(|(path, link)| self.location(path, link.target_range)),
+        );
+
+        Ok(Some(locations))
+    }
+
+    fn hover(&self, params: HoverParams) -> HandlerResult<Option<Hover>> {
+        let Some((document, offset)) = self.locate(&params.text_document_position_params) else {
+            return Ok(None);
+        };
+        let Some(found) = document.name_at(offset) else {
+            return Ok(None);
+        };
+        let Some((path, passage)) = self.workspace.definitions(&found.name).next() else {
+            return Ok(None);
+        };
+
+        let mut value = format!("**{}**", passage.name);
+        if let Some(tags) = &passage.tags {
+            for tag in tags.split_whitespace() {
+                value.push_str(&format!(" `{}`", tag));
+            }
+        }
+
+        let lines: Vec<&str> = passage.content.lines().collect();
+        if !lines.is_empty() {
+            value.push_str("\n\n```\n");
+            value.push_str(&lines[..lines.len().min(PREVIEW_LINES)].join("\n"));
+            if lines.len() > PREVIEW_LINES {
+                value.push_str("\n...");
+            }
+            value.push_str("\n```");
+        }
+
+        value.push_str(&format!(
+            "\n\n*{}:{}*",
+            path.display(),
+            passage.source_line.unwrap_or(1)
+        ));
+
+        let range = match (&document.text, &document.line_index) {
+            (Some(text), Some(index)) => Some(convert::range(text, index, found.range)),
+            _ => None,
+        };
+
+        Ok(Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range,
+        }))
+    }
+
+    fn rename(&self, params: RenameParams) -> HandlerResult<Option<WorkspaceEdit>> {
+        let Some((document, offset)) = self.locate(&params.text_document_position) else {
+            return Ok(None);
+        };
+        let Some(found) = document.name_at(offset) else {
+            return Err("No passage name at this position".to_string());
+        };
+
+        let new_name = params.new_name.trim();
+        if new_name.is_empty() {
+            return Err("Passage name cannot be empty".to_string());
+        }
+        if let Some(markup) = ["[[", "]]", "|", "->", "<-", "\n"]
+            .into_iter()
+            .find(|markup| new_name.contains(markup))
+        {
+            return Err(format!("Passage name cannot contain {:?}", markup));
+        }
+        if new_name != found.name && self.workspace.contains(new_name) {
+            return Err(format!("Passage '{}' already exists", new_name));
+        }
+
+        // Other sources name passages after the file, renaming the links
+        // alone would break every one of them
+        if let Some((path, _)) = self
+            .workspace
+            .definitions(&found.name)
+            .find(|(_, passage)| passage.spans.is_none())
+        {
+            return Err(format!(
+                "Passage '{}' is defined by {}, which cannot be renamed",
+                found.name,
+                path.display()
+            ));
+        }
+
+        let mut changes: HashMap<Url, Vec<TextEdit>> = HashMap::new();
+        let mut add_edit = |path: &Path, range: Range<usize>, text: String| {
+            if let Some(location) = self.location(path, range) {
+                changes
+                    .entry(location.uri)
+                    .or_default()
+                    .push(TextEdit::new(location.range, text));
+            }
+        };
+
+        for (path, passage) in self.workspace.definitions(&found.name) {
+            if let Some(spans) = passage.spans {
+                add_edit(path, spans.name.range(), escape_name(new_name));
+            }
+        }
+        for (path, link) in self.workspace.references(&found.name) {
+            add_edit(path, link.target_range, new_name.to_string());
+        }
+
+        Ok(Some(WorkspaceEdit::new(changes)))
+    }
+}
+
+/// Escape the characters that end a passage name in a header
+fn escape_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for ch in name.chars() {
+        if matches!(ch, '\\' | '[' | ']' | '{' | '}') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+/// Whether the client accepts a file watcher registered after initialize
+fn can_watch_files(params: &InitializeParams) -> bool {
+    params
+        .capabilities
+        .workspace
+        .as_ref()
+        .and_then(|workspace| workspace.did_change_watched_files)
+        .and_then(|watched| watched.dynamic_registration)
+        .unwrap_or(false)
+}
+
+#[allow(deprecated)]
+fn workspace_roots(params: &InitializeParams) -> Vec<PathBuf> {
+    let folders: Vec<PathBuf> = params
+        .workspace_folders
+        .iter()
+        .flatten()
+        .filter_map(|folder| folder.uri.to_file_path().ok())
+        .collect();
+
+    if !folders.is_empty() {
+        return folders;
+    }
+
+    params
+        .root_uri
+        .as_ref()
+        .and_then(|uri| uri.to_file_path().ok())
+        .into_iter()
+        .collect()
+}
@@ -0,0 +1,348 @@
+//! Parsed state of every source known to the server
+
+use indexmap::IndexMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+use tweers_core::analysis::extract_links;
+use tweers_core::core::file::{FileType, detect_file_type};
+use tweers_core::core::parser::TweeParser;
+use tweers_core::core::span::LineIndex;
+use tweers_core::core::story::Passage;
+use tweers_core::error::ParseDiagnostic;
+use tweers_core_full::io::collect_files_with_base64;
+use tweers_core_full::pipeline::parsers::FileParserRegistry;
+
+/// A source file and the passages it defines
+pub struct Document {
+    /// Text of Twee sources, `None` for sources that only provide passages
+    /// (scripts, stylesheets, spreadsheets, media)
+    pub text: Option<String>,
+    pub line_index: Option<LineIndex>,
+    pub passages: IndexMap<String, Passage>,
+    pub diagnostics: Vec<ParseDiagnostic>,
+    /// Whether the editor owns the text of this document
+    pub open: bool,
+}
+
+impl Document {
+    fn from_text(path: &Path, text: String, open: bool) -> Self {
+        let (mut passages, _, diagnostics) = TweeParser::parse_with_diagnostics(&text);
+        let source_file = path.to_string_lossy();
+        for passage in passages.values_mut() {
+            passage.source_file = Some(source_file.to_string());
+        }
+
+        Self {
+            line_index: Some(LineIndex::new(&text)),
+            text: Some(text),
+            passages,
+            diagnostics,
+            open,
+        }
+    }
+
+    /// Links of every passage, with byte ranges into the document text
+    pub fn links(&self) -> Vec<DocumentLink> {
+        let Some(text) = &self.text else {
+            return Vec::new();
+        };
+
+        let mut links = Vec::new();
+        for passage in self.passages.values() {
+            let Some(spans) = &passage.spans else {
+                continue;
+            };
+            let body = spans.body.range();
+            for link in extract_links(&text[body.clone()]) {
+                links.push(DocumentLink {
+                    target: link.target,
+                    range: body.start + link.range.start..body.start + link.range.end,
+                    target_range: body.start + link.target_range.start
+                        ..body.start + link.target_range.end,
+                });
+            }
+        }
+
+        links
+    }
+
+    /// The passage name at a byte offset, from a link or a passage header
+    pub fn name_at(&self, offset: usize) -> Option<NameAt> {
+        if let Some(link) = self
+            .links()
+            .into_iter()
+            .find(|link| link.range.start <= offset && offset <= link.range.end)
+        {
+            return Some(NameAt {
+                name: link.target,
+                range: link.target_range,
+            });
+        }
+
+        self.passages.values().find_map(|passage| {
+            let name = passage.spans?.name.range();
+            (name.start <= offset && offset <= name.end).then(|| NameAt {
+                name: passage.name.clone(),
+                range: name,
+            })
+        })
+    }
+}
+
+/// A link with byte ranges into its document text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentLink {
+    pub target: String,
+    /// The whole `[[...]]` markup
+    pub range: Range<usize>,
+    pub target_range: Range<usize>,
+}
+
+/// A passage name found under the cursor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameAt {
+    pub name: String,
+    pub range: Range<usize>,
+}
+
+/// All sources of the story, keyed by path
+#[derive(Default)]
+pub struct Workspace {
+    documents: BTreeMap<PathBuf, Document>,
+    /// Number of definitions of each passage name across all documents
+    names: HashMap<String, usize>,
+}
+
+impl Workspace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load every supported source below the given roots
+    ///
+    /// Twee sources are parsed with [`TweeParser`] to keep their text and
+    /// diagnostics, other sources go through the [`FileParserRegistry`].
+    pub async fn load(&mut self, roots: &[PathBuf]) {
+        if roots.is_empty() {
+            return;
+        }
+
+        let files = match collect_files_with_base64(roots, false, false).await {
+            Ok(files) => files,
+            Err(e) => {
+                warn!("Failed to collect workspace files: {}", e);
+                return;
+            }
+        };
+
+        let registry = FileParserRegistry::new(false);
+        for path in files {
+            if self.documents.get(&path).is_some_and(|doc| doc.open) {
+                continue;
+            }
+            if let Some(document) = Self::read(&registry, &path).await {
+                self.replace(path, Some(document));
+            }
+        }
+
+        debug!("Loaded {} workspace documents", self.documents.len());
+    }
+
+    async fn read(registry: &FileParserRegistry, path: &Path) -> Option<Document> {
+        if is_twee(path) {
+            match tokio::fs::read_to_string(path).await {
+                Ok(text) => return Some(Document::from_text(path, text, false)),
+                Err(e) => {
+                    warn!("Failed to read {}: {}", path.display(), e);
+                    return None;
+                }
+            }
+        }
+
+        match registry.parse(path).await {
+            Ok((passages, _)) => Some(Document {
+                text: None,
+                line_index: None,
+                passages,
+                diagnostics: Vec::new(),
+                open: false,
+            }),
+            Err(e) => {
+                warn!("Failed to parse {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Set the text of a document owned by the editor
+    ///
+    /// Returns the passage names that started or stopped being defined.
+    pub fn open(&mut self, path: PathBuf, text: String) -> BTreeSet<String> {
+        let document = Document::from_text(&path, text, true);
+        self.replace(path, Some(document))
+    }
+
+    /// Hand a document back to the file system after the editor closed it,
+    /// or reread it after a change on disk
+    ///
+    /// Returns the passage names that started or stopped being defined.
+    pub async fn close(&mut self, path: &Path) -> BTreeSet<String> {
+        let mut document = None;
+        if path.exists() {
+            let registry = FileParserRegistry::new(false);
+            document = Self::read(&registry, path).await;
+        }
+        self.replace(path.to_path_buf(), document)
+    }
+
+    /// Swap the document at `path` and keep the name index in step
+    fn replace(&mut self, path: PathBuf, document: Option<Document>) -> BTreeSet<String> {
+        let old = self.documents.remove(&path);
+        let touched: BTreeSet<String> = old
+            .iter()
+            .chain(document.iter())
+            .flat_map(|doc| doc.passages.keys().cloned())
+            .collect();
+        let before: Vec<bool> = touched.iter().map(|name| self.contains(name)).collect();
+
+        for name in old.iter().flat_map(|doc| doc.passages.keys()) {
+            if let Some(count) = self.names.get_mut(name) {
+                *count -= 1;
+                if *count == 0 {
+                    self.names.remove(name);
+                }
+            }
+        }
+        if let Some(document) = document {
+            for name in document.passages.keys() {
+                *self.names.entry(name.clone()).or_default() += 1;
+            }
+            self.documents.insert(path, document);
+        }
+
+        touched
+            .into_iter()
+            .zip(before)
+            .filter(|(name, before)| self.contains(name) != *before)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    pub fn document(&self, path: &Path) -> Option<&Document> {
+        self.documents.get(path)
+    }
+
+    pub fn documents(&self) -> impl Iterator<Item = (&PathBuf, &Document)> {
+        self.documents.iter()
+    }
+
+    /// Every passage, with the path of the document defining it
+    pub fn passages(&self) -> impl Iterator<Item = (&PathBuf, &Passage)> {
+        self.documents
+            .iter()
+            .flat_map(|(path, doc)| doc.passages.values().map(move |passage| (path, passage)))
+    }
+
+    /// Every definition of a passage, duplicates included
+    pub fn definitions<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = (&'a PathBuf, &'a Passage)> + 'a {
+        self.passages()
+            .filter(move |(_, passage)| passage.name == name)
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.names.contains_key(name)
+    }
+
+    /// Every link to a passage, with the path of the document containing it
+    pub fn references(&self, name: &str) -> Vec<(&PathBuf, DocumentLink)> {
+        self.documents
+            .iter()
+            .flat_map(|(path, doc)| {
+                doc.links()
+                    .into_iter()
+                    .filter(|link| link.target == name)
+                    .map(move |link| (path, link))
+            })
+            .collect()
+    }
+}
+
+fn is_twee(path: &Path) -> bool {
+    matches!(
+        detect_file_type(&path.to_string_lossy()),
+        FileType::Twee | FileType::Unknown
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Workspace;
+    use std::path::PathBuf;
+
+    fn workspace() -> Workspace {
+        let mut workspace = Workspace::new();
+        workspace.open(
+            PathBuf::from("/story/a.twee"),
+            ":: Start\nGo [[Next]] or [[Back|Start]]\n".to_string(),
+        );
+        workspace.open(
+            PathBuf::from("/story/b.twee"),
+            ":: Next [end]\n[[Start]] [[Missing]]\n".to_string(),
+        );
+        workspace
+    }
+
+    #[test]
+    fn test_name_at_link_and_header() {
+        let workspace = workspace();
+        let document = workspace.document(&PathBuf::from("/story/a.twee")).unwrap();
+        let text = document.text.as_deref().unwrap();
+
+        let link = document.name_at(text.find("Next").unwrap()).unwrap();
+        assert_eq!(link.name, "Next");
+        assert_eq!(&text[link.range], "Next");
+
+        let header = document.name_at(4).unwrap();
+        assert_eq!(header.name, "Start");
+        assert_eq!(&text[header.range], "Start");
+
+        assert!(document.name_at(text.find("or").unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_references_across_documents() {
+        let workspace = workspace();
+
+        let references = workspace.references("Start");
+        let files: Vec<_> = references
+            .iter()
+            .map(|(path, _)| path.to_string_lossy().to_string())
+            .collect();
+        assert_eq!(files, vec!["/story/a.twee", "/story/b.twee"]);
+
+        assert!(workspace.contains("Next"));
+        assert!(!workspace.contains("Missing"));
+    }
+
+    #[test]
+    fn test_open_reports_names_that_appear_or_disappear() {
+        let mut workspace = workspace();
+        let path = PathBuf::from("/story/b.twee");
+
+        let changed = workspace.open(path.clone(), ":: Missing\n[[Start]]\n".to_string());
+        assert_eq!(
+            changed.into_iter().collect::<Vec<_>>(),
+            vec!["Missing", "Next"]
+        );
+        assert!(workspace.contains("Missing"));
+        assert!(!workspace.contains("Next"));
+
+        let changed = workspace.open(path, ":: Missing\nEdited\n".to_string());
+        assert!(changed.is_empty());
+    }
+}
@@ -0,0 +1,265 @@
+use lsp_server::{Connection, Message, Notification, Request, RequestId, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidChangeWatchedFiles, DidOpenTextDocument, Exit, Initialized,
+    Notification as _, PublishDiagnostics,
+};
+use lsp_types::request::{
+    GotoDefinition, Initialize, RegisterCapability, Rename, Request as _, Shutdown,
+};
+use lsp_types::{
+    ClientCapabilities, DidChangeTextDocumentParams, DidChangeWatchedFilesClientCapabilities,
+    DidChangeWatchedFilesParams, DidOpenTextDocumentParams, FileChangeType, FileEvent,
+    GotoDefinitionResponse, InitializeParams, Position, PublishDiagnosticsParams,
+    RegistrationParams, TextDocumentContentChangeEvent, TextDocumentItem, Url,
+    VersionedTextDocumentIdentifier, WorkspaceClientCapabilities, WorkspaceEdit,
+};
+use serde_json::{Value, json};
+use std::thread;
+
+struct Client {
+    connection: Connection,
+    next_id: i32,
+    /// Documents whose diagnostics arrived while waiting for a response
+    published: Vec<Url>,
+}
+
+impl Client {
+    fn request<R: lsp_types::request::Request>(&mut self, params: R::Params) -> Response {
+        self.next_id += 1;
+        let id = RequestId::from(self.next_id);
+        self.connection
+            .sender
+            .send(Message::Request(Request::new(
+                id.clone(),
+                R::METHOD.to_string(),
+                params,
+            )))
+            .unwrap();
+
+        loop {
+            match self.connection.receiver.recv().unwrap() {
+                Message::Response(response) if response.id == id => return response,
+                Message::Notification(notification)
+                    if notification.method == PublishDiagnostics::METHOD =>
+                {
+                    let params: PublishDiagnosticsParams =
+                        serde_json::from_value(notification.params).unwrap();
+                    self.published.push(params.uri);
+                }
+                _ => continue,
+            }
+        }
+    }
+
+    fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) {
+        self.connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                N::METHOD.to_string(),
+                params,
+            )))
+            .unwrap();
+    }
+
+    fn diagnostics(&self, uri: &Url) -> PublishDiagnosticsParams {
+        loop {
+            if let Message::Notification(notification) = self.connection.receiver.recv().unwrap()
+                && notification.method == PublishDiagnostics::METHOD
+            {
+                let params: PublishDiagnosticsParams =
+                    serde_json::from_value(notification.params).unwrap();
+                if &params.uri == uri {
+                    return params;
+                }
+            }
+        }
+    }
+}
+
+fn position_params(uri: &Url, line: u32, character: u32) -> Value {
+    json!({
+        "textDocument": { "uri": uri },
+        "position": Position::new(line, character),
+    })
+}
+
+#[test]
+fn test_server_session() {
+    let (server, connection) = Connection::memory();
+    let handle = thread::spawn(move || tweers_lsp::serve(server).unwrap());
+    let mut client = Client {
+        connection,
+        next_id: 0,
+        published: Vec::new(),
+    };
+
+    let response = client.request::<Initialize>(InitializeParams::default());
+    assert!(response.error.is_none());
+    client.notify::<Initialized>(lsp_types::InitializedParams {});
+
+    let start = Url::from_file_path("/story/start.twee").unwrap();
+    let hall = Url::from_file_path("/story/hall.twee").unwrap();
+    client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+        text_document: TextDocumentItem::new(
+            start.clone(),
+            "twee".to_string(),
+            1,
+            ":: Start\nGo to [[the hall->Hall]] or [[Cellar]]\n".to_string(),
+        ),
+    });
+    let diagnostics = client.diagnostics(&start);
+    let messages: Vec<_> = diagnostics
+        .diagnostics
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        vec!["Broken link to 'Hall'", "Broken link to 'Cellar'"]
+    );
+
+    client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+        text_document: TextDocumentItem::new(
+            hall.clone(),
+            "twee".to_string(),
+            1,
+            ":: Hall [room]\nBack to [[Start]]\n".to_string(),
+        ),
+    });
+    let diagnostics = client.diagnostics(&start);
+    assert_eq!(diagnostics.diagnostics.len(), 1);
+
+    let response = client
+        .request::<GotoDefinition>(serde_json::from_value(position_params(&start, 1, 20)).unwrap());
+    let definition: GotoDefinitionResponse =
+        serde_json::from_value(response.result.unwrap()).unwrap();
+    let GotoDefinitionResponse::Scalar(location) = definition else {
+        panic!("expected a single definition");
+    };
+    assert_eq!(location.uri, hall);
+    assert_eq!(location.range.start, Position::new(0, 3));
+
+    // Editing a body without renaming passages only republishes that document
+    client.published.clear();
+    client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+        text_document: VersionedTextDocumentIdentifier::new(hall.clone(), 2),
+        content_changes: vec![TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: ":: Hall [room]\nBack to [[Start]] or [[Attic]]\n".to_string(),
+        }],
+    });
+    client
+        .request::<GotoDefinition>(serde_json::from_value(position_params(&start, 1, 20)).unwrap());
+    assert_eq!(client.published, vec![hall.clone()]);
+
+    let mut params = position_params(&hall, 0, 4);
+    params["newName"] = json!("Great Hall");
+    let response = client.request::<Rename>(serde_json::from_value(params).unwrap());
+    let edit: WorkspaceEdit = serde_json::from_value(response.result.unwrap()).unwrap();
+    let changes = edit.changes.unwrap();
+    assert_eq!(changes[&hall].len(), 1);
+    assert_eq!(changes[&start].len(), 1);
+    assert_eq!(changes[&start][0].new_text, "Great Hall");
+    assert_eq!(changes[&start][0].range.start, Position::new(1, 18));
+
+    let response = client.request::<Shutdown>(());
+    assert!(response.error.is_none());
+    client.notify::<Exit>(());
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_watched_files_and_sources_without_text() {
+    let root = std::env::temp_dir().join(format!("tweers_lsp_watch_{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let script = root.join("script.js");
+    std::fs::write(&script, "setup.ready = true;").unwrap();
+    std::fs::write(
+        root.join("start.twee"),
+        format!(":: Start\n[[Cellar]] [[{}]]\n", script.display()),
+    )
+    .unwrap();
+
+    let (server, connection) = Connection::memory();
+    let handle = thread::spawn(move || tweers_lsp::serve(server).unwrap());
+    let mut client = Client {
+        connection,
+        next_id: 0,
+        published: Vec::new(),
+    };
+
+    #[allow(deprecated)]
+    let params = InitializeParams {
+        root_uri: Some(Url::from_file_path(&root).unwrap()),
+        capabilities: ClientCapabilities {
+            workspace: Some(WorkspaceClientCapabilities {
+                did_change_watched_files: Some(DidChangeWatchedFilesClientCapabilities {
+                    dynamic_registration: Some(true),
+                    relative_pattern_support: None,
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let response = client.request::<Initialize>(params);
+    assert!(response.error.is_none());
+    client.notify::<Initialized>(lsp_types::InitializedParams {});
+
+    let registration = loop {
+        if let Message::Request(request) = client.connection.receiver.recv().unwrap() {
+            break request;
+        }
+    };
+    assert_eq!(registration.method, RegisterCapability::METHOD);
+    let registration: RegistrationParams = serde_json::from_value(registration.params).unwrap();
+    assert_eq!(
+        registration.registrations[0].method,
+        DidChangeWatchedFiles::METHOD
+    );
+
+    let start = Url::from_file_path(root.join("start.twee")).unwrap();
+    assert_eq!(client.diagnostics(&start).diagnostics.len(), 1);
+
+    // Sources created and removed outside the editor update the diagnostics
+    let cellar = root.join("cellar.twee");
+    std::fs::write(&cellar, ":: Cellar\nDark.\n").unwrap();
+    client.notify::<DidChangeWatchedFiles>(DidChangeWatchedFilesParams {
+        changes: vec![FileEvent::new(
+            Url::from_file_path(&cellar).unwrap(),
+            FileChangeType::CREATED,
+        )],
+    });
+    assert!(client.diagnostics(&start).diagnostics.is_empty());
+
+    std::fs::remove_file(&cellar).unwrap();
+    client.notify::<DidChangeWatchedFiles>(DidChangeWatchedFilesParams {
+        changes: vec![FileEvent::new(
+            Url::from_file_path(&cellar).unwrap(),
+            FileChangeType::DELETED,
+        )],
+    });
+    assert_eq!(client.diagnostics(&start).diagnostics.len(), 1);
+
+    // The script names its passage after the file, so the links cannot be
+    // renamed without breaking them
+    let mut params = position_params(&start, 1, 14);
+    params["newName"] = json!("Setup");
+    let response = client.request::<Rename>(serde_json::from_value(params).unwrap());
+    assert!(response.result.is_none());
+    assert!(
+        response
+            .error
+            .unwrap()
+            .message
+            .contains("cannot be renamed")
+    );
+
+    let response = client.request::<Shutdown>(());
+    assert!(response.error.is_none());
+    client.notify::<Exit>(());
+    handle.join().unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+}